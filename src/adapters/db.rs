@@ -1,3 +1,4 @@
+use crate::allocation::BurnCause;
 use anyhow::Error;
 use serde::*;
 use std::collections::HashMap;
@@ -46,6 +47,123 @@ pub struct Transaction {
     pub cenotapth_message: Option<String>,
     pub timestamp: u32,
 }
+/// An etching that named a rune it wasn't allowed to, so no rune was created.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, ToSchema)]
+pub struct EtchingRejection {
+    pub tx_id: String,
+    pub block_height: u64,
+    /// The rune the transaction tried to etch
+    pub rune: String,
+    pub reason: EtchingRejectionReason,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, ToSchema)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum EtchingRejectionReason {
+    /// The name is shorter than the names unlocked at the block
+    TooShort {
+        minimum: String,
+    },
+    Reserved,
+    /// A rune with the same name was etched before
+    Duplicate {
+        rune_id: String,
+    },
+    /// No input reveals a commitment to the name from a taproot output
+    MissingCommitment,
+    /// The commit transaction has fewer confirmations than required
+    ImmatureCommitment {
+        confirmations: u32,
+    },
+}
+
+/// A transaction minting a rune, whether or not the mint succeeded.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, ToSchema)]
+pub struct MintAttempt {
+    pub tx_id: String,
+    pub rune_id: String,
+    pub block_height: u64,
+    pub timestamp: u32,
+    pub outcome: MintOutcome,
+    /// Why the mint failed, e.g. `limited to 10 mints`
+    pub message: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, ToSchema)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum MintOutcome {
+    Minted {
+        amount: u128,
+    },
+    /// Every mint allowed by the cap was already made
+    Cap {
+        cap: u128,
+    },
+    /// The mint ended on block `end`
+    End {
+        end: u64,
+    },
+    /// The mint starts on block `start`
+    Start {
+        start: u64,
+    },
+    /// The rune has no terms
+    Unmintable,
+    /// No rune has the id
+    NotFound,
+}
+
+/// Number of mint attempts of a rune, by outcome.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, ToSchema)]
+pub struct MintAttemptCounts {
+    pub minted: u64,
+    pub cap: u64,
+    pub end: u64,
+    pub start: u64,
+    pub unmintable: u64,
+    pub not_found: u64,
+}
+
+/// Runes entering, moving within, or leaving a transaction.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, ToSchema)]
+pub struct RuneFlow {
+    pub tx_id: String,
+    pub block_height: u64,
+    pub rune_id: String,
+    pub amount: u128,
+    pub kind: RuneFlowKind,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, ToSchema)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum RuneFlowKind {
+    /// Runes held by the output spent by the input, `txid:vout`
+    Input {
+        input_index: u32,
+        outpoint: String,
+    },
+    Premine,
+    Mint,
+    /// Runes the edict at `edict_index` allocated to an output
+    Edict {
+        edict_index: u32,
+        output_index: u32,
+    },
+    /// Runes left unallocated by the edicts, sent to the pointer
+    Pointer {
+        output_index: u32,
+    },
+    /// Runes left unallocated, sent to the first non-OP_RETURN output
+    DefaultOutput {
+        output_index: u32,
+    },
+    /// `output_index` is the OP_RETURN output the runes were sent to
+    Burn {
+        cause: BurnCause,
+        output_index: Option<u32>,
+    },
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
 pub struct Terms {
     pub amount: Option<u128>,
@@ -82,6 +200,96 @@ pub struct RuneTXO {
     pub spent_block_height: Option<u64>,
 }
 
+#[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
+pub struct RuneEvent {
+    pub tx_id: String,
+    pub rune_id: String,
+    pub block_height: u64,
+    pub timestamp: u32,
+    pub amount: u128,
+    pub event_type: String,
+    pub output_index: Option<u32>,
+    pub address: Option<String>,
+    /// Why the runes were burned, for burn events. `output_index` is the
+    /// OP_RETURN output of `op_return` burns. Missing for burns indexed
+    /// before causes were recorded
+    pub burn_cause: Option<BurnCause>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, ToSchema)]
+pub struct BurnTotal {
+    /// Missing for burns indexed before causes were recorded
+    pub burn_cause: Option<BurnCause>,
+    pub amount: u128,
+    /// Number of burn events
+    pub count: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
+pub struct ReorgEvent {
+    pub id: u64,
+    pub fork_height: Option<u64>,
+    pub old_tip_height: u64,
+    pub old_tip_hash: String,
+    pub new_tip_hash: String,
+    pub depth: u64,
+    pub timestamp: u32,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
+pub struct OrphanedTransaction {
+    pub tx_id: String,
+    pub block_height: u64,
+    pub reconfirmed_block_height: Option<u64>,
+}
+
+/// A rune balance held by an output of an unconfirmed transaction.
+#[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
+pub struct PendingRuneTXO {
+    pub tx_id: String,
+    pub output_index: u32,
+    pub rune_id: String,
+    pub amount: u128,
+    pub address: Option<String>,
+    pub spent_tx_id: Option<String>,
+}
+
+/// A confirmed rune output spent by an unconfirmed transaction.
+#[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
+pub struct PendingSpend {
+    pub tx_id: String,
+    pub output_index: u32,
+    pub spent_tx_id: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
+pub struct PendingMint {
+    pub tx_id: String,
+    pub rune_id: String,
+    pub amount: u128,
+}
+
+/// A rune etched by an unconfirmed transaction. Its id is only known once
+/// the transaction confirms.
+#[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
+pub struct PendingEtching {
+    pub tx_id: String,
+    pub name: String,
+    pub symbol: Option<char>,
+    pub divisibility: u8,
+    pub premine: u128,
+    pub is_cenotapth: bool,
+}
+
+/// What the transactions in the mempool would do if they confirmed.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct PendingState {
+    pub runes_txos: Vec<PendingRuneTXO>,
+    pub spends: Vec<PendingSpend>,
+    pub mints: Vec<PendingMint>,
+    pub etchings: Vec<PendingEtching>,
+}
+
 pub trait Database {
     fn get_rune_by_id(&self, rune_id: &str) -> Result<Option<RuneEntry>, Error>;
     fn get_rune_by_raw_name(&self, name: &str) -> Result<Option<RuneEntry>, Error>;
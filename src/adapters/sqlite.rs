@@ -1,8 +1,11 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
 use super::db::*;
+use crate::allocation::BurnCause;
+use crate::decoding::DecodedTransaction;
 use crate::log_file::log;
-use anyhow::Error;
+use anyhow::{bail, Error};
+use chrono::Utc;
 use rusqlite::{params, Connection, Result};
 
 #[derive(Debug, Clone, Copy)]
@@ -47,7 +50,7 @@ impl SQLite {
             height_end INTEGER,
             offset_start INTEGER,
             offset_end INTEGER,
-            block_height INTEGER NOT NUL
+            block_height INTEGER NOT NULL
         )",
             (),
         )?;
@@ -75,7 +78,8 @@ impl SQLite {
             amount TEXT NOT NULL,
             event_type TEXT NOT NULL,
             output_index INTEGER,
-            address TEXT
+            address TEXT,
+            burn_cause TEXT
       )",
             (),
         )?;
@@ -111,6 +115,87 @@ impl SQLite {
         //         (),
         //     )?;
 
+        // taproot outputs created since the index started, so etching
+        // commitments can be checked without looking up the commit
+        // transaction on the node. spent outputs are kept, since a reorg may
+        // roll back to any checkpoint and unspend them
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS taproot_txos (
+            tx_id TEXT NOT NULL,
+            output_index INTEGER NOT NULL,
+            block_height INTEGER NOT NULL,
+            spent_block_height INTEGER
+      )",
+            (),
+        )?;
+
+        // etchings that didn't create a rune, reason is one of too_short,
+        // reserved, duplicate, missing_commitment, immature_commitment
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS etching_rejections (
+            tx_id TEXT NOT NULL,
+            block_height INTEGER NOT NULL,
+            rune TEXT NOT NULL,
+            reason TEXT NOT NULL,
+            minimum TEXT,
+            rune_id TEXT,
+            confirmations INTEGER
+      )",
+            (),
+        )?;
+
+        // outcome: minted, cap, end, start, unmintable, not_found
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS mint_attempts (
+            tx_id TEXT NOT NULL,
+            rune_id TEXT NOT NULL,
+            block_height INTEGER NOT NULL,
+            timestamp INTEGER,
+            outcome TEXT NOT NULL,
+            amount TEXT,
+            cap TEXT,
+            height INTEGER,
+            message TEXT
+      )",
+            (),
+        )?;
+
+        // the runestone or cenotaph of each transaction in the transactions
+        // table, as returned by `decoding::decode`, serialized to JSON
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS runestones (
+            tx_id TEXT NOT NULL,
+            block_height INTEGER NOT NULL,
+            decoded TEXT NOT NULL
+      )",
+            (),
+        )?;
+
+        // kind: input, premine, mint, edict, pointer, default_output, burn
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS rune_flows (
+            tx_id TEXT NOT NULL,
+            block_height INTEGER NOT NULL,
+            rune_id TEXT NOT NULL,
+            amount TEXT NOT NULL,
+            kind TEXT NOT NULL,
+            input_index INTEGER,
+            outpoint TEXT,
+            edict_index INTEGER,
+            output_index INTEGER,
+            burn_cause TEXT
+      )",
+            (),
+        )?;
+
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS metadata (
+            key TEXT PRIMARY KEY,
+            value TEXT NOT NULL
+            )",
+            (),
+        )?;
+
         conn.execute(
             "CREATE TABLE IF NOT EXISTS blocks (
             height INTEGER NOT NULL,
@@ -120,12 +205,112 @@ impl SQLite {
             (),
         )?;
 
+        // fork_height is NULL when no indexed block was left on the active
+        // chain and the whole index was rebuilt
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS reorg_events (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            fork_height INTEGER,
+            old_tip_height INTEGER NOT NULL,
+            old_tip_hash TEXT NOT NULL,
+            new_tip_hash TEXT NOT NULL,
+            depth INTEGER NOT NULL,
+            timestamp INTEGER NOT NULL
+            )",
+            (),
+        )?;
+
+        // what unconfirmed transactions would do to runes, replaced as a
+        // whole when the tip changes, and transaction by transaction as the
+        // mempool changes in between
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS mempool_runes_txos (
+            tx_id TEXT NOT NULL,
+            output_index INTEGER NOT NULL,
+            rune_id TEXT NOT NULL,
+            amount TEXT NOT NULL,
+            address TEXT,
+            spent_tx_id TEXT
+            )",
+            (),
+        )?;
+
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS mempool_spent_txos (
+            tx_id TEXT NOT NULL,
+            output_index INTEGER NOT NULL,
+            spent_tx_id TEXT NOT NULL
+            )",
+            (),
+        )?;
+
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS mempool_mints (
+            tx_id TEXT NOT NULL,
+            rune_id TEXT NOT NULL,
+            amount TEXT NOT NULL
+            )",
+            (),
+        )?;
+
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS mempool_etchings (
+            tx_id TEXT NOT NULL,
+            name TEXT NOT NULL,
+            symbol TEXT,
+            divisibility INTEGER NOT NULL,
+            premine TEXT NOT NULL,
+            is_cenotapth BOOLEAN NOT NULL
+            )",
+            (),
+        )?;
+
+        // rows rolled back by a reorg are moved to an orphaned_* copy of
+        // their table, tagged with the reorg event that removed them
+        for table in Self::ORPHANED_TABLES {
+            conn.execute(
+                &format!(
+                    "CREATE TABLE IF NOT EXISTS orphaned_{table} AS
+                    SELECT NULL AS reorg_event_id, * FROM {table} WHERE FALSE"
+                ),
+                (),
+            )?;
+        }
+
+        // burn_cause was added after rune_events was created, events indexed
+        // before it have none
+        for table in ["rune_events", "orphaned_rune_events"] {
+            Self::add_column_if_missing(conn, table, "burn_cause", "TEXT")?;
+        }
+
         self.create_db_indexes(conn)?;
 
         log("Tables initialized")?;
 
         Ok(())
     }
+
+    fn add_column_if_missing(
+        conn: &Connection,
+        table: &str,
+        column: &str,
+        definition: &str,
+    ) -> Result<(), Error> {
+        let exists: bool = conn.query_row(
+            &format!("SELECT COUNT(*) > 0 FROM pragma_table_info('{table}') WHERE name = ?1"),
+            params![column],
+            |row| row.get(0),
+        )?;
+
+        if !exists {
+            conn.execute(
+                &format!("ALTER TABLE {table} ADD COLUMN {column} {definition}"),
+                (),
+            )?;
+        }
+
+        Ok(())
+    }
     // }
 
     // impl<'a> Database for SQLite<'a> {
@@ -271,7 +456,7 @@ impl SQLite {
             new_mint_count = result_iter.map(|r| r.unwrap()).next().unwrap();
         }
 
-        let tx = conn.transaction()?;
+        let tx = conn.savepoint()?;
 
         tx.execute(
             "UPDATE rune_entries SET mint_count = ?1 WHERE rune_id = ?2",
@@ -326,20 +511,25 @@ impl SQLite {
     pub fn add_rune_burn_event(
         &mut self,
         conn: &mut Connection,
-        rune_id: &str,
-        tx_id: &str,
-        amount: u128,
-        block_height: u64,
-        timestamp: u32,
+        event: RuneEvent,
     ) -> Result<(), Error> {
         conn.execute(
-            "INSERT INTO rune_events (tx_id, rune_id, block_height, timestamp, amount, event_type) VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
-            params![tx_id, rune_id, block_height, timestamp, amount.to_string(), "burn"],
+            "INSERT INTO rune_events (tx_id, rune_id, block_height, timestamp, amount, event_type, output_index, burn_cause) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+            params![
+                event.tx_id,
+                event.rune_id,
+                event.block_height,
+                event.timestamp,
+                event.amount.to_string(),
+                "burn",
+                event.output_index,
+                event.burn_cause.map(|cause| cause.to_string())
+            ],
         )?;
 
         // log(&format!(
         //     "Burn event for rune {} added: {:?}",
-        //     rune_id, tx_id
+        //     event.rune_id, event.tx_id
         // ))?;
 
         Ok(())
@@ -431,7 +621,7 @@ impl SQLite {
         conn: &mut Connection,
         rune_entry: RuneEntry,
     ) -> Result<(), Error> {
-        let tx = conn.transaction()?;
+        let tx = conn.savepoint()?;
 
         tx.execute(
             "INSERT INTO rune_entries (etching_tx_id, block_height, rune_id, name, raw_name, symbol, divisibility, premine, burned, mint_count, timestamp, is_cenotapth, cenotapth_message, rune_number, turbo) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15)",
@@ -483,7 +673,7 @@ impl SQLite {
     }
 
     pub fn add_rune_txo(&mut self, conn: &mut Connection, rune_txo: RuneTXO) -> Result<(), Error> {
-        let tx = conn.transaction()?;
+        let tx = conn.savepoint()?;
         tx.execute("INSERT INTO runes_txos (tx_id, output_index, rune_id, amount, address, is_unspent, spent_tx_id, timestamp, block_height, spent_block_height) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)",
         params![
             rune_txo.tx_id,
@@ -591,145 +781,582 @@ impl SQLite {
     //     Ok(())
     // }
 
-    pub fn get_address_balance_by_rune_id(
-        &self,
+    pub fn add_taproot_txos(
+        &mut self,
         conn: &mut Connection,
-        address: &str,
-        rune_id: &str,
-    ) -> Result<u128, Error> {
-        let mut stmt = conn
-        .prepare("SELECT amount FROM runes_txos WHERE address = ?1 AND rune_id = ?2 AND is_unspent = TRUE")?;
+        block_height: u64,
+        txos: &[(String, u32)],
+    ) -> Result<(), Error> {
+        let tx = conn.savepoint()?;
 
-        let result_iter = stmt.query_map(params![address.to_lowercase(), rune_id], |row| {
-            let amount: String = row.get("amount")?;
+        {
+            let mut stmt = tx.prepare_cached(
+                "INSERT OR REPLACE INTO taproot_txos (tx_id, output_index, block_height, spent_block_height) VALUES (?1, ?2, ?3, NULL)",
+            )?;
 
-            Ok(amount.parse::<u128>().unwrap_or_default())
-        })?;
+            for (tx_id, output_index) in txos {
+                stmt.execute(params![tx_id, output_index, block_height])?;
+            }
+        }
 
-        Ok(result_iter.map(|r| r.unwrap()).sum())
+        tx.commit()?;
+
+        Ok(())
     }
 
-    pub fn get_address_balance_list(
-        &self,
+    pub fn mark_taproot_txos_as_spent(
+        &mut self,
         conn: &mut Connection,
-        address: &str,
-    ) -> Result<HashMap<String, u128>, Error> {
-        let mut stmt = conn.prepare(
-            "SELECT rune_id, amount FROM runes_txos WHERE address = ?1 AND is_unspent = TRUE",
-        )?;
+        outpoints: &[(String, u32)],
+        spent_block_height: u64,
+    ) -> Result<(), Error> {
+        let tx = conn.savepoint()?;
 
-        let result_iter = stmt.query_map(params![address.to_lowercase()], |row| {
-            let rune_id: String = row.get("rune_id")?;
-            let amount: String = row.get("amount")?;
+        {
+            let mut stmt = tx.prepare_cached(
+                "UPDATE taproot_txos SET spent_block_height = ?1 WHERE tx_id = ?2 AND output_index = ?3",
+            )?;
 
-            Ok((rune_id, amount.parse::<u128>().unwrap_or_default()))
-        })?;
+            for (tx_id, output_index) in outpoints {
+                stmt.execute(params![spent_block_height, tx_id, output_index])?;
+            }
+        }
 
-        let mut balance_list: HashMap<String, u128> = HashMap::new();
+        tx.commit()?;
 
-        for balance in result_iter {
-            let (rune_id, amount) = balance.unwrap();
-            *balance_list.entry(rune_id).or_default() += amount;
+        Ok(())
+    }
+
+    pub fn add_rune_flows(
+        &mut self,
+        conn: &mut Connection,
+        flows: &[RuneFlow],
+    ) -> Result<(), Error> {
+        let tx = conn.savepoint()?;
+
+        {
+            let mut stmt = tx.prepare_cached(
+                "INSERT INTO rune_flows (tx_id, block_height, rune_id, amount, kind, input_index, outpoint, edict_index, output_index, burn_cause) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)",
+            )?;
+
+            for flow in flows {
+                let (kind, input_index, outpoint, edict_index, output_index, burn_cause) =
+                    match &flow.kind {
+                        RuneFlowKind::Input {
+                            input_index,
+                            outpoint,
+                        } => (
+                            "input",
+                            Some(*input_index),
+                            Some(outpoint.clone()),
+                            None,
+                            None,
+                            None,
+                        ),
+                        RuneFlowKind::Premine => ("premine", None, None, None, None, None),
+                        RuneFlowKind::Mint => ("mint", None, None, None, None, None),
+                        RuneFlowKind::Edict {
+                            edict_index,
+                            output_index,
+                        } => (
+                            "edict",
+                            None,
+                            None,
+                            Some(*edict_index),
+                            Some(*output_index),
+                            None,
+                        ),
+                        RuneFlowKind::Pointer { output_index } => {
+                            ("pointer", None, None, None, Some(*output_index), None)
+                        }
+                        RuneFlowKind::DefaultOutput { output_index } => (
+                            "default_output",
+                            None,
+                            None,
+                            None,
+                            Some(*output_index),
+                            None,
+                        ),
+                        RuneFlowKind::Burn {
+                            cause,
+                            output_index,
+                        } => (
+                            "burn",
+                            None,
+                            None,
+                            None,
+                            *output_index,
+                            Some(cause.to_string()),
+                        ),
+                    };
+
+                stmt.execute(params![
+                    flow.tx_id,
+                    flow.block_height,
+                    flow.rune_id,
+                    flow.amount.to_string(),
+                    kind,
+                    input_index,
+                    outpoint,
+                    edict_index,
+                    output_index,
+                    burn_cause
+                ])?;
+            }
         }
 
-        Ok(balance_list)
+        tx.commit()?;
+
+        Ok(())
     }
 
-    pub fn get_runes_txo_by_output_index(
+    /// Rune flows of a transaction, in the order they were recorded.
+    pub fn get_transaction_rune_flows(
         &self,
         conn: &mut Connection,
         tx_id: &str,
-        output_index: u32,
-    ) -> Result<Vec<RuneTXO>, Error> {
-        let mut stmt =
-            conn.prepare("SELECT * FROM runes_txos WHERE tx_id = ?1 AND output_index = ?2")?;
+    ) -> Result<Vec<RuneFlow>, Error> {
+        let mut stmt = conn.prepare("SELECT * FROM rune_flows WHERE tx_id = ?1 ORDER BY rowid")?;
 
-        let result_iter = stmt.query_map(params![tx_id, output_index], |row| {
+        let result_iter = stmt.query_map(params![tx_id], |row| {
             let amount: String = row.get("amount")?;
+            let kind: String = row.get("kind")?;
+            let output_index: Option<u32> = row.get("output_index")?;
+            let burn_cause: Option<String> = row.get("burn_cause")?;
 
-            Ok(RuneTXO {
+            Ok(RuneFlow {
                 tx_id: row.get("tx_id")?,
-                output_index: row.get("output_index")?,
-                rune_id: row.get("rune_id")?,
-                amount: amount.parse().unwrap(),
-                address: row.get("address")?,
-                is_unspent: row.get("is_unspent")?,
-                spent_tx_id: row.get("spent_tx_id")?,
-                timestamp: row.get("timestamp")?,
                 block_height: row.get("block_height")?,
-                spent_block_height: row.get("spent_block_height")?,
+                rune_id: row.get("rune_id")?,
+                amount: amount.parse::<u128>().unwrap(),
+                kind: match kind.as_str() {
+                    "input" => RuneFlowKind::Input {
+                        input_index: row.get("input_index")?,
+                        outpoint: row.get("outpoint")?,
+                    },
+                    "premine" => RuneFlowKind::Premine,
+                    "mint" => RuneFlowKind::Mint,
+                    "edict" => RuneFlowKind::Edict {
+                        edict_index: row.get("edict_index")?,
+                        output_index: output_index.unwrap_or_default(),
+                    },
+                    "pointer" => RuneFlowKind::Pointer {
+                        output_index: output_index.unwrap_or_default(),
+                    },
+                    "default_output" => RuneFlowKind::DefaultOutput {
+                        output_index: output_index.unwrap_or_default(),
+                    },
+                    _ => RuneFlowKind::Burn {
+                        cause: burn_cause
+                            .and_then(|cause| cause.parse::<BurnCause>().ok())
+                            .unwrap_or(BurnCause::Cenotaph),
+                        output_index,
+                    },
+                },
             })
         })?;
 
         Ok(result_iter.map(|r| r.unwrap()).collect())
     }
 
-    pub fn get_transaction_runes_txo(
+    pub fn add_runestone(
+        &mut self,
+        conn: &mut Connection,
+        block_height: u64,
+        decoded: &DecodedTransaction,
+    ) -> Result<(), Error> {
+        conn.execute(
+            "INSERT INTO runestones (tx_id, block_height, decoded) VALUES (?1, ?2, ?3)",
+            params![decoded.tx_id, block_height, serde_json::to_string(decoded)?],
+        )?;
+
+        Ok(())
+    }
+
+    pub fn get_runestone(
         &self,
         conn: &mut Connection,
         tx_id: &str,
-    ) -> Result<Vec<RuneTXO>, Error> {
-        let mut stmt =
-            conn.prepare("SELECT * FROM runes_txos WHERE tx_id = ?1 OR spent_tx_id = ?1")?;
+    ) -> Result<Option<DecodedTransaction>, Error> {
+        let mut stmt = conn.prepare("SELECT decoded FROM runestones WHERE tx_id = ?1")?;
 
         let result_iter = stmt.query_map(params![tx_id], |row| {
-            let amount: String = row.get("amount")?;
+            let decoded: String = row.get("decoded")?;
 
-            Ok(RuneTXO {
-                tx_id: row.get("tx_id")?,
-                output_index: row.get("output_index")?,
-                rune_id: row.get("rune_id")?,
-                amount: amount.parse().unwrap(),
-                address: row.get("address")?,
-                is_unspent: row.get("is_unspent")?,
-                spent_tx_id: row.get("spent_tx_id")?,
-                timestamp: row.get("timestamp")?,
-                block_height: row.get("block_height")?,
-                spent_block_height: row.get("spent_block_height")?,
-            })
+            Ok(decoded)
         })?;
 
-        Ok(result_iter.map(|r| r.unwrap()).collect())
+        let Some(decoded) = result_iter.map(|r| r.unwrap()).next() else {
+            return Ok(None);
+        };
+
+        Ok(Some(serde_json::from_str(&decoded)?))
     }
 
-    pub fn get_address_runes_txo(
-        &self,
+    pub fn add_etching_rejection(
+        &mut self,
         conn: &mut Connection,
-        address: &str,
-    ) -> Result<Vec<RuneTXO>, Error> {
-        let mut stmt = conn.prepare("SELECT * FROM runes_txos WHERE address = ?1")?;
-
-        let result_iter = stmt
-            .query_map(params![address.to_lowercase()], |row| {
-                let amount: String = row.get("amount")?;
+        rejection: EtchingRejection,
+    ) -> Result<(), Error> {
+        let (reason, minimum, rune_id, confirmations) = match rejection.reason {
+            EtchingRejectionReason::TooShort { minimum } => {
+                ("too_short", Some(minimum), None, None)
+            }
+            EtchingRejectionReason::Reserved => ("reserved", None, None, None),
+            EtchingRejectionReason::Duplicate { rune_id } => {
+                ("duplicate", None, Some(rune_id), None)
+            }
+            EtchingRejectionReason::MissingCommitment => ("missing_commitment", None, None, None),
+            EtchingRejectionReason::ImmatureCommitment { confirmations } => {
+                ("immature_commitment", None, None, Some(confirmations))
+            }
+        };
 
-                Ok(RuneTXO {
-                    tx_id: row.get("tx_id")?,
-                    output_index: row.get("output_index")?,
-                    rune_id: row.get("rune_id")?,
-                    amount: amount.parse().unwrap(),
-                    address: row.get("address")?,
-                    is_unspent: row.get("is_unspent")?,
-                    spent_tx_id: row.get("spent_tx_id")?,
-                    timestamp: row.get("timestamp")?,
-                    block_height: row.get("block_height")?,
-                    spent_block_height: row.get("spent_block_height")?,
-                })
-            })
-            .unwrap();
+        conn.execute(
+            "INSERT INTO etching_rejections (tx_id, block_height, rune, reason, minimum, rune_id, confirmations) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            params![
+                rejection.tx_id,
+                rejection.block_height,
+                rejection.rune,
+                reason,
+                minimum,
+                rune_id,
+                confirmations
+            ],
+        )?;
 
-        Ok(result_iter.map(|r| r.unwrap()).collect())
+        Ok(())
     }
 
-    pub fn get_address_runes_utxo_by_rune_id(
+    pub fn get_etching_rejection(
         &self,
         conn: &mut Connection,
-        address: &str,
-        rune_id: &str,
-    ) -> Result<Vec<RuneTXO>, Error> {
-        let mut stmt = conn.prepare(
-            "SELECT * FROM runes_txos WHERE address = ?1 AND rune_id = ?2 AND is_unspent = TRUE",
-        )?;
+        tx_id: &str,
+    ) -> Result<Option<EtchingRejection>, Error> {
+        let mut stmt = conn.prepare("SELECT * FROM etching_rejections WHERE tx_id = ?1")?;
+
+        let result_iter = stmt.query_map(params![tx_id], |row| {
+            let reason: String = row.get("reason")?;
+            let minimum: Option<String> = row.get("minimum")?;
+            let rune_id: Option<String> = row.get("rune_id")?;
+            let confirmations: Option<u32> = row.get("confirmations")?;
+
+            Ok(EtchingRejection {
+                tx_id: row.get("tx_id")?,
+                block_height: row.get("block_height")?,
+                rune: row.get("rune")?,
+                reason: match reason.as_str() {
+                    "too_short" => EtchingRejectionReason::TooShort {
+                        minimum: minimum.unwrap_or_default(),
+                    },
+                    "reserved" => EtchingRejectionReason::Reserved,
+                    "duplicate" => EtchingRejectionReason::Duplicate {
+                        rune_id: rune_id.unwrap_or_default(),
+                    },
+                    "missing_commitment" => EtchingRejectionReason::MissingCommitment,
+                    _ => EtchingRejectionReason::ImmatureCommitment {
+                        confirmations: confirmations.unwrap_or_default(),
+                    },
+                },
+            })
+        })?;
+
+        let rejection = result_iter.map(|r| r.unwrap()).next();
+        Ok(rejection)
+    }
+
+    pub fn add_mint_attempt(
+        &mut self,
+        conn: &mut Connection,
+        mint_attempt: MintAttempt,
+    ) -> Result<(), Error> {
+        let (outcome, amount, cap, height) = match mint_attempt.outcome {
+            MintOutcome::Minted { amount } => ("minted", Some(amount.to_string()), None, None),
+            MintOutcome::Cap { cap } => ("cap", None, Some(cap.to_string()), None),
+            MintOutcome::End { end } => ("end", None, None, Some(end)),
+            MintOutcome::Start { start } => ("start", None, None, Some(start)),
+            MintOutcome::Unmintable => ("unmintable", None, None, None),
+            MintOutcome::NotFound => ("not_found", None, None, None),
+        };
+
+        conn.execute(
+            "INSERT INTO mint_attempts (tx_id, rune_id, block_height, timestamp, outcome, amount, cap, height, message) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+            params![
+                mint_attempt.tx_id,
+                mint_attempt.rune_id,
+                mint_attempt.block_height,
+                mint_attempt.timestamp,
+                outcome,
+                amount,
+                cap,
+                height,
+                mint_attempt.message
+            ],
+        )?;
+
+        Ok(())
+    }
+
+    fn mint_attempt_from_row(row: &rusqlite::Row) -> Result<MintAttempt> {
+        let outcome: String = row.get("outcome")?;
+        let amount: Option<String> = row.get("amount")?;
+        let cap: Option<String> = row.get("cap")?;
+        let height: Option<u64> = row.get("height")?;
+
+        Ok(MintAttempt {
+            tx_id: row.get("tx_id")?,
+            rune_id: row.get("rune_id")?,
+            block_height: row.get("block_height")?,
+            timestamp: row.get("timestamp")?,
+            outcome: match outcome.as_str() {
+                "minted" => MintOutcome::Minted {
+                    amount: amount
+                        .map(|v| v.parse::<u128>().unwrap())
+                        .unwrap_or_default(),
+                },
+                "cap" => MintOutcome::Cap {
+                    cap: cap.map(|v| v.parse::<u128>().unwrap()).unwrap_or_default(),
+                },
+                "end" => MintOutcome::End {
+                    end: height.unwrap_or_default(),
+                },
+                "start" => MintOutcome::Start {
+                    start: height.unwrap_or_default(),
+                },
+                "not_found" => MintOutcome::NotFound,
+                _ => MintOutcome::Unmintable,
+            },
+            message: row.get("message")?,
+        })
+    }
+
+    /// Mint attempts of a rune, oldest first.
+    pub fn get_rune_mint_attempts(
+        &self,
+        conn: &mut Connection,
+        rune_id: &str,
+        offset: u32,
+        limit: u32,
+    ) -> Result<Vec<MintAttempt>, Error> {
+        let mut stmt = conn.prepare(
+            "SELECT * FROM mint_attempts WHERE rune_id = ?1 ORDER BY block_height, rowid LIMIT ?2 OFFSET ?3",
+        )?;
+
+        let result_iter =
+            stmt.query_map(params![rune_id, limit, offset], Self::mint_attempt_from_row)?;
+
+        Ok(result_iter.map(|r| r.unwrap()).collect())
+    }
+
+    /// Mint attempts of a rune, counted by outcome.
+    pub fn get_rune_mint_attempt_counts(
+        &self,
+        conn: &mut Connection,
+        rune_id: &str,
+    ) -> Result<MintAttemptCounts, Error> {
+        let mut stmt = conn.prepare(
+            "SELECT outcome, COUNT(*) AS count FROM mint_attempts WHERE rune_id = ?1 GROUP BY outcome",
+        )?;
+
+        let result_iter = stmt.query_map(params![rune_id], |row| {
+            Ok((
+                row.get::<_, String>("outcome")?,
+                row.get::<_, u64>("count")?,
+            ))
+        })?;
+
+        let mut counts = MintAttemptCounts::default();
+
+        for result in result_iter {
+            let (outcome, count) = result?;
+
+            match outcome.as_str() {
+                "minted" => counts.minted = count,
+                "cap" => counts.cap = count,
+                "end" => counts.end = count,
+                "start" => counts.start = count,
+                "not_found" => counts.not_found = count,
+                _ => counts.unmintable += count,
+            }
+        }
+
+        Ok(counts)
+    }
+
+    pub fn get_transaction_mint_attempt(
+        &self,
+        conn: &mut Connection,
+        tx_id: &str,
+    ) -> Result<Option<MintAttempt>, Error> {
+        let mut stmt = conn.prepare("SELECT * FROM mint_attempts WHERE tx_id = ?1")?;
+
+        let result_iter = stmt.query_map(params![tx_id], Self::mint_attempt_from_row)?;
+
+        let mint_attempt = result_iter.map(|r| r.unwrap()).next();
+        Ok(mint_attempt)
+    }
+
+    /// Height of the block that created taproot output `tx_id:output_index`,
+    /// or `None` if it isn't a taproot output created since the index started.
+    pub fn get_taproot_txo_block_height(
+        &self,
+        conn: &mut Connection,
+        tx_id: &str,
+        output_index: u32,
+    ) -> Result<Option<u64>, Error> {
+        let mut stmt = conn.prepare_cached(
+            "SELECT block_height FROM taproot_txos WHERE tx_id = ?1 AND output_index = ?2",
+        )?;
+
+        let result_iter = stmt.query_map(params![tx_id, output_index], |row| {
+            let block_height: u64 = row.get("block_height")?;
+
+            Ok(block_height)
+        })?;
+
+        let block_height = result_iter.map(|r| r.unwrap()).next();
+        Ok(block_height)
+    }
+
+    pub fn get_address_balance_by_rune_id(
+        &self,
+        conn: &mut Connection,
+        address: &str,
+        rune_id: &str,
+    ) -> Result<u128, Error> {
+        let mut stmt = conn
+        .prepare("SELECT amount FROM runes_txos WHERE address = ?1 AND rune_id = ?2 AND is_unspent = TRUE")?;
+
+        let result_iter = stmt.query_map(params![address.to_lowercase(), rune_id], |row| {
+            let amount: String = row.get("amount")?;
+
+            Ok(amount.parse::<u128>().unwrap_or_default())
+        })?;
+
+        Ok(result_iter.map(|r| r.unwrap()).sum())
+    }
+
+    pub fn get_address_balance_list(
+        &self,
+        conn: &mut Connection,
+        address: &str,
+    ) -> Result<HashMap<String, u128>, Error> {
+        let mut stmt = conn.prepare(
+            "SELECT rune_id, amount FROM runes_txos WHERE address = ?1 AND is_unspent = TRUE",
+        )?;
+
+        let result_iter = stmt.query_map(params![address.to_lowercase()], |row| {
+            let rune_id: String = row.get("rune_id")?;
+            let amount: String = row.get("amount")?;
+
+            Ok((rune_id, amount.parse::<u128>().unwrap_or_default()))
+        })?;
+
+        let mut balance_list: HashMap<String, u128> = HashMap::new();
+
+        for balance in result_iter {
+            let (rune_id, amount) = balance.unwrap();
+            *balance_list.entry(rune_id).or_default() += amount;
+        }
+
+        Ok(balance_list)
+    }
+
+    pub fn get_runes_txo_by_output_index(
+        &self,
+        conn: &mut Connection,
+        tx_id: &str,
+        output_index: u32,
+    ) -> Result<Vec<RuneTXO>, Error> {
+        let mut stmt =
+            conn.prepare("SELECT * FROM runes_txos WHERE tx_id = ?1 AND output_index = ?2")?;
+
+        let result_iter = stmt.query_map(params![tx_id, output_index], |row| {
+            let amount: String = row.get("amount")?;
+
+            Ok(RuneTXO {
+                tx_id: row.get("tx_id")?,
+                output_index: row.get("output_index")?,
+                rune_id: row.get("rune_id")?,
+                amount: amount.parse().unwrap(),
+                address: row.get("address")?,
+                is_unspent: row.get("is_unspent")?,
+                spent_tx_id: row.get("spent_tx_id")?,
+                timestamp: row.get("timestamp")?,
+                block_height: row.get("block_height")?,
+                spent_block_height: row.get("spent_block_height")?,
+            })
+        })?;
+
+        Ok(result_iter.map(|r| r.unwrap()).collect())
+    }
+
+    pub fn get_transaction_runes_txo(
+        &self,
+        conn: &mut Connection,
+        tx_id: &str,
+    ) -> Result<Vec<RuneTXO>, Error> {
+        let mut stmt =
+            conn.prepare("SELECT * FROM runes_txos WHERE tx_id = ?1 OR spent_tx_id = ?1")?;
+
+        let result_iter = stmt.query_map(params![tx_id], |row| {
+            let amount: String = row.get("amount")?;
+
+            Ok(RuneTXO {
+                tx_id: row.get("tx_id")?,
+                output_index: row.get("output_index")?,
+                rune_id: row.get("rune_id")?,
+                amount: amount.parse().unwrap(),
+                address: row.get("address")?,
+                is_unspent: row.get("is_unspent")?,
+                spent_tx_id: row.get("spent_tx_id")?,
+                timestamp: row.get("timestamp")?,
+                block_height: row.get("block_height")?,
+                spent_block_height: row.get("spent_block_height")?,
+            })
+        })?;
+
+        Ok(result_iter.map(|r| r.unwrap()).collect())
+    }
+
+    pub fn get_address_runes_txo(
+        &self,
+        conn: &mut Connection,
+        address: &str,
+    ) -> Result<Vec<RuneTXO>, Error> {
+        let mut stmt = conn.prepare("SELECT * FROM runes_txos WHERE address = ?1")?;
+
+        let result_iter = stmt
+            .query_map(params![address.to_lowercase()], |row| {
+                let amount: String = row.get("amount")?;
+
+                Ok(RuneTXO {
+                    tx_id: row.get("tx_id")?,
+                    output_index: row.get("output_index")?,
+                    rune_id: row.get("rune_id")?,
+                    amount: amount.parse().unwrap(),
+                    address: row.get("address")?,
+                    is_unspent: row.get("is_unspent")?,
+                    spent_tx_id: row.get("spent_tx_id")?,
+                    timestamp: row.get("timestamp")?,
+                    block_height: row.get("block_height")?,
+                    spent_block_height: row.get("spent_block_height")?,
+                })
+            })
+            .unwrap();
+
+        Ok(result_iter.map(|r| r.unwrap()).collect())
+    }
+
+    pub fn get_address_runes_utxo_by_rune_id(
+        &self,
+        conn: &mut Connection,
+        address: &str,
+        rune_id: &str,
+    ) -> Result<Vec<RuneTXO>, Error> {
+        let mut stmt = conn.prepare(
+            "SELECT * FROM runes_txos WHERE address = ?1 AND rune_id = ?2 AND is_unspent = TRUE",
+        )?;
 
         let result_iter = stmt
             .query_map(params![address.to_lowercase(), rune_id], |row| {
@@ -863,6 +1490,50 @@ impl SQLite {
         Ok(result_iter.map(|r| r.unwrap()).collect())
     }
 
+    pub fn get_metadata(&self, conn: &mut Connection, key: &str) -> Result<Option<String>, Error> {
+        let mut stmt = conn.prepare("SELECT value FROM metadata WHERE key = ?1")?;
+        let result_iter = stmt.query_map(params![key], |row| {
+            let value: String = row.get("value")?;
+
+            Ok(value)
+        })?;
+
+        let value = result_iter.map(|r| r.unwrap()).next();
+        Ok(value)
+    }
+
+    pub fn set_metadata(
+        &mut self,
+        conn: &mut Connection,
+        key: &str,
+        value: &str,
+    ) -> Result<(), Error> {
+        conn.execute(
+            "INSERT OR REPLACE INTO metadata (key, value) VALUES (?1, ?2)",
+            params![key, value],
+        )?;
+
+        Ok(())
+    }
+
+    /// Refuses to use an index built from another chain. Empty databases are
+    /// labeled with `chain`, databases created before the chain was recorded
+    /// have to be labeled after checking their blocks against the node.
+    pub fn check_chain(&mut self, conn: &mut Connection, chain: &str) -> Result<(), Error> {
+        match self.get_metadata(conn, "chain")? {
+            Some(indexed_chain) if indexed_chain != chain => bail!(
+                "database contains an index of {}, not {}. Use a different db_path for each chain",
+                indexed_chain,
+                chain
+            ),
+            Some(_) => Ok(()),
+            None if self.get_latest_block(conn)?.is_some() => bail!(
+                "database contains an index of an unknown chain. Use a different db_path for each chain"
+            ),
+            None => self.set_metadata(conn, "chain", chain),
+        }
+    }
+
     pub fn get_latest_block(&self, conn: &mut Connection) -> Result<Option<Block>, Error> {
         let mut stmt = conn.prepare("SELECT * FROM blocks ORDER BY height DESC LIMIT 1")?;
         let result_iter = stmt.query_map([], |row| {
@@ -895,74 +1566,898 @@ impl SQLite {
         Ok(block)
     }
 
-    pub fn insert_block(&mut self, conn: &mut Connection, block: Block) -> Result<(), Error> {
-        let count: u64;
+    /// Blocks below the reorg window are only kept every
+    /// `CHECKPOINT_INTERVAL` blocks, to find a common ancestor after reorgs
+    /// deeper than the window.
+    pub const CHECKPOINT_INTERVAL: u64 = 1000;
 
-        {
-            let mut stmt = conn.prepare("SELECT COUNT(*) FROM blocks")?;
-            let result_iter = stmt.query_map([], |row| {
-                let count: u64 = row.get("0")?;
+    pub fn insert_block(
+        &mut self,
+        conn: &mut Connection,
+        block: Block,
+        reorg_window: u32,
+    ) -> Result<(), Error> {
+        let tx = conn.savepoint()?;
 
-                Ok(count)
-            })?;
+        let window_start = block.height.saturating_sub(reorg_window.into());
 
-            count = result_iter.map(|r| r.unwrap()).next().unwrap_or_default();
-        }
+        tx.execute(
+            "DELETE FROM blocks WHERE height <= ?1 AND height % ?2 != 0",
+            params![window_start, Self::CHECKPOINT_INTERVAL],
+        )?;
+
+        tx.execute(
+            "INSERT INTO blocks (height, hash, timestamp) VALUES (?1, ?2, ?3)",
+            params![block.height, block.hash, block.timestamp],
+        )?;
+
+        tx.commit()?;
+
+        Ok(())
+    }
+
+    /// Retained blocks below `height`, newest first.
+    pub fn get_blocks_below(
+        &self,
+        conn: &mut Connection,
+        height: u64,
+    ) -> Result<Vec<Block>, Error> {
+        let mut stmt =
+            conn.prepare("SELECT * FROM blocks WHERE height < ?1 ORDER BY height DESC")?;
+        let result_iter = stmt.query_map(params![height], |row| {
+            Ok(Block {
+                height: row.get("height")?,
+                hash: row.get("hash")?,
+                timestamp: row.get("timestamp")?,
+            })
+        })?;
 
-        let tx = conn.transaction()?;
+        Ok(result_iter.map(|r| r.unwrap()).collect())
+    }
+
+    /// Removes everything indexed, so that indexing starts over.
+    pub fn reset(&mut self, conn: &mut Connection, new_tip_hash: &str) -> Result<(), Error> {
+        let tx = conn.savepoint()?;
+
+        Self::archive_orphans(&tx, None, new_tip_hash)?;
+
+        for table in [
+            "blocks",
+            "rune_entries",
+            "terms",
+            "transactions",
+            "rune_events",
+            "runes_txos",
+            "taproot_txos",
+            "etching_rejections",
+            "mint_attempts",
+            "runestones",
+            "rune_flows",
+        ] {
+            tx.execute(&format!("DELETE FROM {}", table), [])?;
+        }
+
+        tx.commit()?;
+
+        Ok(())
+    }
+
+    /// Tables whose rows are archived when a reorg removes them.
+    const ORPHANED_TABLES: [&'static str; 9] = [
+        "transactions",
+        "rune_entries",
+        "terms",
+        "rune_events",
+        "runes_txos",
+        "etching_rejections",
+        "mint_attempts",
+        "runestones",
+        "rune_flows",
+    ];
+
+    /// Records a reorg event forking at `fork_height`, and copies the rows of
+    /// the blocks above it to the orphaned_* tables. Without a fork height
+    /// every row is archived.
+    fn archive_orphans(
+        conn: &Connection,
+        fork_height: Option<u64>,
+        new_tip_hash: &str,
+    ) -> Result<(), Error> {
+        let (old_tip_height, old_tip_hash): (u64, String) = conn.query_row(
+            "SELECT height, hash FROM blocks ORDER BY height DESC LIMIT 1",
+            [],
+            |row| Ok((row.get("height")?, row.get("hash")?)),
+        )?;
+
+        conn.execute(
+            "INSERT INTO reorg_events (fork_height, old_tip_height, old_tip_hash, new_tip_hash, depth, timestamp) VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            params![
+                fork_height,
+                old_tip_height,
+                old_tip_hash,
+                new_tip_hash,
+                old_tip_height - fork_height.unwrap_or_default(),
+                u32::try_from(Utc::now().timestamp())?
+            ],
+        )?;
+
+        let reorg_event_id = conn.last_insert_rowid();
+
+        let above = match fork_height {
+            Some(height) => i64::try_from(height)?,
+            None => -1,
+        };
+
+        // columns added by migrations may be in a different order in the
+        // orphaned_* tables, so they are copied by name
+        for table in Self::ORPHANED_TABLES {
+            let columns = Self::columns(conn, table)?.join(", ");
+
+            conn.execute(
+                &format!(
+                    "INSERT INTO orphaned_{table} (reorg_event_id, {columns}) SELECT ?1, {columns} FROM {table} WHERE block_height > ?2"
+                ),
+                params![reorg_event_id, above],
+            )?;
+        }
+
+        Ok(())
+    }
+
+    fn columns(conn: &Connection, table: &str) -> Result<Vec<String>, Error> {
+        let mut stmt = conn.prepare(&format!("SELECT name FROM pragma_table_info('{table}')"))?;
+        let result_iter = stmt.query_map([], |row| row.get("name"))?;
+
+        Ok(result_iter.map(|r| r.unwrap()).collect())
+    }
+
+    pub fn reorg_blocks(
+        &mut self,
+        conn: &mut Connection,
+        height: u64,
+        new_tip_hash: &str,
+    ) -> Result<(), Error> {
+        let tx = conn.savepoint()?;
+
+        Self::archive_orphans(&tx, Some(height), new_tip_hash)?;
+
+        // runes minted or burned in the orphaned blocks, their aggregates are
+        // recomputed from the surviving events once those blocks are removed
+        let affected_rune_ids: Vec<String> = {
+            let mut stmt = tx.prepare(
+                "SELECT DISTINCT rune_id FROM rune_events WHERE block_height > ?1 AND event_type IN ('mint', 'burn')",
+            )?;
+
+            let result_iter = stmt.query_map(params![height], |row| row.get("rune_id"))?;
+
+            result_iter.map(|r| r.unwrap()).collect()
+        };
+
+        tx.execute("DELETE FROM blocks WHERE height > ?1", params![height])?;
+
+        tx.execute(
+            "DELETE FROM rune_entries WHERE block_height > ?1",
+            params![height],
+        )?;
+
+        tx.execute("DELETE FROM terms WHERE block_height > ?1", params![height])?;
+
+        tx.execute(
+            "DELETE FROM transactions WHERE block_height > ?1",
+            params![height],
+        )?;
+
+        tx.execute(
+            "DELETE FROM rune_events WHERE block_height > ?1",
+            params![height],
+        )?;
+
+        tx.execute(
+            "DELETE FROM runes_txos WHERE block_height > ?1",
+            params![height],
+        )?;
+
+        tx.execute(
+            "DELETE FROM etching_rejections WHERE block_height > ?1",
+            params![height],
+        )?;
+
+        tx.execute(
+            "DELETE FROM mint_attempts WHERE block_height > ?1",
+            params![height],
+        )?;
+
+        tx.execute(
+            "DELETE FROM runestones WHERE block_height > ?1",
+            params![height],
+        )?;
+
+        tx.execute(
+            "DELETE FROM rune_flows WHERE block_height > ?1",
+            params![height],
+        )?;
+
+        tx.execute(
+            "UPDATE runes_txos SET is_unspent = TRUE, spent_tx_id = NULL, spent_block_height = NULL WHERE spent_block_height > ?1", 
+            params![height]
+        )?;
+
+        tx.execute(
+            "DELETE FROM taproot_txos WHERE block_height > ?1",
+            params![height],
+        )?;
+
+        tx.execute(
+            "UPDATE taproot_txos SET spent_block_height = NULL WHERE spent_block_height > ?1",
+            params![height],
+        )?;
+
+        for rune_id in affected_rune_ids {
+            Self::recompute_rune_entry_aggregates(&tx, &rune_id)?;
+        }
+
+        tx.commit()?;
+
+        Ok(())
+    }
+
+    /// Sets `mint_count` and `burned` of a rune entry from its mint and burn
+    /// events.
+    fn recompute_rune_entry_aggregates(conn: &Connection, rune_id: &str) -> Result<(), Error> {
+        let mut mint_count: u128 = 0;
+        let mut burned: u128 = 0;
+
+        {
+            let mut stmt = conn.prepare(
+                "SELECT amount, event_type FROM rune_events WHERE rune_id = ?1 AND event_type IN ('mint', 'burn')",
+            )?;
+
+            let result_iter = stmt.query_map(params![rune_id], |row| {
+                let amount: String = row.get("amount")?;
+                let event_type: String = row.get("event_type")?;
+
+                Ok((amount.parse::<u128>().unwrap_or_default(), event_type))
+            })?;
+
+            for event in result_iter {
+                match event? {
+                    (_, event_type) if event_type == "mint" => mint_count += 1,
+                    (amount, _) => burned += amount,
+                }
+            }
+        }
+
+        conn.execute(
+            "UPDATE rune_entries SET mint_count = ?1, burned = ?2 WHERE rune_id = ?3",
+            params![mint_count.to_string(), burned.to_string(), rune_id],
+        )?;
+
+        Ok(())
+    }
+
+    /// Burn events of a rune, newest first.
+    pub fn get_rune_burn_events(
+        &self,
+        conn: &mut Connection,
+        rune_id: &str,
+        offset: u32,
+        limit: u32,
+    ) -> Result<Vec<RuneEvent>, Error> {
+        let mut stmt = conn.prepare(
+            "SELECT * FROM rune_events WHERE rune_id = ?1 AND event_type = 'burn' ORDER BY block_height DESC, rowid DESC LIMIT ?2 OFFSET ?3",
+        )?;
+
+        let result_iter = stmt.query_map(params![rune_id, limit, offset], |row| {
+            let amount: String = row.get("amount")?;
+
+            Ok(RuneEvent {
+                tx_id: row.get("tx_id")?,
+                rune_id: row.get("rune_id")?,
+                block_height: row.get("block_height")?,
+                timestamp: row.get("timestamp")?,
+                amount: amount.parse().unwrap(),
+                event_type: row.get("event_type")?,
+                output_index: row.get("output_index")?,
+                address: row.get("address")?,
+                burn_cause: row
+                    .get::<_, Option<String>>("burn_cause")?
+                    .and_then(|cause| cause.parse().ok()),
+            })
+        })?;
+
+        Ok(result_iter.map(|r| r.unwrap()).collect())
+    }
+
+    /// Runes burned of a rune, by cause. Amounts are summed here since they
+    /// don't fit in an SQLite integer.
+    pub fn get_rune_burn_totals(
+        &self,
+        conn: &mut Connection,
+        rune_id: &str,
+    ) -> Result<Vec<BurnTotal>, Error> {
+        let mut stmt = conn.prepare(
+            "SELECT amount, burn_cause FROM rune_events WHERE rune_id = ?1 AND event_type = 'burn'",
+        )?;
+
+        let result_iter = stmt.query_map(params![rune_id], |row| {
+            let amount: String = row.get("amount")?;
+            let burn_cause: Option<String> = row.get("burn_cause")?;
+
+            Ok((amount.parse::<u128>().unwrap_or_default(), burn_cause))
+        })?;
+
+        let mut totals: Vec<BurnTotal> = Vec::new();
+
+        for event in result_iter {
+            let (amount, burn_cause) = event?;
+            let burn_cause = burn_cause.and_then(|cause| cause.parse::<BurnCause>().ok());
+
+            match totals
+                .iter_mut()
+                .find(|total| total.burn_cause == burn_cause)
+            {
+                Some(total) => {
+                    total.amount += amount;
+                    total.count += 1;
+                }
+                None => totals.push(BurnTotal {
+                    burn_cause,
+                    amount,
+                    count: 1,
+                }),
+            }
+        }
+
+        totals.sort_by_key(|total| total.burn_cause.map(|cause| cause.to_string()));
+
+        Ok(totals)
+    }
+
+    pub fn get_reorg_events(&self, conn: &mut Connection) -> Result<Vec<ReorgEvent>, Error> {
+        let mut stmt = conn.prepare("SELECT * FROM reorg_events ORDER BY id DESC")?;
+
+        let result_iter = stmt.query_map([], |row| {
+            Ok(ReorgEvent {
+                id: row.get("id")?,
+                fork_height: row.get("fork_height")?,
+                old_tip_height: row.get("old_tip_height")?,
+                old_tip_hash: row.get("old_tip_hash")?,
+                new_tip_hash: row.get("new_tip_hash")?,
+                depth: row.get("depth")?,
+                timestamp: row.get("timestamp")?,
+            })
+        })?;
+
+        Ok(result_iter.map(|r| r.unwrap()).collect())
+    }
+
+    pub fn get_reorg_event(
+        &self,
+        conn: &mut Connection,
+        id: u64,
+    ) -> Result<Option<ReorgEvent>, Error> {
+        let mut stmt = conn.prepare("SELECT * FROM reorg_events WHERE id = ?1")?;
+
+        let result_iter = stmt.query_map(params![id], |row| {
+            Ok(ReorgEvent {
+                id: row.get("id")?,
+                fork_height: row.get("fork_height")?,
+                old_tip_height: row.get("old_tip_height")?,
+                old_tip_hash: row.get("old_tip_hash")?,
+                new_tip_hash: row.get("new_tip_hash")?,
+                depth: row.get("depth")?,
+                timestamp: row.get("timestamp")?,
+            })
+        })?;
+
+        let reorg_event = result_iter.map(|r| r.unwrap()).next();
+        Ok(reorg_event)
+    }
+
+    /// Transactions with rune activity in the blocks orphaned by a reorg
+    /// event, and the height they confirmed at on the new chain, if they did.
+    pub fn get_orphaned_transactions(
+        &self,
+        conn: &mut Connection,
+        reorg_event_id: u64,
+    ) -> Result<Vec<OrphanedTransaction>, Error> {
+        let mut stmt = conn.prepare(
+            "SELECT
+                orphaned.tx_id,
+                MIN(orphaned.block_height) AS block_height,
+                (
+                    SELECT block_height FROM transactions WHERE tx_id = orphaned.tx_id
+                    UNION SELECT block_height FROM rune_events WHERE tx_id = orphaned.tx_id
+                    LIMIT 1
+                ) AS reconfirmed_block_height
+            FROM (
+                SELECT tx_id, block_height FROM orphaned_transactions WHERE reorg_event_id = ?1
+                UNION SELECT tx_id, block_height FROM orphaned_rune_events WHERE reorg_event_id = ?1
+            ) AS orphaned
+            GROUP BY orphaned.tx_id
+            ORDER BY block_height, orphaned.tx_id",
+        )?;
+
+        let result_iter = stmt.query_map(params![reorg_event_id], |row| {
+            Ok(OrphanedTransaction {
+                tx_id: row.get("tx_id")?,
+                block_height: row.get("block_height")?,
+                reconfirmed_block_height: row.get("reconfirmed_block_height")?,
+            })
+        })?;
+
+        Ok(result_iter.map(|r| r.unwrap()).collect())
+    }
+
+    pub fn get_orphaned_rune_entries(
+        &self,
+        conn: &mut Connection,
+        reorg_event_id: u64,
+    ) -> Result<Vec<RuneEntry>, Error> {
+        let mut stmt =
+            conn.prepare("SELECT * FROM orphaned_rune_entries WHERE reorg_event_id = ?1")?;
+
+        let result_iter = stmt.query_map(params![reorg_event_id], |row| {
+            let symbol: Option<String> = row.get("symbol")?;
+            let premine: String = row.get("premine")?;
+            let burned: String = row.get("burned")?;
+            let mint_count: String = row.get("mint_count")?;
+            let rune_number: String = row.get("rune_number")?;
+            let rune_id: String = row.get("rune_id")?;
+
+            let mut terms_stmt = conn.prepare(
+                "SELECT * FROM orphaned_terms WHERE reorg_event_id = ?1 AND rune_id = ?2",
+            )?;
+
+            let terms_result_iter =
+                terms_stmt.query_map(params![reorg_event_id, rune_id], |row| {
+                    let amount: Option<String> = row.get("amount")?;
+                    let cap: Option<String> = row.get("cap")?;
+
+                    Ok(Terms {
+                        amount: amount.map(|a| a.parse::<u128>().unwrap()),
+                        cap: cap.map(|c| c.parse::<u128>().unwrap()),
+                        height_start: row.get("height_start")?,
+                        height_end: row.get("height_end")?,
+                        offset_start: row.get("offset_start")?,
+                        offset_end: row.get("offset_end")?,
+                        block_height: row.get("block_height")?,
+                    })
+                })?;
+
+            let terms: Option<Terms> = terms_result_iter.into_iter().next().map(|t| t.unwrap());
+
+            Ok(RuneEntry {
+                etching_tx_id: row.get("etching_tx_id")?,
+                block_height: row.get("block_height")?,
+                rune_id,
+                name: row.get("name")?,
+                raw_name: row.get("raw_name")?,
+                symbol: symbol.map(|s| s.chars().next().unwrap()),
+                divisibility: row.get("divisibility")?,
+                premine: premine.parse().unwrap(),
+                terms,
+                burned: burned.parse().unwrap(),
+                mint_count: mint_count.parse().unwrap(),
+                timestamp: row.get("timestamp")?,
+                is_cenotapth: row.get("is_cenotapth")?,
+                cenotapth_message: row.get("cenotapth_message")?,
+                rune_number: rune_number.parse().unwrap(),
+                turbo: row.get("turbo")?,
+            })
+        })?;
+
+        Ok(result_iter.map(|r| r.unwrap()).collect())
+    }
+
+    pub fn get_orphaned_rune_events(
+        &self,
+        conn: &mut Connection,
+        reorg_event_id: u64,
+    ) -> Result<Vec<RuneEvent>, Error> {
+        let mut stmt =
+            conn.prepare("SELECT * FROM orphaned_rune_events WHERE reorg_event_id = ?1")?;
+
+        let result_iter = stmt.query_map(params![reorg_event_id], |row| {
+            let amount: String = row.get("amount")?;
+
+            Ok(RuneEvent {
+                tx_id: row.get("tx_id")?,
+                rune_id: row.get("rune_id")?,
+                block_height: row.get("block_height")?,
+                timestamp: row.get("timestamp")?,
+                amount: amount.parse().unwrap(),
+                event_type: row.get("event_type")?,
+                output_index: row.get("output_index")?,
+                address: row.get("address")?,
+                burn_cause: row
+                    .get::<_, Option<String>>("burn_cause")?
+                    .and_then(|cause| cause.parse().ok()),
+            })
+        })?;
+
+        Ok(result_iter.map(|r| r.unwrap()).collect())
+    }
+
+    pub fn get_orphaned_runes_txos(
+        &self,
+        conn: &mut Connection,
+        reorg_event_id: u64,
+    ) -> Result<Vec<RuneTXO>, Error> {
+        let mut stmt =
+            conn.prepare("SELECT * FROM orphaned_runes_txos WHERE reorg_event_id = ?1")?;
+
+        let result_iter = stmt.query_map(params![reorg_event_id], |row| {
+            let amount: String = row.get("amount")?;
+
+            Ok(RuneTXO {
+                tx_id: row.get("tx_id")?,
+                output_index: row.get("output_index")?,
+                rune_id: row.get("rune_id")?,
+                amount: amount.parse().unwrap(),
+                address: row.get("address")?,
+                is_unspent: row.get("is_unspent")?,
+                spent_tx_id: row.get("spent_tx_id")?,
+                timestamp: row.get("timestamp")?,
+                block_height: row.get("block_height")?,
+                spent_block_height: row.get("spent_block_height")?,
+            })
+        })?;
+
+        Ok(result_iter.map(|r| r.unwrap()).collect())
+    }
+
+    /// Rune outputs created at `block_height`.
+    pub fn get_runes_txos_by_block_height(
+        &self,
+        conn: &mut Connection,
+        block_height: u64,
+    ) -> Result<Vec<RuneTXO>, Error> {
+        let mut stmt = conn.prepare("SELECT * FROM runes_txos WHERE block_height = ?1")?;
+
+        let result_iter = stmt.query_map(params![block_height], |row| {
+            let amount: String = row.get("amount")?;
+
+            Ok(RuneTXO {
+                tx_id: row.get("tx_id")?,
+                output_index: row.get("output_index")?,
+                rune_id: row.get("rune_id")?,
+                amount: amount.parse().unwrap(),
+                address: row.get("address")?,
+                is_unspent: row.get("is_unspent")?,
+                spent_tx_id: row.get("spent_tx_id")?,
+                timestamp: row.get("timestamp")?,
+                block_height: row.get("block_height")?,
+                spent_block_height: row.get("spent_block_height")?,
+            })
+        })?;
+
+        Ok(result_iter.map(|r| r.unwrap()).collect())
+    }
+
+    /// Rune outputs created before `block_height` and spent at it.
+    pub fn get_runes_txos_spent_at_block_height(
+        &self,
+        conn: &mut Connection,
+        block_height: u64,
+    ) -> Result<Vec<RuneTXO>, Error> {
+        let mut stmt = conn.prepare(
+            "SELECT * FROM runes_txos WHERE spent_block_height = ?1 AND block_height < ?1",
+        )?;
+
+        let result_iter = stmt.query_map(params![block_height], |row| {
+            let amount: String = row.get("amount")?;
+
+            Ok(RuneTXO {
+                tx_id: row.get("tx_id")?,
+                output_index: row.get("output_index")?,
+                rune_id: row.get("rune_id")?,
+                amount: amount.parse().unwrap(),
+                address: row.get("address")?,
+                is_unspent: row.get("is_unspent")?,
+                spent_tx_id: row.get("spent_tx_id")?,
+                timestamp: row.get("timestamp")?,
+                block_height: row.get("block_height")?,
+                spent_block_height: row.get("spent_block_height")?,
+            })
+        })?;
+
+        Ok(result_iter.map(|r| r.unwrap()).collect())
+    }
+
+    pub fn get_rune_events_by_block_height(
+        &self,
+        conn: &mut Connection,
+        block_height: u64,
+    ) -> Result<Vec<RuneEvent>, Error> {
+        let mut stmt = conn.prepare("SELECT * FROM rune_events WHERE block_height = ?1")?;
+
+        let result_iter = stmt.query_map(params![block_height], |row| {
+            let amount: String = row.get("amount")?;
+
+            Ok(RuneEvent {
+                tx_id: row.get("tx_id")?,
+                rune_id: row.get("rune_id")?,
+                block_height: row.get("block_height")?,
+                timestamp: row.get("timestamp")?,
+                amount: amount.parse().unwrap(),
+                event_type: row.get("event_type")?,
+                output_index: row.get("output_index")?,
+                address: row.get("address")?,
+                burn_cause: row
+                    .get::<_, Option<String>>("burn_cause")?
+                    .and_then(|cause| cause.parse().ok()),
+            })
+        })?;
+
+        Ok(result_iter.map(|r| r.unwrap()).collect())
+    }
+
+    pub fn get_runes_by_block_height(
+        &self,
+        conn: &mut Connection,
+        block_height: u64,
+    ) -> Result<Vec<RuneEntry>, Error> {
+        let rune_ids: Vec<String> = {
+            let mut stmt =
+                conn.prepare("SELECT rune_id FROM rune_entries WHERE block_height = ?1")?;
+
+            let result_iter = stmt.query_map(params![block_height], |row| row.get("rune_id"))?;
+
+            result_iter.map(|r| r.unwrap()).collect()
+        };
+
+        let mut rune_entries = Vec::new();
+
+        for rune_id in rune_ids {
+            rune_entries.extend(self.get_rune_by_id(conn, &rune_id)?);
+        }
+
+        Ok(rune_entries)
+    }
+
+    /// Replaces the pending state of the mempool.
+    pub fn replace_mempool(
+        &mut self,
+        conn: &mut Connection,
+        pending: &PendingState,
+    ) -> Result<(), Error> {
+        let tx = conn.savepoint()?;
+
+        for table in [
+            "mempool_runes_txos",
+            "mempool_spent_txos",
+            "mempool_mints",
+            "mempool_etchings",
+        ] {
+            tx.execute(&format!("DELETE FROM {}", table), [])?;
+        }
+
+        Self::insert_pending(&tx, pending)?;
+
+        tx.commit()?;
+
+        Ok(())
+    }
+
+    /// Replaces the pending state of the transactions in `tx_ids` with
+    /// `pending`, leaving that of other transactions as is.
+    pub fn update_mempool(
+        &mut self,
+        conn: &mut Connection,
+        tx_ids: &HashSet<String>,
+        pending: &PendingState,
+    ) -> Result<(), Error> {
+        let tx = conn.savepoint()?;
+
+        for tx_id in tx_ids {
+            tx.execute(
+                "DELETE FROM mempool_runes_txos WHERE tx_id = ?1",
+                params![tx_id],
+            )?;
+            tx.execute(
+                "DELETE FROM mempool_spent_txos WHERE spent_tx_id = ?1",
+                params![tx_id],
+            )?;
+            tx.execute("DELETE FROM mempool_mints WHERE tx_id = ?1", params![tx_id])?;
+            tx.execute(
+                "DELETE FROM mempool_etchings WHERE tx_id = ?1",
+                params![tx_id],
+            )?;
+        }
+
+        Self::insert_pending(&tx, pending)?;
+
+        tx.commit()?;
+
+        Ok(())
+    }
+
+    fn insert_pending(conn: &Connection, pending: &PendingState) -> Result<(), Error> {
+        for txo in &pending.runes_txos {
+            conn.execute(
+                "INSERT INTO mempool_runes_txos (tx_id, output_index, rune_id, amount, address, spent_tx_id) VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+                params![
+                    txo.tx_id,
+                    txo.output_index,
+                    txo.rune_id,
+                    txo.amount.to_string(),
+                    txo.address,
+                    txo.spent_tx_id
+                ],
+            )?;
+        }
+
+        for spend in &pending.spends {
+            conn.execute(
+                "INSERT INTO mempool_spent_txos (tx_id, output_index, spent_tx_id) VALUES (?1, ?2, ?3)",
+                params![spend.tx_id, spend.output_index, spend.spent_tx_id],
+            )?;
+        }
+
+        for mint in &pending.mints {
+            conn.execute(
+                "INSERT INTO mempool_mints (tx_id, rune_id, amount) VALUES (?1, ?2, ?3)",
+                params![mint.tx_id, mint.rune_id, mint.amount.to_string()],
+            )?;
+        }
+
+        for etching in &pending.etchings {
+            conn.execute(
+                "INSERT INTO mempool_etchings (tx_id, name, symbol, divisibility, premine, is_cenotapth) VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+                params![
+                    etching.tx_id,
+                    etching.name,
+                    etching.symbol.map(|s| s.to_string()),
+                    etching.divisibility,
+                    etching.premine.to_string(),
+                    etching.is_cenotapth
+                ],
+            )?;
+        }
+
+        Ok(())
+    }
+
+    /// Balances of `address` once the transactions in the mempool confirm.
+    pub fn get_address_pending_balance_list(
+        &self,
+        conn: &mut Connection,
+        address: &str,
+    ) -> Result<HashMap<String, u128>, Error> {
+        let mut stmt = conn.prepare(
+            "SELECT rune_id, amount FROM runes_txos
+            WHERE address = ?1 AND is_unspent = TRUE AND NOT EXISTS (
+                SELECT 1 FROM mempool_spent_txos
+                WHERE mempool_spent_txos.tx_id = runes_txos.tx_id
+                AND mempool_spent_txos.output_index = runes_txos.output_index
+            )
+            UNION ALL
+            SELECT rune_id, amount FROM mempool_runes_txos
+            WHERE address = ?1 AND spent_tx_id IS NULL",
+        )?;
+
+        let result_iter = stmt.query_map(params![address.to_lowercase()], |row| {
+            let rune_id: String = row.get("rune_id")?;
+            let amount: String = row.get("amount")?;
+
+            Ok((rune_id, amount.parse::<u128>().unwrap_or_default()))
+        })?;
+
+        let mut balance_list: HashMap<String, u128> = HashMap::new();
+
+        for balance in result_iter {
+            let (rune_id, amount) = balance.unwrap();
+            *balance_list.entry(rune_id).or_default() += amount;
+        }
+
+        Ok(balance_list)
+    }
+
+    /// Unspent rune outputs of `address` that no transaction in the mempool
+    /// spends.
+    pub fn get_address_spendable_runes_utxo(
+        &self,
+        conn: &mut Connection,
+        address: &str,
+    ) -> Result<Vec<RuneTXO>, Error> {
+        let mut stmt = conn.prepare(
+            "SELECT * FROM runes_txos
+            WHERE address = ?1 AND is_unspent = TRUE AND NOT EXISTS (
+                SELECT 1 FROM mempool_spent_txos
+                WHERE mempool_spent_txos.tx_id = runes_txos.tx_id
+                AND mempool_spent_txos.output_index = runes_txos.output_index
+            )
+            ORDER BY block_height, tx_id, output_index",
+        )?;
+
+        let result_iter = stmt.query_map(params![address.to_lowercase()], |row| {
+            let amount: String = row.get("amount")?;
+
+            Ok(RuneTXO {
+                tx_id: row.get("tx_id")?,
+                output_index: row.get("output_index")?,
+                rune_id: row.get("rune_id")?,
+                amount: amount.parse().unwrap(),
+                address: row.get("address")?,
+                is_unspent: row.get("is_unspent")?,
+                spent_tx_id: row.get("spent_tx_id")?,
+                timestamp: row.get("timestamp")?,
+                block_height: row.get("block_height")?,
+                spent_block_height: row.get("spent_block_height")?,
+            })
+        })?;
 
-        if count >= 20 {
-            tx.execute(
-                "DELETE FROM blocks WHERE height = (SELECT MIN(height) FROM blocks)",
-                [],
-            )?;
-        }
+        Ok(result_iter.map(|r| r.unwrap()).collect())
+    }
 
-        tx.execute(
-            "INSERT INTO blocks (height, hash, timestamp) VALUES (?1, ?2, ?3)",
-            params![block.height, block.hash, block.timestamp],
+    /// Unspent rune outputs of unconfirmed transactions sent to `address`.
+    pub fn get_address_pending_runes_utxo(
+        &self,
+        conn: &mut Connection,
+        address: &str,
+    ) -> Result<Vec<PendingRuneTXO>, Error> {
+        let mut stmt = conn.prepare(
+            "SELECT * FROM mempool_runes_txos WHERE address = ?1 AND spent_tx_id IS NULL",
         )?;
 
-        tx.commit()?;
-
-        Ok(())
-    }
+        let result_iter = stmt.query_map(params![address.to_lowercase()], |row| {
+            let amount: String = row.get("amount")?;
 
-    pub fn reorg_blocks(&mut self, conn: &mut Connection, height: u64) -> Result<(), Error> {
-        let tx = conn.transaction()?;
+            Ok(PendingRuneTXO {
+                tx_id: row.get("tx_id")?,
+                output_index: row.get("output_index")?,
+                rune_id: row.get("rune_id")?,
+                amount: amount.parse().unwrap(),
+                address: row.get("address")?,
+                spent_tx_id: row.get("spent_tx_id")?,
+            })
+        })?;
 
-        tx.execute("DELETE FROM blocks WHERE height > ?1", params![height])?;
+        Ok(result_iter.map(|r| r.unwrap()).collect())
+    }
 
-        tx.execute(
-            "DELETE FROM rune_entries WHERE block_height > ?1",
-            params![height],
-        )?;
+    pub fn get_pending_mints(
+        &self,
+        conn: &mut Connection,
+        rune_id: &str,
+    ) -> Result<Vec<PendingMint>, Error> {
+        let mut stmt = conn.prepare("SELECT * FROM mempool_mints WHERE rune_id = ?1")?;
 
-        tx.execute("DELETE FROM terms WHERE block_height > ?1", params![height])?;
+        let result_iter = stmt.query_map(params![rune_id], |row| {
+            let amount: String = row.get("amount")?;
 
-        tx.execute(
-            "DELETE FROM transactions WHERE block_height > ?1",
-            params![height],
-        )?;
+            Ok(PendingMint {
+                tx_id: row.get("tx_id")?,
+                rune_id: row.get("rune_id")?,
+                amount: amount.parse().unwrap(),
+            })
+        })?;
 
-        tx.execute(
-            "DELETE FROM rune_events WHERE block_height > ?1",
-            params![height],
-        )?;
+        Ok(result_iter.map(|r| r.unwrap()).collect())
+    }
 
-        tx.execute(
-            "DELETE FROM runes_txos WHERE block_height > ?1",
-            params![height],
-        )?;
+    pub fn get_pending_etchings(
+        &self,
+        conn: &mut Connection,
+    ) -> Result<Vec<PendingEtching>, Error> {
+        let mut stmt = conn.prepare("SELECT * FROM mempool_etchings")?;
 
-        tx.execute(
-            "UPDATE runes_txos SET is_unspent = TRUE, spent_tx_id = NULL, spent_block_height = NULL WHERE spent_block_height > ?1", 
-            params![height]
-        )?;
+        let result_iter = stmt.query_map([], |row| {
+            let symbol: Option<String> = row.get("symbol")?;
+            let premine: String = row.get("premine")?;
 
-        tx.commit()?;
+            Ok(PendingEtching {
+                tx_id: row.get("tx_id")?,
+                name: row.get("name")?,
+                symbol: symbol.map(|s| s.chars().next().unwrap()),
+                divisibility: row.get("divisibility")?,
+                premine: premine.parse().unwrap(),
+                is_cenotapth: row.get("is_cenotapth")?,
+            })
+        })?;
 
-        Ok(())
+        Ok(result_iter.map(|r| r.unwrap()).collect())
     }
 
     pub fn get_db_indexes(&self, conn: &mut Connection) -> Result<Vec<SQLiteIndex>, Error> {
@@ -1158,6 +2653,110 @@ impl SQLite {
             (),
         )?;
 
+        conn.execute(
+            "
+            CREATE INDEX IF NOT EXISTS idx_rune_events_rune_id_event_type_block_height
+            ON rune_events(rune_id, event_type, block_height);
+        ",
+            (),
+        )?;
+
+        conn.execute(
+            "
+            CREATE UNIQUE INDEX IF NOT EXISTS idx_taproot_txos_tx_id_output_index
+            ON taproot_txos(tx_id, output_index);
+        ",
+            (),
+        )?;
+
+        conn.execute(
+            "
+            CREATE INDEX IF NOT EXISTS idx_taproot_txos_block_height
+            ON taproot_txos(block_height);
+        ",
+            (),
+        )?;
+
+        conn.execute(
+            "
+            CREATE INDEX IF NOT EXISTS idx_taproot_txos_spent_block_height
+            ON taproot_txos(spent_block_height);
+        ",
+            (),
+        )?;
+
+        conn.execute(
+            "
+            CREATE INDEX IF NOT EXISTS idx_etching_rejections_tx_id
+            ON etching_rejections(tx_id);
+        ",
+            (),
+        )?;
+
+        conn.execute(
+            "
+            CREATE INDEX IF NOT EXISTS idx_etching_rejections_block_height
+            ON etching_rejections(block_height);
+        ",
+            (),
+        )?;
+
+        conn.execute(
+            "
+            CREATE INDEX IF NOT EXISTS idx_mint_attempts_rune_id
+            ON mint_attempts(rune_id);
+        ",
+            (),
+        )?;
+
+        conn.execute(
+            "
+            CREATE INDEX IF NOT EXISTS idx_mint_attempts_tx_id
+            ON mint_attempts(tx_id);
+        ",
+            (),
+        )?;
+
+        conn.execute(
+            "
+            CREATE INDEX IF NOT EXISTS idx_mint_attempts_block_height
+            ON mint_attempts(block_height);
+        ",
+            (),
+        )?;
+
+        conn.execute(
+            "
+            CREATE INDEX IF NOT EXISTS idx_runestones_tx_id
+            ON runestones(tx_id);
+        ",
+            (),
+        )?;
+
+        conn.execute(
+            "
+            CREATE INDEX IF NOT EXISTS idx_runestones_block_height
+            ON runestones(block_height);
+        ",
+            (),
+        )?;
+
+        conn.execute(
+            "
+            CREATE INDEX IF NOT EXISTS idx_rune_flows_tx_id
+            ON rune_flows(tx_id);
+        ",
+            (),
+        )?;
+
+        conn.execute(
+            "
+            CREATE INDEX IF NOT EXISTS idx_rune_flows_block_height
+            ON rune_flows(block_height);
+        ",
+            (),
+        )?;
+
         conn.execute(
             "
             CREATE INDEX IF NOT EXISTS idx_blocks_height
@@ -1166,6 +2765,264 @@ impl SQLite {
             (),
         )?;
 
+        conn.execute(
+            "
+            CREATE INDEX IF NOT EXISTS idx_mempool_runes_txos_address
+            ON mempool_runes_txos(address);
+        ",
+            (),
+        )?;
+
+        conn.execute(
+            "
+            CREATE INDEX IF NOT EXISTS idx_mempool_spent_txos_tx_id_output_index
+            ON mempool_spent_txos(tx_id, output_index);
+        ",
+            (),
+        )?;
+
+        conn.execute(
+            "
+            CREATE INDEX IF NOT EXISTS idx_mempool_mints_rune_id
+            ON mempool_mints(rune_id);
+        ",
+            (),
+        )?;
+
+        conn.execute(
+            "
+            CREATE INDEX IF NOT EXISTS idx_mempool_runes_txos_tx_id
+            ON mempool_runes_txos(tx_id);
+        ",
+            (),
+        )?;
+
+        conn.execute(
+            "
+            CREATE INDEX IF NOT EXISTS idx_mempool_spent_txos_spent_tx_id
+            ON mempool_spent_txos(spent_tx_id);
+        ",
+            (),
+        )?;
+
+        conn.execute(
+            "
+            CREATE INDEX IF NOT EXISTS idx_mempool_mints_tx_id
+            ON mempool_mints(tx_id);
+        ",
+            (),
+        )?;
+
+        for table in Self::ORPHANED_TABLES {
+            conn.execute(
+                &format!(
+                    "
+                CREATE INDEX IF NOT EXISTS idx_orphaned_{table}_reorg_event_id
+                ON orphaned_{table}(reorg_event_id);
+            "
+                ),
+                (),
+            )?;
+        }
+
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn check_chain() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        let mut database = SQLite {};
+
+        database.init_tables(&mut conn).unwrap();
+
+        database.check_chain(&mut conn, "signet").unwrap();
+        database.check_chain(&mut conn, "signet").unwrap();
+
+        assert_eq!(
+            database
+                .get_metadata(&mut conn, "chain")
+                .unwrap()
+                .as_deref(),
+            Some("signet")
+        );
+
+        assert!(database.check_chain(&mut conn, "mainnet").is_err());
+    }
+
+    #[test]
+    fn check_chain_of_unlabeled_index() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        let mut database = SQLite {};
+
+        database.init_tables(&mut conn).unwrap();
+
+        database
+            .insert_block(
+                &mut conn,
+                Block {
+                    height: 1,
+                    hash: "00".to_string(),
+                    timestamp: 0,
+                },
+                20,
+            )
+            .unwrap();
+
+        assert!(database.check_chain(&mut conn, "mainnet").is_err());
+        assert!(database.get_metadata(&mut conn, "chain").unwrap().is_none());
+    }
+
+    #[test]
+    fn add_burn_cause_to_existing_tables() {
+        let mut conn = Connection::open_in_memory().unwrap();
+
+        conn.execute(
+            "CREATE TABLE rune_events (
+            tx_id TEXT NOT NULL,
+            rune_id TEXT NOT NULL,
+            block_height INTEGER NOT NULL,
+            timestamp INTEGER,
+            amount TEXT NOT NULL,
+            event_type TEXT NOT NULL,
+            output_index INTEGER,
+            address TEXT
+      )",
+            (),
+        )
+        .unwrap();
+
+        conn.execute(
+            "CREATE TABLE orphaned_rune_events AS
+            SELECT NULL AS reorg_event_id, * FROM rune_events WHERE FALSE",
+            (),
+        )
+        .unwrap();
+
+        conn.execute(
+            "INSERT INTO rune_events (tx_id, rune_id, block_height, amount, event_type) VALUES ('a', '1:1', 1, '5', 'burn')",
+            (),
+        )
+        .unwrap();
+
+        let mut database = SQLite {};
+
+        database.init_tables(&mut conn).unwrap();
+        database.init_tables(&mut conn).unwrap();
+
+        database
+            .add_rune_burn_event(
+                &mut conn,
+                RuneEvent {
+                    tx_id: "b".to_string(),
+                    rune_id: "1:1".to_string(),
+                    block_height: 2,
+                    timestamp: 0,
+                    amount: 7,
+                    event_type: "burn".to_string(),
+                    output_index: Some(0),
+                    address: None,
+                    burn_cause: Some(BurnCause::OpReturn),
+                },
+            )
+            .unwrap();
+
+        assert_eq!(
+            database.get_rune_burn_totals(&mut conn, "1:1").unwrap(),
+            vec![
+                BurnTotal {
+                    burn_cause: None,
+                    amount: 5,
+                    count: 1,
+                },
+                BurnTotal {
+                    burn_cause: Some(BurnCause::OpReturn),
+                    amount: 7,
+                    count: 1,
+                },
+            ]
+        );
+
+        conn.execute(
+            "INSERT INTO orphaned_rune_events SELECT 1, * FROM rune_events",
+            (),
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn archive_orphans_by_column_name() {
+        let mut conn = Connection::open_in_memory().unwrap();
+
+        // same columns as rune_events, in another order
+        conn.execute(
+            "CREATE TABLE orphaned_rune_events (
+            burn_cause TEXT,
+            address TEXT,
+            output_index INTEGER,
+            event_type TEXT NOT NULL,
+            amount TEXT NOT NULL,
+            timestamp INTEGER,
+            block_height INTEGER NOT NULL,
+            rune_id TEXT NOT NULL,
+            tx_id TEXT NOT NULL,
+            reorg_event_id INTEGER
+      )",
+            (),
+        )
+        .unwrap();
+
+        let mut database = SQLite {};
+
+        database.init_tables(&mut conn).unwrap();
+
+        for height in 1..=2 {
+            database
+                .insert_block(
+                    &mut conn,
+                    Block {
+                        height,
+                        hash: height.to_string(),
+                        timestamp: 0,
+                    },
+                    20,
+                )
+                .unwrap();
+        }
+
+        database
+            .add_rune_burn_event(
+                &mut conn,
+                RuneEvent {
+                    tx_id: "a".to_string(),
+                    rune_id: "1:1".to_string(),
+                    block_height: 2,
+                    timestamp: 0,
+                    amount: 7,
+                    event_type: "burn".to_string(),
+                    output_index: Some(0),
+                    address: None,
+                    burn_cause: Some(BurnCause::OpReturn),
+                },
+            )
+            .unwrap();
+
+        database.reorg_blocks(&mut conn, 1, "").unwrap();
+
+        let (tx_id, amount, burn_cause): (String, String, String) = conn
+            .query_row(
+                "SELECT tx_id, amount, burn_cause FROM orphaned_rune_events",
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+            )
+            .unwrap();
+
+        assert_eq!(tx_id, "a");
+        assert_eq!(amount, "7");
+        assert_eq!(burn_cause, BurnCause::OpReturn.to_string());
+    }
+}
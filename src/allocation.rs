@@ -0,0 +1,324 @@
+use super::lot::Lot;
+use super::runes::*;
+use utoipa::ToSchema;
+
+/// Where the runes of a transaction end up: the runes of its inputs, plus
+/// the runes it mints or premines.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Allocation {
+    /// Balances received by each output. OP_RETURN outputs never hold
+    /// balances, runes sent to them are burned.
+    pub outputs: Vec<HashMap<RuneId, Lot>>,
+    pub burned: HashMap<RuneId, Lot>,
+    /// Edicts that didn't allocate anything.
+    pub ignored_edicts: Vec<IgnoredEdict>,
+    /// Every allocation to an output, in the order they were made.
+    pub transfers: Vec<Transfer>,
+    /// What makes up `burned`.
+    pub burns: Vec<Burn>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Transfer {
+    pub id: RuneId,
+    pub amount: Lot,
+    pub output: usize,
+    pub source: TransferSource,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TransferSource {
+    /// The edict at this position in the runestone.
+    Edict(usize),
+    /// Runes left unallocated by the edicts, sent to the pointer.
+    Pointer,
+    /// Runes left unallocated, sent to the first non-OP_RETURN output when
+    /// there is no pointer.
+    DefaultOutput,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Burn {
+    pub id: RuneId,
+    pub amount: Lot,
+    pub cause: BurnCause,
+    /// The OP_RETURN output the runes were sent to.
+    pub output: Option<usize>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum BurnCause {
+    /// Every rune of a cenotaph is burned.
+    Cenotaph,
+    /// Runes were sent to an OP_RETURN output.
+    OpReturn,
+    /// Runes were left unallocated, and there is no output that isn't an
+    /// OP_RETURN to send them to.
+    NoOutput,
+}
+
+impl Display for BurnCause {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Self::Cenotaph => write!(f, "cenotaph"),
+            Self::OpReturn => write!(f, "op_return"),
+            Self::NoOutput => write!(f, "no_output"),
+        }
+    }
+}
+
+impl FromStr for BurnCause {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "cenotaph" => Ok(Self::Cenotaph),
+            "op_return" => Ok(Self::OpReturn),
+            "no_output" => Ok(Self::NoOutput),
+            _ => bail!("invalid burn cause {}", s),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct IgnoredEdict {
+    /// Position of the edict in the runestone.
+    pub index: usize,
+    pub edict: Edict,
+    pub reason: IgnoreReason,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum IgnoreReason {
+    /// The edict refers to the rune being etched, but nothing is etched.
+    NothingEtched,
+    /// No runes with the edict's id are being transferred.
+    NoBalance,
+    /// Earlier edicts allocated all runes with the edict's id.
+    BalanceExhausted,
+}
+
+impl Display for IgnoreReason {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Self::NothingEtched => {
+                write!(f, "edict refers to an etched rune, but nothing is etched")
+            }
+            Self::NoBalance => write!(f, "inputs carry none of the rune"),
+            Self::BalanceExhausted => write!(f, "earlier edicts allocated all of the rune"),
+        }
+    }
+}
+
+/// Allocates `unallocated` to the outputs of `tx`, following the edicts and
+/// pointer of its artifact. `etched` is the id of the rune the transaction
+/// etches, its premine is allocated along with the rest.
+///
+/// This has no side effects, so it can be run on transactions that aren't
+/// confirmed yet.
+pub fn allocate(
+    tx: &Transaction,
+    artifact: Option<&Artifact>,
+    mut unallocated: HashMap<RuneId, Lot>,
+    etched: Option<RuneId>,
+) -> Allocation {
+    let mut allocated: Vec<HashMap<RuneId, Lot>> = vec![HashMap::new(); tx.output.len()];
+
+    let mut ignored_edicts = Vec::new();
+
+    let mut transfers = Vec::new();
+
+    if let Some(Artifact::Runestone(runestone)) = artifact {
+        if let Some(id) = etched {
+            *unallocated.entry(id).or_default() +=
+                runestone.etching.unwrap().premine.unwrap_or_default();
+        }
+
+        for (index, edict) in runestone.edicts.iter().copied().enumerate() {
+            let Edict { id, amount, output } = edict;
+
+            let mut ignore = |reason| {
+                ignored_edicts.push(IgnoredEdict {
+                    index,
+                    edict,
+                    reason,
+                })
+            };
+
+            let amount = Lot(amount);
+
+            // edicts with output values greater than the number of outputs
+            // should never be produced by the edict parser
+            let output = usize::try_from(output).unwrap();
+            assert!(output <= tx.output.len());
+
+            let id = if id == RuneId::default() {
+                let Some(id) = etched else {
+                    ignore(IgnoreReason::NothingEtched);
+                    continue;
+                };
+
+                id
+            } else {
+                id
+            };
+
+            let Some(balance) = unallocated.get_mut(&id) else {
+                ignore(IgnoreReason::NoBalance);
+                continue;
+            };
+
+            if *balance == 0 {
+                ignore(IgnoreReason::BalanceExhausted);
+                continue;
+            }
+
+            let mut allocate = |balance: &mut Lot, amount: Lot, output: usize| {
+                if amount > 0 {
+                    *balance -= amount;
+                    *allocated[output].entry(id).or_default() += amount;
+                    transfers.push(Transfer {
+                        id,
+                        amount,
+                        output,
+                        source: TransferSource::Edict(index),
+                    });
+                }
+            };
+
+            if output == tx.output.len() {
+                // find non-OP_RETURN outputs
+                let destinations = tx
+                    .output
+                    .iter()
+                    .enumerate()
+                    .filter_map(|(output, tx_out)| {
+                        (!tx_out.script_pubkey.is_op_return()).then_some(output)
+                    })
+                    .collect::<Vec<usize>>();
+
+                if !destinations.is_empty() {
+                    if amount == 0 {
+                        // if amount is zero, divide balance between eligible outputs
+                        let amount = *balance / destinations.len() as u128;
+                        let remainder =
+                            usize::try_from(*balance % destinations.len() as u128).unwrap();
+
+                        for (i, output) in destinations.iter().enumerate() {
+                            allocate(
+                                balance,
+                                if i < remainder { amount + 1 } else { amount },
+                                *output,
+                            );
+                        }
+                    } else {
+                        // if amount is non-zero, distribute amount to eligible outputs
+                        for output in destinations {
+                            allocate(balance, amount.min(*balance), output);
+                        }
+                    }
+                }
+            } else {
+                // Get the allocatable amount
+                let amount = if amount == 0 {
+                    *balance
+                } else {
+                    amount.min(*balance)
+                };
+
+                allocate(balance, amount, output);
+            }
+        }
+    }
+
+    let mut burned: HashMap<RuneId, Lot> = HashMap::new();
+
+    let mut burns = Vec::new();
+
+    let mut burn = |id: RuneId, amount: Lot, cause: BurnCause, output: Option<usize>| {
+        *burned.entry(id).or_default() += amount;
+        burns.push(Burn {
+            id,
+            amount,
+            cause,
+            output,
+        });
+    };
+
+    // sorted so transfers and burns are listed in a fixed order
+    let mut unallocated = unallocated.into_iter().collect::<Vec<(RuneId, Lot)>>();
+    unallocated.sort();
+
+    if let Some(Artifact::Cenotaph(_)) = artifact {
+        for (id, balance) in unallocated {
+            burn(id, balance, BurnCause::Cenotaph, None);
+        }
+    } else {
+        let pointer = artifact
+            .map(|artifact| match artifact {
+                Artifact::Runestone(runestone) => runestone.pointer,
+                Artifact::Cenotaph(_) => unreachable!(),
+            })
+            .unwrap_or_default();
+
+        // assign all un-allocated runes to the default output, or the first non
+        // OP_RETURN output if there is no default, or if the default output is
+        // too large
+        let source = if pointer.is_some() {
+            TransferSource::Pointer
+        } else {
+            TransferSource::DefaultOutput
+        };
+
+        if let Some(vout) = pointer
+            .map(|pointer| pointer.into_usize())
+            .inspect(|&pointer| assert!(pointer < allocated.len()))
+            .or_else(|| {
+                tx.output
+                    .iter()
+                    .enumerate()
+                    .find(|(_vout, tx_out)| !tx_out.script_pubkey.is_op_return())
+                    .map(|(vout, _tx_out)| vout)
+            })
+        {
+            for (id, balance) in unallocated {
+                if balance > 0 {
+                    *allocated[vout].entry(id).or_default() += balance;
+                    transfers.push(Transfer {
+                        id,
+                        amount: balance,
+                        output: vout,
+                        source,
+                    });
+                }
+            }
+        } else {
+            for (id, balance) in unallocated {
+                if balance > 0 {
+                    burn(id, balance, BurnCause::NoOutput, None);
+                }
+            }
+        }
+    }
+
+    // increment burned balances
+    for (vout, balances) in allocated.iter_mut().enumerate() {
+        if tx.output[vout].script_pubkey.is_op_return() {
+            let mut balances = balances.drain().collect::<Vec<(RuneId, Lot)>>();
+            balances.sort();
+
+            for (id, balance) in balances {
+                burn(id, balance, BurnCause::OpReturn, Some(vout));
+            }
+        }
+    }
+
+    Allocation {
+        outputs: allocated,
+        burned,
+        ignored_edicts,
+        transfers,
+        burns,
+    }
+}
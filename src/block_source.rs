@@ -0,0 +1,165 @@
+use crate::btc_rpc::{Prevout, RawBlockHeader, TransactionInfo, BTCRPC};
+use anyhow::Error;
+use bitcoin::{Block, OutPoint};
+use std::collections::HashMap;
+use std::future::Future;
+use std::ops::Range;
+
+pub use blk_files::BlkFileSource;
+#[cfg(test)]
+pub use fixture::FixtureSource;
+
+pub mod blk_files;
+#[cfg(test)]
+pub mod fixture;
+
+/// Where the indexer reads the chain from. Block hashes are lowercase hex
+/// strings, like everywhere else in the index.
+pub trait BlockSource: Clone + Send + Sync + 'static {
+    fn get_block_count(&self) -> impl Future<Output = Result<u32, Error>> + Send;
+
+    /// Returns `None` if there is no block at `height` on the active chain.
+    fn get_block_hash_by_height(
+        &self,
+        height: u32,
+    ) -> impl Future<Output = Result<Option<String>, Error>> + Send;
+
+    fn get_block_by_hash(
+        &self,
+        block_hash: &str,
+    ) -> impl Future<Output = Result<Block, Error>> + Send;
+
+    fn get_block_header_by_hash(
+        &self,
+        block_hash: &str,
+    ) -> impl Future<Output = Result<RawBlockHeader, Error>> + Send;
+
+    /// Looks up a previous transaction, and the hash of the block that
+    /// confirmed it. Returns `None` if the source doesn't know the
+    /// transaction, like a node without -txindex for most confirmed ones.
+    fn get_transaction(
+        &self,
+        tx_id: &str,
+    ) -> impl Future<Output = Result<Option<TransactionInfo>, Error>> + Send;
+
+    /// Looks up several transactions, see `get_transaction`. Sources that
+    /// can look up several transactions at once should override this.
+    fn get_transactions(
+        &self,
+        tx_ids: &[String],
+    ) -> impl Future<Output = Result<Vec<Option<TransactionInfo>>, Error>> + Send {
+        async move {
+            let mut txs = Vec::new();
+
+            for tx_id in tx_ids {
+                txs.push(self.get_transaction(tx_id).await?);
+            }
+
+            Ok(txs)
+        }
+    }
+
+    /// The outputs spent by the inputs of a block, with the heights they were
+    /// created at. Returns `None` if the source can't tell anymore, like a
+    /// pruned node that deleted the block's undo data.
+    fn get_block_prevouts(
+        &self,
+        block_hash: &str,
+    ) -> impl Future<Output = Result<Option<HashMap<OutPoint, Prevout>>, Error>> + Send;
+
+    /// Txids of the transactions in the node's mempool. Sources without a
+    /// mempool report an empty one.
+    fn get_raw_mempool(&self) -> impl Future<Output = Result<Vec<String>, Error>> + Send {
+        async { Ok(Vec::new()) }
+    }
+
+    fn get_block_by_height(
+        &self,
+        height: u32,
+    ) -> impl Future<Output = Result<Block, Error>> + Send {
+        async move {
+            let Some(block_hash) = self.get_block_hash_by_height(height).await? else {
+                anyhow::bail!("no block at height {}", height);
+            };
+
+            self.get_block_by_hash(&block_hash).await
+        }
+    }
+
+    /// Fetches a range of blocks in height order. Sources that can fetch
+    /// several blocks at once should override this.
+    fn get_blocks_by_heights(
+        &self,
+        heights: Range<u32>,
+    ) -> impl Future<Output = Result<Vec<Block>, Error>> + Send {
+        async move {
+            let mut blocks = Vec::new();
+
+            for height in heights {
+                blocks.push(self.get_block_by_height(height).await?);
+            }
+
+            Ok(blocks)
+        }
+    }
+}
+
+impl BlockSource for BTCRPC {
+    async fn get_block_count(&self) -> Result<u32, Error> {
+        Ok(BTCRPC::get_block_count(self).await?)
+    }
+
+    async fn get_block_hash_by_height(&self, height: u32) -> Result<Option<String>, Error> {
+        match BTCRPC::get_block_hash_by_height(self, height).await {
+            Ok(block_hash) => Ok(Some(block_hash)),
+            Err(err) if err.is_out_of_range() => Ok(None),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    async fn get_block_by_hash(&self, block_hash: &str) -> Result<Block, Error> {
+        Ok(BTCRPC::get_block_by_hash(self, block_hash).await?)
+    }
+
+    async fn get_block_header_by_hash(&self, block_hash: &str) -> Result<RawBlockHeader, Error> {
+        Ok(BTCRPC::get_block_header_by_hash(self, block_hash).await?)
+    }
+
+    async fn get_transaction(&self, tx_id: &str) -> Result<Option<TransactionInfo>, Error> {
+        match BTCRPC::get_transaction(self, tx_id).await {
+            Ok(tx_info) => Ok(Some(tx_info)),
+            Err(err) if err.is_not_found() => Ok(None),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    async fn get_transactions(
+        &self,
+        tx_ids: &[String],
+    ) -> Result<Vec<Option<TransactionInfo>>, Error> {
+        Ok(BTCRPC::get_transactions(self, tx_ids).await?)
+    }
+
+    async fn get_block_prevouts(
+        &self,
+        block_hash: &str,
+    ) -> Result<Option<HashMap<OutPoint, Prevout>>, Error> {
+        match BTCRPC::get_block_prevouts(self, block_hash).await {
+            Ok(prevouts) => Ok(Some(prevouts)),
+            Err(err) if err.is_not_found() || err.is_unavailable() => Ok(None),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    async fn get_raw_mempool(&self) -> Result<Vec<String>, Error> {
+        Ok(BTCRPC::get_raw_mempool(self).await?)
+    }
+
+    async fn get_block_by_height(&self, height: u32) -> Result<Block, Error> {
+        Ok(BTCRPC::get_block_by_height(self, height).await?)
+    }
+
+    async fn get_blocks_by_heights(&self, heights: Range<u32>) -> Result<Vec<Block>, Error> {
+        Ok(BTCRPC::get_blocks_by_heights(self, heights).await?)
+    }
+}
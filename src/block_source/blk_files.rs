@@ -0,0 +1,551 @@
+use super::BlockSource;
+use crate::btc_rpc::{Prevout, RawBlockHeader, TransactionInfo};
+use crate::log_file::log;
+use anyhow::{anyhow, bail, ensure, Context, Error};
+use bitcoin::blockdata::block::Header;
+use bitcoin::consensus::deserialize;
+use bitcoin::hashes::Hash;
+use bitcoin::{Block, BlockHash, OutPoint};
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{BufReader, ErrorKind, Read, Seek, SeekFrom};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Instant;
+
+/// Reads blocks straight from the `blk*.dat` files in bitcoind's `blocks/`
+/// directory, and everything else from `fallback`.
+///
+/// bitcoind writes blocks to disk in the order they were downloaded, not in
+/// height order, and keeps the order in a LevelDB index. Instead of reading
+/// that index, the block headers in the files are scanned on startup and the
+/// active chain is rebuilt by following previous block hashes back from a
+/// block `fallback` reports as being on the active chain.
+///
+/// Blocks within `TIP_DISTANCE` of the tip at startup, and blocks found later,
+/// are read from `fallback`, so that reorgs are picked up as usual. Once a
+/// height past the blocks found on disk is asked for, the initial sync is over
+/// and heights are always resolved by `fallback`, since a reorg may since have
+/// replaced blocks found on disk.
+#[derive(Clone, Debug)]
+pub struct BlkFileSource<S: BlockSource> {
+    fallback: S,
+    files: Arc<BlkFiles>,
+}
+
+#[derive(Debug)]
+struct BlkFiles {
+    dir: PathBuf,
+    xor_key: [u8; 8],
+    // hashes of the blocks of the active chain found on disk, by height
+    // starting at `first_height`
+    first_height: u32,
+    hashes: Vec<String>,
+    locations: HashMap<String, Location>,
+    caught_up: AtomicBool,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Location {
+    file: u32,
+    offset: u64,
+    size: u32,
+}
+
+impl<S: BlockSource> BlkFileSource<S> {
+    /// Blocks this close to the tip are always read from the fallback.
+    pub const TIP_DISTANCE: u32 = 6;
+
+    pub async fn open(dir: &Path, magic: [u8; 4], fallback: S) -> Result<BlkFileSource<S>, Error> {
+        let start = Instant::now();
+
+        log(&format!("Scanning block files in {}", dir.display()))?;
+
+        let scan_dir = dir.to_path_buf();
+        let (xor_key, locations, previous) =
+            tokio::task::spawn_blocking(move || scan(&scan_dir, magic)).await??;
+
+        log(&format!(
+            "Found {} blocks on disk in {} seconds",
+            locations.len(),
+            start.elapsed().as_secs()
+        ))?;
+
+        let tip_height = fallback.get_block_count().await?;
+
+        let mut first_height = 0;
+        let mut hashes = Vec::new();
+
+        // bitcoind may not have flushed the latest blocks to disk yet, step
+        // back until a block on the active chain is found in the files
+        let mut anchor_height = tip_height.saturating_sub(Self::TIP_DISTANCE);
+
+        loop {
+            let Some(hash) = fallback.get_block_hash_by_height(anchor_height).await? else {
+                break;
+            };
+
+            if locations.contains_key(&hash) {
+                let mut hash = hash;
+                let mut height = anchor_height;
+
+                loop {
+                    hashes.push(hash.clone());
+
+                    match previous.get(&hash) {
+                        Some(previous) if height > 0 && locations.contains_key(previous) => {
+                            hash = previous.clone();
+                            height -= 1;
+                        }
+                        // pruned nodes don't keep the oldest blocks
+                        _ => break,
+                    }
+                }
+
+                hashes.reverse();
+                first_height = height;
+
+                break;
+            }
+
+            if anchor_height == 0 || tip_height - anchor_height > 1000 {
+                break;
+            }
+
+            anchor_height -= 1;
+        }
+
+        if hashes.is_empty() {
+            log("No blocks of the active chain found on disk, reading all blocks over RPC")?;
+        } else {
+            log(&format!(
+                "Reading blocks {} to {} from disk",
+                first_height,
+                first_height + hashes.len() as u32 - 1
+            ))?;
+        }
+
+        Ok(BlkFileSource {
+            fallback,
+            files: Arc::new(BlkFiles {
+                dir: dir.to_path_buf(),
+                xor_key,
+                first_height,
+                hashes,
+                locations,
+                caught_up: AtomicBool::new(false),
+            }),
+        })
+    }
+}
+
+impl BlkFiles {
+    fn hash_at(&self, height: u32) -> Option<&String> {
+        if self.caught_up.load(Ordering::Relaxed) {
+            return None;
+        }
+
+        let index = height.checked_sub(self.first_height)?;
+
+        let hash = self.hashes.get(index as usize);
+
+        if hash.is_none() {
+            self.caught_up.store(true, Ordering::Relaxed);
+        }
+
+        hash
+    }
+
+    fn read_block(&self, location: Location) -> Result<Block, Error> {
+        let path = blk_file_path(&self.dir, location.file);
+
+        let mut file =
+            File::open(&path).with_context(|| format!("failed to open `{}`", path.display()))?;
+
+        file.seek(SeekFrom::Start(location.offset))?;
+
+        let mut data = vec![0; location.size as usize];
+        file.read_exact(&mut data)?;
+
+        unxor(&self.xor_key, location.offset, &mut data);
+
+        deserialize(&data).with_context(|| {
+            format!(
+                "invalid block at offset {} of `{}`",
+                location.offset,
+                path.display()
+            )
+        })
+    }
+}
+
+impl<S: BlockSource> BlockSource for BlkFileSource<S> {
+    async fn get_block_count(&self) -> Result<u32, Error> {
+        self.fallback.get_block_count().await
+    }
+
+    async fn get_block_hash_by_height(&self, height: u32) -> Result<Option<String>, Error> {
+        match self.files.hash_at(height) {
+            Some(hash) => Ok(Some(hash.clone())),
+            None => self.fallback.get_block_hash_by_height(height).await,
+        }
+    }
+
+    async fn get_block_by_hash(&self, block_hash: &str) -> Result<Block, Error> {
+        let Some(location) = self.files.locations.get(block_hash).copied() else {
+            return self.fallback.get_block_by_hash(block_hash).await;
+        };
+
+        let files = self.files.clone();
+
+        tokio::task::spawn_blocking(move || files.read_block(location)).await?
+    }
+
+    async fn get_block_header_by_hash(&self, block_hash: &str) -> Result<RawBlockHeader, Error> {
+        self.fallback.get_block_header_by_hash(block_hash).await
+    }
+
+    async fn get_transaction(&self, tx_id: &str) -> Result<Option<TransactionInfo>, Error> {
+        self.fallback.get_transaction(tx_id).await
+    }
+
+    async fn get_transactions(
+        &self,
+        tx_ids: &[String],
+    ) -> Result<Vec<Option<TransactionInfo>>, Error> {
+        self.fallback.get_transactions(tx_ids).await
+    }
+
+    // bitcoind keeps undo data in rev*.dat files, which aren't read, so
+    // prevouts come from the fallback
+    async fn get_block_prevouts(
+        &self,
+        block_hash: &str,
+    ) -> Result<Option<HashMap<OutPoint, Prevout>>, Error> {
+        self.fallback.get_block_prevouts(block_hash).await
+    }
+
+    async fn get_raw_mempool(&self) -> Result<Vec<String>, Error> {
+        self.fallback.get_raw_mempool().await
+    }
+
+    async fn get_blocks_by_heights(&self, heights: Range<u32>) -> Result<Vec<Block>, Error> {
+        let mut locations = Vec::new();
+
+        for height in heights.clone() {
+            match self.files.hash_at(height) {
+                Some(hash) => locations.push(self.files.locations[hash]),
+                None => break,
+            }
+        }
+
+        let on_disk = heights.start..heights.start + locations.len() as u32;
+
+        let files = self.files.clone();
+
+        let mut blocks = tokio::task::spawn_blocking(move || {
+            locations
+                .into_iter()
+                .map(|location| files.read_block(location))
+                .collect::<Result<Vec<Block>, Error>>()
+        })
+        .await??;
+
+        if on_disk.end < heights.end {
+            blocks.extend(
+                self.fallback
+                    .get_blocks_by_heights(on_disk.end..heights.end)
+                    .await?,
+            );
+        }
+
+        Ok(blocks)
+    }
+}
+
+type Scan = ([u8; 8], HashMap<String, Location>, HashMap<String, String>);
+
+/// Reads the header of every block in the `blk*.dat` files of `dir`, returning
+/// the XOR key, where each block is stored, and each block's previous block.
+fn scan(dir: &Path, magic: [u8; 4]) -> Result<Scan, Error> {
+    let xor_key = read_xor_key(dir)?;
+
+    let mut locations = HashMap::new();
+    let mut previous = HashMap::new();
+
+    for file in 0.. {
+        let path = blk_file_path(dir, file);
+
+        let mut reader = match File::open(&path) {
+            Ok(file) => BufReader::new(file),
+            Err(err) if err.kind() == ErrorKind::NotFound => break,
+            Err(err) => {
+                return Err(err).with_context(|| format!("failed to open `{}`", path.display()))
+            }
+        };
+
+        let mut offset = 0;
+
+        loop {
+            // every block is prefixed with the network magic and its size
+            let mut prefix = [0; 8];
+
+            match reader.read_exact(&mut prefix) {
+                Ok(()) => {}
+                Err(err) if err.kind() == ErrorKind::UnexpectedEof => break,
+                Err(err) => return Err(err.into()),
+            }
+
+            unxor(&xor_key, offset, &mut prefix);
+
+            // the end of the file is preallocated with zeros
+            if prefix[..4] == [0; 4] {
+                break;
+            }
+
+            ensure!(
+                prefix[..4] == magic,
+                "unexpected network magic {} at offset {} of `{}`, is the chain set correctly?",
+                hex::encode(&prefix[..4]),
+                offset,
+                path.display()
+            );
+
+            let size = u32::from_le_bytes(prefix[4..].try_into().unwrap());
+
+            ensure!(
+                size >= 80,
+                "invalid block size {} at offset {} of `{}`",
+                size,
+                offset,
+                path.display()
+            );
+
+            let block_offset = offset + 8;
+
+            let mut header = [0; 80];
+            reader.read_exact(&mut header)?;
+            unxor(&xor_key, block_offset, &mut header);
+
+            let header: Header = deserialize(&header)?;
+
+            let hash = header.block_hash().to_string();
+
+            if header.prev_blockhash != BlockHash::all_zeros() {
+                previous.insert(hash.clone(), header.prev_blockhash.to_string());
+            }
+
+            locations.insert(
+                hash,
+                Location {
+                    file,
+                    offset: block_offset,
+                    size,
+                },
+            );
+
+            reader.seek_relative(i64::from(size) - 80)?;
+
+            offset = block_offset + u64::from(size);
+        }
+    }
+
+    if locations.is_empty() {
+        bail!("no blk*.dat files found in `{}`", dir.display());
+    }
+
+    Ok((xor_key, locations, previous))
+}
+
+/// bitcoind 28.0 and later obfuscate block files with the key in `xor.dat`.
+fn read_xor_key(dir: &Path) -> Result<[u8; 8], Error> {
+    match fs::read(dir.join("xor.dat")) {
+        Ok(key) => key
+            .try_into()
+            .map_err(|key: Vec<u8>| anyhow!("invalid xor.dat key length {}", key.len())),
+        Err(err) if err.kind() == ErrorKind::NotFound => Ok([0; 8]),
+        Err(err) => Err(err.into()),
+    }
+}
+
+fn unxor(key: &[u8; 8], offset: u64, data: &mut [u8]) {
+    if key == &[0; 8] {
+        return;
+    }
+
+    for (i, byte) in data.iter_mut().enumerate() {
+        *byte ^= key[((offset + i as u64) % 8) as usize];
+    }
+}
+
+fn blk_file_path(dir: &Path, file: u32) -> PathBuf {
+    dir.join(format!("blk{:05}.dat", file))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::fixture::testing::*;
+    use super::super::FixtureSource;
+    use super::*;
+    use bitcoin::consensus::serialize;
+    use bitcoin::Network;
+
+    const XOR_KEY: [u8; 8] = [1, 2, 3, 4, 5, 6, 7, 8];
+
+    fn write_blk_file(dir: &Path, file: u32, blocks: &[&Block]) {
+        let mut data = Vec::new();
+
+        for block in blocks {
+            let block = serialize(*block);
+            data.extend(Network::Regtest.magic().to_bytes());
+            data.extend(u32::try_from(block.len()).unwrap().to_le_bytes());
+            data.extend(block);
+        }
+
+        // preallocated space
+        data.extend([0; 64]);
+
+        unxor(&XOR_KEY, 0, &mut data);
+
+        fs::write(blk_file_path(dir, file), data).unwrap();
+    }
+
+    #[tokio::test]
+    async fn read_blocks_from_disk_in_height_order() {
+        let fixtures = temp_dir();
+        let blocks_dir = temp_dir();
+
+        // genesis and 10 more blocks, plus a stale block
+        let main = chain(BlockHash::all_zeros(), 0, vec![vec![]; 11]);
+        let stale = block(
+            main[4].block_hash(),
+            5,
+            vec![tx(&[outpoint(1)], None, vec![])],
+        );
+
+        FixtureSource::record(&fixtures, FixtureSource::DEFAULT_CHAIN, 0, &main).unwrap();
+
+        fs::write(blocks_dir.join("xor.dat"), XOR_KEY).unwrap();
+
+        // blocks are stored out of order, and the last blocks haven't been
+        // written to disk yet
+        write_blk_file(&blocks_dir, 0, &[&main[0], &main[2], &main[1], &stale]);
+        write_blk_file(&blocks_dir, 1, &[&main[4], &main[3], &main[5]]);
+
+        let fallback = FixtureSource::open(&fixtures, FixtureSource::DEFAULT_CHAIN).unwrap();
+
+        let source =
+            BlkFileSource::open(&blocks_dir, Network::Regtest.magic().to_bytes(), fallback)
+                .await
+                .unwrap();
+
+        assert_eq!(source.files.first_height, 0);
+        assert_eq!(source.files.hashes.len(), 5);
+
+        assert_eq!(source.get_block_count().await.unwrap(), 10);
+
+        assert_eq!(
+            source
+                .get_blocks_by_heights(0..11)
+                .await
+                .unwrap()
+                .iter()
+                .map(|block| block.block_hash())
+                .collect::<Vec<BlockHash>>(),
+            main.iter()
+                .map(|block| block.block_hash())
+                .collect::<Vec<BlockHash>>()
+        );
+
+        assert_eq!(
+            source
+                .get_block_by_hash(&stale.block_hash().to_string())
+                .await
+                .unwrap(),
+            stale
+        );
+
+        fs::remove_dir_all(fixtures).unwrap();
+        fs::remove_dir_all(blocks_dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn follow_reorgs_of_blocks_found_on_disk_once_caught_up() {
+        let fixtures = temp_dir();
+        let blocks_dir = temp_dir();
+
+        let main = chain(BlockHash::all_zeros(), 0, vec![vec![]; 11]);
+        let fork = chain(
+            main[2].block_hash(),
+            3,
+            vec![vec![tx(&[outpoint(1)], None, vec![])]; 9],
+        );
+
+        FixtureSource::record(&fixtures, FixtureSource::DEFAULT_CHAIN, 0, &main).unwrap();
+        FixtureSource::record(&fixtures, "fork", 0, &[&main[..3], &fork[..]].concat()).unwrap();
+
+        fs::write(blocks_dir.join("xor.dat"), XOR_KEY).unwrap();
+
+        write_blk_file(&blocks_dir, 0, &main.iter().collect::<Vec<&Block>>());
+
+        let fallback = FixtureSource::open(&fixtures, FixtureSource::DEFAULT_CHAIN).unwrap();
+
+        let source = BlkFileSource::open(
+            &blocks_dir,
+            Network::Regtest.magic().to_bytes(),
+            fallback.clone(),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(source.files.hashes.len(), 5);
+
+        source.get_blocks_by_heights(0..11).await.unwrap();
+
+        // a reorg deeper than the blocks read from the fallback
+        fallback.switch_chain("fork").unwrap();
+
+        assert_eq!(
+            source.get_block_hash_by_height(3).await.unwrap(),
+            Some(fork[0].block_hash().to_string())
+        );
+
+        assert_eq!(
+            source
+                .get_blocks_by_heights(3..12)
+                .await
+                .unwrap()
+                .iter()
+                .map(|block| block.block_hash())
+                .collect::<Vec<BlockHash>>(),
+            fork.iter()
+                .map(|block| block.block_hash())
+                .collect::<Vec<BlockHash>>()
+        );
+
+        fs::remove_dir_all(fixtures).unwrap();
+        fs::remove_dir_all(blocks_dir).unwrap();
+    }
+
+    #[test]
+    fn wrong_network_magic_is_an_error() {
+        let blocks_dir = temp_dir();
+
+        fs::write(blocks_dir.join("xor.dat"), XOR_KEY).unwrap();
+
+        write_blk_file(&blocks_dir, 0, &[&block(BlockHash::all_zeros(), 0, vec![])]);
+
+        assert_eq!(
+            scan(&blocks_dir, Network::Regtest.magic().to_bytes())
+                .unwrap()
+                .1
+                .len(),
+            1
+        );
+        assert!(scan(&blocks_dir, Network::Bitcoin.magic().to_bytes()).is_err());
+
+        fs::remove_dir_all(blocks_dir).unwrap();
+    }
+}
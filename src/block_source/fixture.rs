@@ -0,0 +1,740 @@
+use super::BlockSource;
+use crate::btc_rpc::{Prevout, RawBlockHeader, TransactionInfo};
+use anyhow::{anyhow, Context, Error};
+use bitcoin::consensus::{deserialize, serialize};
+use bitcoin::{Block, OutPoint, Transaction};
+use serde::*;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+
+/// Replays blocks recorded in a fixture directory:
+///
+/// ```text
+/// <dir>/blocks/<block hash>.hex   consensus encoded blocks
+/// <dir>/chains/<name>.json        {"start_height": 840000, "blocks": ["<block hash>", ...]}
+/// <dir>/mempool/<name>.json       ["<consensus encoded transaction>", ...]
+/// ```
+///
+/// Several chains can share the same blocks directory, switching between
+/// chains that diverge after a common prefix replays a reorg. The mempool
+/// of a chain is read again on every request, so it can be rewritten while
+/// the source is in use.
+#[derive(Clone, Debug)]
+pub struct FixtureSource {
+    fixtures: Arc<RwLock<Fixtures>>,
+}
+
+#[derive(Debug)]
+struct Fixtures {
+    dir: PathBuf,
+    blocks: HashMap<String, Block>,
+    chain_name: String,
+    chain: FixtureChain,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct FixtureChain {
+    pub start_height: u32,
+    pub blocks: Vec<String>,
+}
+
+impl FixtureChain {
+    fn height_of(&self, block_hash: &str) -> Option<u32> {
+        self.blocks
+            .iter()
+            .position(|hash| hash == block_hash)
+            .map(|index| self.start_height + index as u32)
+    }
+}
+
+impl FixtureSource {
+    pub const DEFAULT_CHAIN: &'static str = "default";
+
+    pub fn open(dir: &Path, chain: &str) -> Result<FixtureSource, Error> {
+        let mut blocks = HashMap::new();
+
+        let blocks_dir = dir.join("blocks");
+
+        for entry in fs::read_dir(&blocks_dir)
+            .with_context(|| format!("failed to read fixtures from `{}`", blocks_dir.display()))?
+        {
+            let path = entry?.path();
+
+            if path.extension().and_then(|extension| extension.to_str()) != Some("hex") {
+                continue;
+            }
+
+            let hex = fs::read_to_string(&path)?;
+
+            let block: Block = deserialize(&hex::decode(hex.trim())?)
+                .with_context(|| format!("invalid block fixture `{}`", path.display()))?;
+
+            blocks.insert(block.block_hash().to_string().to_lowercase(), block);
+        }
+
+        Ok(FixtureSource {
+            fixtures: Arc::new(RwLock::new(Fixtures {
+                dir: dir.to_path_buf(),
+                chain: Self::read_chain(dir, chain, &blocks)?,
+                chain_name: chain.to_string(),
+                blocks,
+            })),
+        })
+    }
+
+    /// Makes `chain` the active chain.
+    pub fn switch_chain(&self, chain: &str) -> Result<(), Error> {
+        let mut fixtures = self.fixtures.write().unwrap();
+
+        fixtures.chain = Self::read_chain(&fixtures.dir, chain, &fixtures.blocks)?;
+        fixtures.chain_name = chain.to_string();
+
+        Ok(())
+    }
+
+    /// Writes `blocks`, starting at `start_height`, as chain `chain` of the
+    /// fixture directory `dir`.
+    pub fn record(
+        dir: &Path,
+        chain: &str,
+        start_height: u32,
+        blocks: &[Block],
+    ) -> Result<(), Error> {
+        let blocks_dir = dir.join("blocks");
+        let chains_dir = dir.join("chains");
+
+        fs::create_dir_all(&blocks_dir)?;
+        fs::create_dir_all(&chains_dir)?;
+
+        for block in blocks {
+            fs::write(
+                blocks_dir.join(format!("{}.hex", block.block_hash())),
+                hex::encode(serialize(block)),
+            )?;
+        }
+
+        let chain_file = FixtureChain {
+            start_height,
+            blocks: blocks
+                .iter()
+                .map(|block| block.block_hash().to_string().to_lowercase())
+                .collect(),
+        };
+
+        fs::write(
+            chains_dir.join(format!("{chain}.json")),
+            serde_json::to_string_pretty(&chain_file)?,
+        )?;
+
+        Ok(())
+    }
+
+    /// Writes `txs` as the mempool of chain `chain` of the fixture directory
+    /// `dir`.
+    pub fn record_mempool(dir: &Path, chain: &str, txs: &[Transaction]) -> Result<(), Error> {
+        let mempool_dir = dir.join("mempool");
+
+        fs::create_dir_all(&mempool_dir)?;
+
+        let txs = txs
+            .iter()
+            .map(|tx| hex::encode(serialize(tx)))
+            .collect::<Vec<String>>();
+
+        fs::write(
+            mempool_dir.join(format!("{chain}.json")),
+            serde_json::to_string_pretty(&txs)?,
+        )?;
+
+        Ok(())
+    }
+
+    fn read_mempool(&self) -> Result<Vec<Transaction>, Error> {
+        let fixtures = self.fixtures.read().unwrap();
+
+        let path = fixtures
+            .dir
+            .join("mempool")
+            .join(format!("{}.json", fixtures.chain_name));
+
+        if !path.exists() {
+            return Ok(Vec::new());
+        }
+
+        let txs: Vec<String> = serde_json::from_str(&fs::read_to_string(&path)?)?;
+
+        txs.iter()
+            .map(|hex| {
+                deserialize(&hex::decode(hex)?)
+                    .with_context(|| format!("invalid transaction in `{}`", path.display()))
+            })
+            .collect()
+    }
+
+    fn read_chain(
+        dir: &Path,
+        chain: &str,
+        blocks: &HashMap<String, Block>,
+    ) -> Result<FixtureChain, Error> {
+        let path = dir.join("chains").join(format!("{chain}.json"));
+
+        let chain: FixtureChain = serde_json::from_str(
+            &fs::read_to_string(&path)
+                .with_context(|| format!("failed to read fixture chain `{}`", path.display()))?,
+        )?;
+
+        if let Some(missing) = chain.blocks.iter().find(|hash| !blocks.contains_key(*hash)) {
+            return Err(anyhow!(
+                "fixture chain `{}` references missing block {}",
+                path.display(),
+                missing
+            ));
+        }
+
+        Ok(chain)
+    }
+}
+
+impl BlockSource for FixtureSource {
+    async fn get_block_count(&self) -> Result<u32, Error> {
+        let fixtures = self.fixtures.read().unwrap();
+
+        let chain = &fixtures.chain;
+
+        (chain.start_height + chain.blocks.len() as u32)
+            .checked_sub(1)
+            .ok_or_else(|| anyhow!("fixture chain is empty"))
+    }
+
+    async fn get_block_hash_by_height(&self, height: u32) -> Result<Option<String>, Error> {
+        let fixtures = self.fixtures.read().unwrap();
+
+        let chain = &fixtures.chain;
+
+        Ok(height
+            .checked_sub(chain.start_height)
+            .and_then(|index| chain.blocks.get(index as usize))
+            .cloned())
+    }
+
+    async fn get_block_by_hash(&self, block_hash: &str) -> Result<Block, Error> {
+        self.fixtures
+            .read()
+            .unwrap()
+            .blocks
+            .get(block_hash)
+            .cloned()
+            .ok_or_else(|| anyhow!("block {} not found in fixtures", block_hash))
+    }
+
+    async fn get_block_header_by_hash(&self, block_hash: &str) -> Result<RawBlockHeader, Error> {
+        let fixtures = self.fixtures.read().unwrap();
+
+        let block = fixtures
+            .blocks
+            .get(block_hash)
+            .ok_or_else(|| anyhow!("block {} not found in fixtures", block_hash))?;
+
+        let height = fixtures
+            .chain
+            .height_of(block_hash)
+            .ok_or_else(|| anyhow!("block {} is not on the active fixture chain", block_hash))?;
+
+        let tip_height = fixtures.chain.start_height + fixtures.chain.blocks.len() as u32 - 1;
+
+        Ok(RawBlockHeader {
+            hash: block_hash.to_string(),
+            confirmations: tip_height - height + 1,
+            height,
+            time: block.header.time.into(),
+            n_tx: block.txdata.len() as u32,
+            previousblockhash: block.header.prev_blockhash.to_string().to_lowercase(),
+        })
+    }
+
+    async fn get_transaction(&self, tx_id: &str) -> Result<Option<TransactionInfo>, Error> {
+        let fixtures = self.fixtures.read().unwrap();
+
+        for block_hash in &fixtures.chain.blocks {
+            let block = &fixtures.blocks[block_hash];
+
+            if let Some(tx) = block
+                .txdata
+                .iter()
+                .find(|tx| tx.txid().to_string().to_lowercase() == tx_id)
+            {
+                return Ok(Some(TransactionInfo {
+                    data: tx.clone(),
+                    blockhash: Some(block_hash.clone()),
+                }));
+            }
+        }
+
+        drop(fixtures);
+
+        if let Some(tx) = self
+            .read_mempool()?
+            .into_iter()
+            .find(|tx| tx.txid().to_string().to_lowercase() == tx_id)
+        {
+            return Ok(Some(TransactionInfo {
+                data: tx,
+                blockhash: None,
+            }));
+        }
+
+        Ok(None)
+    }
+
+    async fn get_block_prevouts(
+        &self,
+        block_hash: &str,
+    ) -> Result<Option<HashMap<OutPoint, Prevout>>, Error> {
+        let fixtures = self.fixtures.read().unwrap();
+
+        let Some(height) = fixtures.chain.height_of(block_hash) else {
+            return Ok(None);
+        };
+
+        let mut outputs = HashMap::new();
+
+        for (hash, output_height) in fixtures
+            .chain
+            .blocks
+            .iter()
+            .zip(fixtures.chain.start_height..=height)
+        {
+            for tx in &fixtures.blocks[hash].txdata {
+                for (vout, output) in tx.output.iter().enumerate() {
+                    outputs.insert(
+                        OutPoint {
+                            txid: tx.txid(),
+                            vout: vout as u32,
+                        },
+                        Prevout {
+                            script_pubkey: output.script_pubkey.clone(),
+                            height: output_height,
+                        },
+                    );
+                }
+            }
+        }
+
+        Ok(Some(
+            fixtures.blocks[block_hash]
+                .txdata
+                .iter()
+                .flat_map(|tx| &tx.input)
+                .filter_map(|input| {
+                    outputs
+                        .get(&input.previous_output)
+                        .map(|prevout| (input.previous_output, prevout.clone()))
+                })
+                .collect(),
+        ))
+    }
+
+    async fn get_raw_mempool(&self) -> Result<Vec<String>, Error> {
+        Ok(self
+            .read_mempool()?
+            .iter()
+            .map(|tx| tx.txid().to_string().to_lowercase())
+            .collect())
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod testing {
+    use super::FixtureSource;
+    use crate::adapters::sqlite::SQLite;
+    use crate::block_source::BlockSource;
+    use crate::indexer::Indexer;
+    use crate::runes::*;
+    use bitcoin::blockdata::block::{Header, Version};
+    use bitcoin::{CompactTarget, PubkeyHash};
+    use rusqlite::Connection;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use tokio::sync::watch;
+
+    /// A fresh, empty directory for fixtures.
+    pub(crate) fn temp_dir() -> PathBuf {
+        static COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+        let dir = std::env::temp_dir().join(format!(
+            "runes-explorer-fixtures-{}-{}",
+            std::process::id(),
+            COUNTER.fetch_add(1, Ordering::Relaxed)
+        ));
+
+        std::fs::remove_dir_all(&dir).ok();
+        std::fs::create_dir_all(&dir).unwrap();
+
+        dir
+    }
+
+    /// Builds consecutive blocks on top of `prev`, one per entry of `txs`,
+    /// each with a coinbase followed by the given transactions.
+    pub(crate) fn chain(
+        prev: BlockHash,
+        start_height: u32,
+        txs: Vec<Vec<Transaction>>,
+    ) -> Vec<Block> {
+        let mut prev = prev;
+
+        txs.into_iter()
+            .enumerate()
+            .map(|(i, txs)| {
+                let block = block(prev, start_height + i as u32, txs);
+                prev = block.block_hash();
+                block
+            })
+            .collect()
+    }
+
+    pub(crate) fn block(prev: BlockHash, height: u32, txs: Vec<Transaction>) -> Block {
+        let mut txdata = vec![coinbase(height)];
+        txdata.extend(txs);
+
+        let mut block = Block {
+            header: Header {
+                version: Version::ONE,
+                prev_blockhash: prev,
+                merkle_root: TxMerkleNode::all_zeros(),
+                time: 1_700_000_000 + height,
+                bits: CompactTarget::from_consensus(0x207fffff),
+                nonce: 0,
+            },
+            txdata,
+        };
+
+        block.header.merkle_root = block.compute_merkle_root().unwrap();
+
+        block
+    }
+
+    pub(crate) fn coinbase(height: u32) -> Transaction {
+        Transaction {
+            version: 2,
+            lock_time: LockTime::ZERO,
+            input: vec![TxIn {
+                previous_output: OutPoint::null(),
+                script_sig: script::Builder::new().push_int(height.into()).into_script(),
+                sequence: Sequence::MAX,
+                witness: Witness::new(),
+            }],
+            output: vec![TxOut {
+                value: 50 * 100_000_000,
+                script_pubkey: address_script(u8::try_from(height % 256).unwrap()),
+            }],
+        }
+    }
+
+    /// A transaction spending `inputs` to `outputs`, with `runestone` in
+    /// front of the outputs if there is one.
+    pub(crate) fn tx(
+        inputs: &[OutPoint],
+        runestone: Option<Runestone>,
+        outputs: Vec<ScriptBuf>,
+    ) -> Transaction {
+        let mut output = Vec::new();
+
+        if let Some(runestone) = runestone {
+            output.push(TxOut {
+                value: 0,
+                script_pubkey: runestone.encipher(),
+            });
+        }
+
+        output.extend(outputs.into_iter().map(|script_pubkey| TxOut {
+            value: 10_000,
+            script_pubkey,
+        }));
+
+        Transaction {
+            version: 2,
+            lock_time: LockTime::ZERO,
+            input: inputs
+                .iter()
+                .map(|previous_output| TxIn {
+                    previous_output: *previous_output,
+                    script_sig: ScriptBuf::new(),
+                    sequence: Sequence::MAX,
+                    witness: Witness::new(),
+                })
+                .collect(),
+            output,
+        }
+    }
+
+    /// A P2PKH output script, distinct for every `n`.
+    pub(crate) fn address_script(n: u8) -> ScriptBuf {
+        ScriptBuf::new_p2pkh(&PubkeyHash::from_byte_array([n; 20]))
+    }
+
+    /// A taproot output script, distinct for every `n`. The output key is not
+    /// a valid point, which the runes protocol doesn't check.
+    pub(crate) fn taproot_script(n: u8) -> ScriptBuf {
+        script::Builder::new()
+            .push_opcode(opcodes::all::OP_PUSHNUM_1)
+            .push_slice([n; 32])
+            .into_script()
+    }
+
+    /// A script path spend witness whose tapscript commits to `rune`.
+    pub(crate) fn commitment_witness(rune: Rune) -> Witness {
+        let commitment = rune.commitment();
+
+        let tapscript = script::Builder::new()
+            .push_slice(<&script::PushBytes>::try_from(commitment.as_slice()).unwrap())
+            .push_opcode(opcodes::all::OP_DROP)
+            .push_opcode(opcodes::OP_TRUE)
+            .into_script();
+
+        let mut control_block = vec![0xc0];
+        control_block.extend([0; 32]);
+
+        Witness::from_slice(&[tapscript.into_bytes(), control_block])
+    }
+
+    /// An outpoint of a transaction that was never indexed, distinct for
+    /// every `n`.
+    pub(crate) fn outpoint(n: u8) -> OutPoint {
+        OutPoint {
+            txid: Txid::from_byte_array([n; 32]),
+            vout: 0,
+        }
+    }
+
+    pub(crate) fn address(n: u8) -> String {
+        Address::from_script(&address_script(n), Network::Regtest)
+            .unwrap()
+            .to_string()
+            .to_lowercase()
+    }
+
+    /// Chains recorded in a fresh fixture directory, and an in-memory
+    /// database to index them into. The directory is removed on drop.
+    pub(crate) struct Fixture {
+        pub(crate) dir: PathBuf,
+        pub(crate) source: FixtureSource,
+        pub(crate) conn: Connection,
+    }
+
+    impl Fixture {
+        /// Records `chains`, each a name, a start height and its blocks, and
+        /// opens the first.
+        pub(crate) fn record(chains: &[(&str, u32, &[Block])]) -> Fixture {
+            let dir = temp_dir();
+
+            for (name, start_height, blocks) in chains {
+                FixtureSource::record(&dir, name, *start_height, blocks).unwrap();
+            }
+
+            Fixture {
+                source: FixtureSource::open(&dir, chains[0].0).unwrap(),
+                conn: Connection::open_in_memory().unwrap(),
+                dir,
+            }
+        }
+
+        /// Records blocks holding `txs` from height 1 on, and indexes them.
+        pub(crate) async fn index(txs: Vec<Vec<Transaction>>) -> Fixture {
+            let mut fixture = Fixture::record(&[(
+                FixtureSource::DEFAULT_CHAIN,
+                1,
+                &chain(BlockHash::all_zeros(), 1, txs),
+            )]);
+
+            index(&fixture.source, &mut fixture.conn).await;
+
+            fixture
+        }
+    }
+
+    impl Drop for Fixture {
+        fn drop(&mut self) {
+            std::fs::remove_dir_all(&self.dir).ok();
+        }
+    }
+
+    pub(crate) async fn index(source: &FixtureSource, conn: &mut Connection) {
+        index_from(source, 1, conn).await.unwrap();
+    }
+
+    pub(crate) async fn index_from<S: BlockSource>(
+        source: &S,
+        start_height: u32,
+        conn: &mut Connection,
+    ) -> Result<(), Error> {
+        index_with_reorg_window(source, start_height, 20, conn).await
+    }
+
+    pub(crate) async fn index_with_reorg_window<S: BlockSource>(
+        source: &S,
+        start_height: u32,
+        reorg_window: u32,
+        conn: &mut Connection,
+    ) -> Result<(), Error> {
+        let (_shutdown_sender, shutdown) = watch::channel(false);
+
+        Indexer {
+            chain: Network::Regtest,
+            block_source: source.clone(),
+            start_height: Some(start_height),
+            prefetch_blocks: 4,
+            rpc_batch_size: 2,
+            reorg_window,
+            mempool: false,
+            shutdown,
+            conn,
+        }
+        .index_blocks()
+        .await
+    }
+
+    pub(crate) fn balance(conn: &mut Connection, n: u8, rune_id: RuneId) -> u128 {
+        SQLite {}
+            .get_address_balance_by_rune_id(conn, &address(n), &rune_id.to_string())
+            .unwrap()
+    }
+
+    pub(crate) fn outpoint_of(tx: &Transaction, vout: u32) -> OutPoint {
+        OutPoint {
+            txid: tx.txid(),
+            vout,
+        }
+    }
+
+    // etches a reserved rune with a premine of 1000 to address 10 at 1:1 and
+    // mints it twice, to addresses 11 and 12, in block 2. the third mint in
+    // block 2 exceeds the cap
+    pub(crate) fn etch_and_mint() -> (Transaction, Vec<Vec<Transaction>>) {
+        let etching = tx(
+            &[outpoint(1)],
+            Some(Runestone {
+                etching: Some(Etching {
+                    premine: Some(1000),
+                    terms: Some(Terms {
+                        amount: Some(100),
+                        cap: Some(2),
+                        ..Default::default()
+                    }),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            }),
+            vec![address_script(10)],
+        );
+
+        let mint = |input: u8, output: u8| {
+            tx(
+                &[outpoint(input)],
+                Some(Runestone {
+                    mint: Some(RuneId::new(1, 1).unwrap()),
+                    ..Default::default()
+                }),
+                vec![address_script(output)],
+            )
+        };
+
+        (
+            etching.clone(),
+            vec![vec![etching], vec![mint(2, 11), mint(3, 12), mint(4, 13)]],
+        )
+    }
+
+    pub(crate) fn transfer(etching: &Transaction, to: u8) -> Transaction {
+        tx(
+            &[outpoint_of(etching, 1)],
+            Some(Runestone {
+                edicts: vec![Edict {
+                    id: RuneId::new(1, 1).unwrap(),
+                    amount: 300,
+                    output: 2,
+                }],
+                ..Default::default()
+            }),
+            vec![address_script(10), address_script(to)],
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::testing::*;
+    use super::*;
+    use bitcoin::hashes::Hash;
+    use bitcoin::BlockHash;
+
+    #[tokio::test]
+    async fn replay_recorded_chains() {
+        let dir = temp_dir();
+
+        let main = chain(
+            BlockHash::all_zeros(),
+            10,
+            vec![
+                vec![],
+                vec![],
+                vec![tx(&[outpoint(1)], None, vec![address_script(1)])],
+            ],
+        );
+
+        let fork = chain(main[0].block_hash(), 11, vec![vec![], vec![], vec![]]);
+
+        FixtureSource::record(&dir, FixtureSource::DEFAULT_CHAIN, 10, &main).unwrap();
+        FixtureSource::record(
+            &dir,
+            "fork",
+            10,
+            &[vec![main[0].clone()], fork.clone()].concat(),
+        )
+        .unwrap();
+
+        let source = FixtureSource::open(&dir, FixtureSource::DEFAULT_CHAIN).unwrap();
+
+        assert_eq!(source.get_block_count().await.unwrap(), 12);
+        assert_eq!(source.get_block_hash_by_height(9).await.unwrap(), None);
+        assert_eq!(source.get_block_hash_by_height(13).await.unwrap(), None);
+        assert_eq!(
+            source.get_block_by_height(11).await.unwrap().block_hash(),
+            main[1].block_hash()
+        );
+
+        let header = source
+            .get_block_header_by_hash(&main[1].block_hash().to_string())
+            .await
+            .unwrap();
+        assert_eq!(header.height, 11);
+        assert_eq!(header.confirmations, 2);
+
+        let tx_id = main[2].txdata[1].txid().to_string();
+        let tx_info = source.get_transaction(&tx_id).await.unwrap().unwrap();
+        assert_eq!(tx_info.blockhash, Some(main[2].block_hash().to_string()));
+
+        source.switch_chain("fork").unwrap();
+
+        assert_eq!(source.get_block_count().await.unwrap(), 13);
+        assert_eq!(
+            source
+                .get_blocks_by_heights(10..14)
+                .await
+                .unwrap()
+                .iter()
+                .map(|block| block.block_hash())
+                .collect::<Vec<BlockHash>>(),
+            [
+                vec![main[0].block_hash()],
+                fork.iter().map(|block| block.block_hash()).collect()
+            ]
+            .concat()
+        );
+        assert!(source.get_transaction(&tx_id).await.unwrap().is_none());
+
+        assert!(source.switch_chain("missing").is_err());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
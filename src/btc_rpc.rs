@@ -1,11 +1,27 @@
-use anyhow::{Error, Ok};
-use bitcoin::{consensus::deserialize, Block, Transaction};
-use reqwest::Response;
+use crate::log_file::log;
+use anyhow::{anyhow, Context, Error};
+use bitcoin::{consensus::deserialize, Block, OutPoint, ScriptBuf, Transaction, Txid};
+use reqwest::StatusCode;
+use serde::de::DeserializeOwned;
 use serde::*;
+use std::collections::HashMap;
+use std::fmt::{self, Display, Formatter};
+use std::fs;
+use std::future::Future;
+use std::ops::Range;
+use std::path::PathBuf;
+use std::time::Duration;
 #[derive(Clone, Debug)]
 pub struct TransactionInfo {
-    pub raw: RawTxObj,
     pub data: Transaction,
+    pub blockhash: Option<String>,
+}
+
+/// The output spent by an input, and the height of the block that created it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Prevout {
+    pub script_pubkey: ScriptBuf,
+    pub height: u32,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -95,11 +111,178 @@ struct ScriptPubKey {
     type_field: String,
 }
 
+// the parts of `getblock` with verbosity 3 needed to look up prevouts
+#[derive(Deserialize, Debug)]
+struct RawPrevoutBlock {
+    tx: Vec<RawPrevoutTx>,
+}
+
+#[derive(Deserialize, Debug)]
+struct RawPrevoutTx {
+    vin: Vec<RawPrevoutVin>,
+}
+
+#[derive(Deserialize, Debug)]
+struct RawPrevoutVin {
+    txid: Option<String>,
+    vout: Option<u32>,
+    prevout: Option<RawPrevout>,
+}
+
+#[derive(Deserialize, Debug)]
+struct RawPrevout {
+    height: u32,
+    #[serde(rename = "scriptPubKey")]
+    script_pubkey: RawPrevoutScript,
+}
+
+#[derive(Deserialize, Debug)]
+struct RawPrevoutScript {
+    hex: String,
+}
+
+impl RawPrevoutBlock {
+    fn prevouts(self) -> Result<HashMap<OutPoint, Prevout>, RPCError> {
+        let mut prevouts = HashMap::new();
+
+        for input in self.tx.into_iter().flat_map(|tx| tx.vin) {
+            // coinbase inputs don't spend anything
+            let (Some(txid), Some(vout), Some(prevout)) = (input.txid, input.vout, input.prevout)
+            else {
+                continue;
+            };
+
+            let txid = txid
+                .parse::<Txid>()
+                .map_err(|err| RPCError::Decode(err.to_string()))?;
+
+            let script_pubkey = ScriptBuf::from_hex(&prevout.script_pubkey.hex)
+                .map_err(|err| RPCError::Decode(err.to_string()))?;
+
+            prevouts.insert(
+                OutPoint { txid, vout },
+                Prevout {
+                    script_pubkey,
+                    height: prevout.height,
+                },
+            );
+        }
+
+        Ok(prevouts)
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 pub struct RPCResponse<T> {
-    result: T,
-    error: Option<String>,
-    id: String,
+    result: Option<T>,
+    error: Option<RPCErrorObject>,
+    id: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct RPCErrorObject {
+    code: i64,
+    message: String,
+}
+
+// bitcoind error codes, see src/rpc/protocol.h
+pub const RPC_MISC_ERROR: i64 = -1;
+pub const RPC_INVALID_ADDRESS_OR_KEY: i64 = -5;
+pub const RPC_INVALID_PARAMETER: i64 = -8;
+pub const RPC_IN_WARMUP: i64 = -28;
+
+#[derive(Debug)]
+pub enum RPCError {
+    Credentials(String),
+    Transport(reqwest::Error),
+    Http { status: StatusCode, body: String },
+    Rpc { code: i64, message: String },
+    Decode(String),
+}
+
+impl RPCError {
+    /// Errors that are expected to go away by themselves, like the node being
+    /// unreachable, restarting or still loading its block index.
+    pub fn is_transient(&self) -> bool {
+        match self {
+            RPCError::Credentials(_) | RPCError::Transport(_) => true,
+            RPCError::Http { status, .. } => {
+                status.is_server_error() || *status == StatusCode::TOO_MANY_REQUESTS
+            }
+            RPCError::Rpc { code, .. } => *code == RPC_IN_WARMUP,
+            RPCError::Decode(_) => false,
+        }
+    }
+
+    pub fn is_not_found(&self) -> bool {
+        matches!(
+            self,
+            RPCError::Rpc {
+                code: RPC_INVALID_ADDRESS_OR_KEY,
+                ..
+            }
+        )
+    }
+
+    /// Data the node no longer has, like pruned blocks or their undo data.
+    pub fn is_unavailable(&self) -> bool {
+        matches!(
+            self,
+            RPCError::Rpc {
+                code: RPC_MISC_ERROR,
+                ..
+            }
+        )
+    }
+
+    pub fn is_out_of_range(&self) -> bool {
+        matches!(
+            self,
+            RPCError::Rpc {
+                code: RPC_INVALID_PARAMETER,
+                ..
+            }
+        )
+    }
+}
+
+impl Display for RPCError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            RPCError::Credentials(message) => write!(f, "rpc credentials error: {message}"),
+            RPCError::Transport(err) => write!(f, "rpc transport error: {err}"),
+            RPCError::Http { status, body } => write!(f, "rpc http error {status}: {body}"),
+            RPCError::Rpc { code, message } => write!(f, "rpc error {code}: {message}"),
+            RPCError::Decode(message) => write!(f, "rpc decode error: {message}"),
+        }
+    }
+}
+
+impl std::error::Error for RPCError {}
+
+#[derive(Debug, Clone, Copy)]
+pub struct RetryPolicy {
+    pub max_retries: u32,
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+}
+
+impl RetryPolicy {
+    fn backoff(&self, attempt: u32) -> Duration {
+        self.initial_backoff
+            .saturating_mul(2u32.saturating_pow(attempt))
+            .min(self.max_backoff)
+    }
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_retries: 10,
+            initial_backoff: Duration::from_millis(500),
+            max_backoff: Duration::from_secs(30),
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -127,55 +310,117 @@ impl RPCRequest {
             params: Vec::from(params),
         }
     }
+
+    fn with_id(self, id: usize) -> RPCRequest {
+        RPCRequest {
+            id: id.to_string(),
+            ..self
+        }
+    }
 }
 
-// TESTNET
-pub const BTC_RPC_URL: &str =
-    "https://powerful-cool-bush.btc-testnet.quiknode.pro/cf40fbe86ac4d435ce4799c8aae18c1dc65b96c8";
+#[derive(Debug, Clone)]
+pub enum Auth {
+    None,
+    UserPass(String, String),
+    CookieFile(PathBuf),
+}
 
-// MAINNET
-// pub const BTC_RPC_URL: &str =
-// "https://clean-light-violet.btc.quiknode.pro/c22960fe7aa43d1cfaf1e8a2b8cf60a1a430b7cb";
+impl Auth {
+    fn credentials(&self) -> Result<Option<(String, String)>, Error> {
+        match self {
+            Auth::None => Ok(None),
+            Auth::UserPass(user, password) => Ok(Some((user.clone(), password.clone()))),
+            // bitcoind writes a new cookie every time it starts, so it is read
+            // again on each request instead of being cached
+            Auth::CookieFile(path) => {
+                let cookie = fs::read_to_string(path).with_context(|| {
+                    format!("failed to read rpc cookie file `{}`", path.display())
+                })?;
+
+                let (user, password) = cookie
+                    .trim()
+                    .split_once(':')
+                    .ok_or_else(|| anyhow!("invalid rpc cookie file `{}`", path.display()))?;
+
+                Ok(Some((user.to_string(), password.to_string())))
+            }
+        }
+    }
+}
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Debug, Clone)]
 pub struct BTCRPC {
     pub url: String,
+    auth: Auth,
+    retry: RetryPolicy,
+    client: reqwest::Client,
 }
 
 impl BTCRPC {
-    pub async fn get_transaction(&self, id: &str) -> Result<TransactionInfo, Error> {
+    pub fn new(url: &str, auth: Auth, retry: RetryPolicy) -> BTCRPC {
+        BTCRPC {
+            url: url.to_string(),
+            auth,
+            retry,
+            client: reqwest::Client::new(),
+        }
+    }
+
+    pub async fn get_transaction(&self, id: &str) -> Result<TransactionInfo, RPCError> {
         let raw = self.get_raw_transaction(id).await?;
-        let hex = hex::decode(&raw.hex).unwrap();
 
-        let data: Transaction = deserialize(&hex).unwrap();
+        let data: Transaction = decode_hex(&raw.hex)?;
 
-        let transaction_info = TransactionInfo { raw, data };
+        let transaction_info = TransactionInfo {
+            data,
+            blockhash: raw.blockhash,
+        };
 
         Ok(transaction_info)
     }
 
-    pub async fn get_raw_transaction(&self, id: &str) -> Result<RawTxObj, Error> {
-        let response: Response = self
-            .rpc_request(&RPCRequest::new(
-                "getrawtransaction",
-                &[RPCValue::Str(id.to_string()), RPCValue::Int(1)],
-            ))
+    /// Looks up several transactions with one batched round trip. Unknown
+    /// transactions are `None`.
+    pub async fn get_transactions(
+        &self,
+        ids: &[String],
+    ) -> Result<Vec<Option<TransactionInfo>>, RPCError> {
+        let raw_txs: Vec<Result<RawTxObj, RPCError>> = self
+            .call_batch_results(
+                ids.iter()
+                    .map(|id| {
+                        RPCRequest::new(
+                            "getrawtransaction",
+                            &[RPCValue::Str(id.to_string()), RPCValue::Int(1)],
+                        )
+                    })
+                    .collect(),
+            )
             .await?;
 
-        let result = response.json::<RPCResponse<RawTxObj>>().await?.result;
-        Ok(result)
+        raw_txs
+            .into_iter()
+            .map(|raw| match raw {
+                Ok(raw) => Ok(Some(TransactionInfo {
+                    data: decode_hex(&raw.hex)?,
+                    blockhash: raw.blockhash,
+                })),
+                Err(err) if err.is_not_found() => Ok(None),
+                Err(err) => Err(err),
+            })
+            .collect()
     }
 
-    pub async fn get_block_header_by_height(&self, block_height: u32) -> Result<RawBlockHeader, Error> {
-        let block_hash = self.get_block_hash_by_height(block_height).await?;
-
-        let block_header = self.get_block_header_by_hash(&block_hash).await?;
-
-        Ok(block_header)
+    pub async fn get_raw_transaction(&self, id: &str) -> Result<RawTxObj, RPCError> {
+        self.call(
+            "getrawtransaction",
+            &[RPCValue::Str(id.to_string()), RPCValue::Int(1)],
+        )
+        .await
     }
 
-
-    pub async fn get_block_by_height(&self, block_height: u32) -> Result<Block, Error> {
+    pub async fn get_block_by_height(&self, block_height: u32) -> Result<Block, RPCError> {
         let block_hash = self.get_block_hash_by_height(block_height).await?;
 
         let block = self.get_block_by_hash(&block_hash).await?;
@@ -183,77 +428,483 @@ impl BTCRPC {
         Ok(block)
     }
 
-    pub async fn get_block_hash_by_height(&self, block_height: u32) -> Result<String, Error> {
-        let response = self
-            .rpc_request(&RPCRequest::new(
-                "getblockhash",
-                &[RPCValue::Int(block_height as usize)],
-            ))
+    pub async fn get_block_hash_by_height(&self, block_height: u32) -> Result<String, RPCError> {
+        let block_hash: String = self
+            .call("getblockhash", &[RPCValue::Int(block_height as usize)])
             .await?;
 
-        let block_hash = response.json::<RPCResponse<String>>().await?.result.to_lowercase();
-        Ok(block_hash)
+        Ok(block_hash.to_lowercase())
     }
 
-    pub async fn get_block_by_hash(&self, block_hash: &str) -> Result<Block, Error> {
-        let response = self
-            .rpc_request(&RPCRequest::new(
+    pub async fn get_block_by_hash(&self, block_hash: &str) -> Result<Block, RPCError> {
+        let result: String = self
+            .call(
                 "getblock",
                 &[RPCValue::Str(block_hash.to_string()), RPCValue::Int(0)],
-            ))
+            )
             .await?;
 
-        let result = response.json::<RPCResponse<String>>().await?.result;
+        decode_hex(&result)
+    }
 
-        let hex = hex::decode(&result).unwrap();
+    /// Looks up the outputs spent by the inputs of a block in the block's undo
+    /// data, which unlike `getrawtransaction` works without -txindex.
+    pub async fn get_block_prevouts(
+        &self,
+        block_hash: &str,
+    ) -> Result<HashMap<OutPoint, Prevout>, RPCError> {
+        let block: RawPrevoutBlock = self
+            .call(
+                "getblock",
+                &[RPCValue::Str(block_hash.to_string()), RPCValue::Int(3)],
+            )
+            .await?;
 
-        let data: bitcoin::Block = deserialize(&hex).unwrap();
+        block.prevouts()
+    }
 
-        Ok(data)
+    pub async fn get_block_header_by_hash(
+        &self,
+        block_hash: &str,
+    ) -> Result<RawBlockHeader, RPCError> {
+        self.call(
+            "getblockheader",
+            &[RPCValue::Str(block_hash.to_string()), RPCValue::Bool(true)],
+        )
+        .await
     }
 
-    // pub async fn get_best_block_hash(&self) -> Result<String, Error> {
-    //     let response = self
-    //         .rpc_request(&RPCRequest::new("getbestblockhash", &[]))
-    //         .await?;
+    pub async fn get_block_count(&self) -> Result<u32, RPCError> {
+        self.call("getblockcount", &[]).await
+    }
 
-    //     let result = response.json::<RPCResponse<String>>().await?.result;
+    pub async fn get_raw_mempool(&self) -> Result<Vec<String>, RPCError> {
+        let tx_ids: Vec<String> = self.call("getrawmempool", &[]).await?;
 
-    //     Ok(result)
-    // }
+        Ok(tx_ids.iter().map(|tx_id| tx_id.to_lowercase()).collect())
+    }
 
-    pub async fn get_block_header_by_hash(&self, block_hash: &str) -> Result<RawBlockHeader, Error> {
-        let response = self
-            .rpc_request(&RPCRequest::new(
-                "getblockheader",
-                &[RPCValue::Str(block_hash.to_string()), RPCValue::Bool(true)],
-            ))
+    /// Fetches a range of blocks with two batched round trips, one for the
+    /// hashes and one for the blocks themselves.
+    pub async fn get_blocks_by_heights(&self, heights: Range<u32>) -> Result<Vec<Block>, RPCError> {
+        let block_hashes: Vec<String> = self
+            .call_batch(
+                heights
+                    .map(|height| {
+                        RPCRequest::new("getblockhash", &[RPCValue::Int(height as usize)])
+                    })
+                    .collect(),
+            )
             .await?;
-        let block_header = response.json::<RPCResponse<RawBlockHeader>>().await?.result;
 
-        Ok(block_header)
+        let blocks: Vec<String> = self
+            .call_batch(
+                block_hashes
+                    .into_iter()
+                    .map(|block_hash| {
+                        RPCRequest::new("getblock", &[RPCValue::Str(block_hash), RPCValue::Int(0)])
+                    })
+                    .collect(),
+            )
+            .await?;
+
+        blocks.iter().map(|block| decode_hex(block)).collect()
     }
 
-    pub async fn get_block_count(&self) -> Result<u32, Error> {
-        let response = self
-            .rpc_request(&RPCRequest::new("getblockcount", &[]))
-            .await?;
-        let block_height = response.json::<RPCResponse<u32>>().await?.result;
+    async fn call<T: DeserializeOwned>(
+        &self,
+        method: &str,
+        params: &[RPCValue],
+    ) -> Result<T, RPCError> {
+        let request = RPCRequest::new(method, params);
+
+        self.with_retry(method, || self.rpc_request(&request)).await
+    }
 
-        Ok(block_height)
+    /// Sends several requests as one JSON-RPC batch. Results are returned in
+    /// request order, the first failed request fails the whole batch.
+    async fn call_batch<T: DeserializeOwned>(
+        &self,
+        requests: Vec<RPCRequest>,
+    ) -> Result<Vec<T>, RPCError> {
+        self.call_batch_results(requests)
+            .await?
+            .into_iter()
+            .collect()
     }
 
-    async fn rpc_request(&self, request: &RPCRequest) -> Result<Response, Error> {
-        let client = reqwest::Client::new();
+    /// Like `call_batch`, but a failed request only fails its own result.
+    async fn call_batch_results<T: DeserializeOwned>(
+        &self,
+        requests: Vec<RPCRequest>,
+    ) -> Result<Vec<Result<T, RPCError>>, RPCError> {
+        let Some(first) = requests.first() else {
+            return Ok(Vec::new());
+        };
+
+        let description = format!("Batch of {} {}", requests.len(), first.method);
+
+        let requests = requests
+            .into_iter()
+            .enumerate()
+            .map(|(id, request)| request.with_id(id))
+            .collect::<Vec<RPCRequest>>();
+
+        self.with_retry(&description, || self.rpc_batch_request(&requests))
+            .await
+    }
 
-        let response = client
-            .post(BTC_RPC_URL)
+    /// Retries transient failures with exponential backoff.
+    async fn with_retry<T, F, Fut>(&self, description: &str, request: F) -> Result<T, RPCError>
+    where
+        F: Fn() -> Fut,
+        Fut: Future<Output = Result<T, RPCError>>,
+    {
+        let mut attempt = 0;
+
+        loop {
+            match request().await {
+                Err(err) if err.is_transient() && attempt < self.retry.max_retries => {
+                    let backoff = self.retry.backoff(attempt);
+                    attempt += 1;
+
+                    log(&format!(
+                        "{} failed: {}. Retrying in {} ms ({}/{})",
+                        description,
+                        err,
+                        backoff.as_millis(),
+                        attempt,
+                        self.retry.max_retries
+                    ))
+                    .ok();
+
+                    tokio::time::sleep(backoff).await;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    async fn rpc_batch_request<T: DeserializeOwned>(
+        &self,
+        requests: &[RPCRequest],
+    ) -> Result<Vec<Result<T, RPCError>>, RPCError> {
+        let (status, body) = self.post(serde_json::to_string(requests).unwrap()).await?;
+
+        let mut results = parse_batch_response(status, &body, requests.len())?;
+
+        // a node that is still warming up fails every request, the whole
+        // batch is retried
+        if let Some(index) = results
+            .iter()
+            .position(|result| matches!(result, Err(err) if err.is_transient()))
+        {
+            results.swap_remove(index)?;
+        }
+
+        Ok(results)
+    }
+
+    async fn rpc_request<T: DeserializeOwned>(&self, request: &RPCRequest) -> Result<T, RPCError> {
+        let (status, body) = self.post(serde_json::to_string(request).unwrap()).await?;
+
+        parse_response(status, &body)
+    }
+
+    async fn post(&self, body: String) -> Result<(StatusCode, String), RPCError> {
+        let mut builder = self
+            .client
+            .post(&self.url)
             .header("Content-Type", "application/json")
-            .body(serde_json::to_string(request).unwrap())
-            // .body(json_data.to_owned())
-            .send()
-            .await?;
+            .body(body);
+
+        if let Some((user, password)) = self
+            .auth
+            .credentials()
+            .map_err(|err| RPCError::Credentials(format!("{err:#}")))?
+        {
+            builder = builder.basic_auth(user, Some(password));
+        }
+
+        let response = builder.send().await.map_err(RPCError::Transport)?;
+
+        let status = response.status();
+
+        let body = response.text().await.map_err(RPCError::Transport)?;
+
+        Ok((status, body))
+    }
+}
+
+// bitcoind answers failed calls with a non-2xx status and the error object in
+// the body, so the body is parsed before looking at the status
+fn parse_response<T: DeserializeOwned>(status: StatusCode, body: &str) -> Result<T, RPCError> {
+    match serde_json::from_str::<RPCResponse<T>>(body) {
+        Ok(RPCResponse {
+            error: Some(RPCErrorObject { code, message }),
+            ..
+        }) => Err(RPCError::Rpc { code, message }),
+        Ok(RPCResponse {
+            result: Some(result),
+            ..
+        }) if status.is_success() => Ok(result),
+        _ if !status.is_success() => Err(RPCError::Http {
+            status,
+            body: body.to_string(),
+        }),
+        Ok(_) => Err(RPCError::Decode("response has no result".to_string())),
+        Err(err) => Err(RPCError::Decode(err.to_string())),
+    }
+}
+
+/// Results of a batch in request order. Requests fail one by one, only a
+/// response that isn't a batch fails them all.
+fn parse_batch_response<T: DeserializeOwned>(
+    status: StatusCode,
+    body: &str,
+    count: usize,
+) -> Result<Vec<Result<T, RPCError>>, RPCError> {
+    let Ok(responses) = serde_json::from_str::<Vec<RPCResponse<serde_json::Value>>>(body) else {
+        // a failed batch may be answered with a single error object
+        return match parse_response::<serde_json::Value>(status, body) {
+            Err(err) => Err(err),
+            Ok(_) => Err(RPCError::Decode("expected a batch response".to_string())),
+        };
+    };
+
+    let mut results: Vec<Option<Result<T, RPCError>>> = (0..count).map(|_| None).collect();
+
+    for response in responses {
+        let id = response
+            .id
+            .as_deref()
+            .and_then(|id| id.parse::<usize>().ok())
+            .filter(|id| *id < count)
+            .ok_or_else(|| {
+                RPCError::Decode(format!("unexpected batch response id: {:?}", response.id))
+            })?;
+
+        let result = match response {
+            RPCResponse {
+                error: Some(RPCErrorObject { code, message }),
+                ..
+            } => Err(RPCError::Rpc { code, message }),
+            RPCResponse {
+                result: Some(result),
+                ..
+            } => serde_json::from_value(result).map_err(|err| RPCError::Decode(err.to_string())),
+            _ => Err(RPCError::Decode("response has no result".to_string())),
+        };
+
+        results[id] = Some(result);
+    }
+
+    Ok(results
+        .into_iter()
+        .enumerate()
+        .map(|(id, result)| {
+            result.unwrap_or_else(|| {
+                Err(RPCError::Decode(format!(
+                    "missing batch response for id {id}"
+                )))
+            })
+        })
+        .collect())
+}
+
+fn decode_hex<T: bitcoin::consensus::Decodable>(hex: &str) -> Result<T, RPCError> {
+    let bytes = hex::decode(hex).map_err(|err| RPCError::Decode(err.to_string()))?;
+
+    deserialize(&bytes).map_err(|err| RPCError::Decode(err.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_successful_response() {
+        assert_eq!(
+            parse_response::<u32>(
+                StatusCode::OK,
+                r#"{"result":840000,"error":null,"id":"rm"}"#
+            )
+            .unwrap(),
+            840000
+        );
+    }
+
+    #[test]
+    fn parse_rpc_error_response() {
+        let err = parse_response::<String>(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            r#"{"result":null,"error":{"code":-5,"message":"Block not found"},"id":"rm"}"#,
+        )
+        .unwrap_err();
+
+        assert!(err.is_not_found());
+        assert!(!err.is_transient());
+
+        let err = parse_response::<u32>(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            r#"{"result":null,"error":{"code":-28,"message":"Loading block index..."},"id":"rm"}"#,
+        )
+        .unwrap_err();
+
+        assert!(matches!(
+            err,
+            RPCError::Rpc {
+                code: RPC_IN_WARMUP,
+                ..
+            }
+        ));
+        assert!(err.is_transient());
+    }
+
+    #[test]
+    fn parse_block_prevouts() {
+        let block = parse_response::<RawPrevoutBlock>(
+            StatusCode::OK,
+            r#"{"result":{"hash":"00","tx":[
+                {"txid":"aa","vin":[{"coinbase":"03","sequence":4294967295}]},
+                {"txid":"bb","vin":[{
+                    "txid":"1111111111111111111111111111111111111111111111111111111111111111",
+                    "vout":1,
+                    "prevout":{"generated":false,"height":839000,"value":0.0001,
+                        "scriptPubKey":{"hex":"51200000000000000000000000000000000000000000000000000000000000000000","type":"witness_v1_taproot"}}
+                }]}
+            ]},"error":null,"id":"rm"}"#,
+        )
+        .unwrap();
+
+        let prevouts = block.prevouts().unwrap();
+
+        assert_eq!(prevouts.len(), 1);
+
+        let prevout = &prevouts[&OutPoint {
+            txid: "1111111111111111111111111111111111111111111111111111111111111111"
+                .parse()
+                .unwrap(),
+            vout: 1,
+        }];
+
+        assert_eq!(prevout.height, 839000);
+        assert!(prevout.script_pubkey.is_v1_p2tr());
+    }
+
+    #[test]
+    fn parse_http_error_response() {
+        let err = parse_response::<u32>(StatusCode::UNAUTHORIZED, "").unwrap_err();
+        assert!(matches!(
+            err,
+            RPCError::Http {
+                status: StatusCode::UNAUTHORIZED,
+                ..
+            }
+        ));
+        assert!(!err.is_transient());
+
+        let err = parse_response::<u32>(StatusCode::SERVICE_UNAVAILABLE, "busy").unwrap_err();
+        assert!(err.is_transient());
+    }
+
+    #[test]
+    fn parse_malformed_response() {
+        let err =
+            parse_response::<u32>(StatusCode::OK, r#"{"result":"abc","error":null}"#).unwrap_err();
+        assert!(matches!(err, RPCError::Decode(_)));
+
+        let err =
+            parse_response::<u32>(StatusCode::OK, r#"{"result":null,"error":null}"#).unwrap_err();
+        assert!(matches!(err, RPCError::Decode(_)));
+    }
+
+    #[test]
+    fn parse_batch_response_in_request_order() {
+        assert_eq!(
+            parse_batch_response::<String>(
+                StatusCode::OK,
+                r#"[{"result":"b","error":null,"id":"1"},{"result":"a","error":null,"id":"0"}]"#,
+                2
+            )
+            .unwrap()
+            .into_iter()
+            .collect::<Result<Vec<String>, RPCError>>()
+            .unwrap(),
+            vec!["a".to_string(), "b".to_string()]
+        );
+    }
+
+    #[test]
+    fn parse_batch_response_errors() {
+        let results = parse_batch_response::<String>(
+            StatusCode::OK,
+            r#"[{"result":"a","error":null,"id":"0"},{"result":null,"error":{"code":-5,"message":"No such mempool or blockchain transaction"},"id":"1"}]"#,
+            2,
+        )
+        .unwrap();
+        assert_eq!(results[0].as_ref().unwrap(), "a");
+        assert!(results[1].as_ref().unwrap_err().is_not_found());
+
+        let results = parse_batch_response::<String>(
+            StatusCode::OK,
+            r#"[{"result":"a","error":null,"id":"0"}]"#,
+            2,
+        )
+        .unwrap();
+        assert!(matches!(results[1], Err(RPCError::Decode(_))));
+
+        let err = parse_batch_response::<String>(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            r#"{"result":null,"error":{"code":-28,"message":"Loading block index..."},"id":null}"#,
+            2,
+        )
+        .unwrap_err();
+        assert!(err.is_transient());
+    }
+
+    #[test]
+    fn malformed_hex_is_a_decode_error() {
+        assert!(matches!(
+            decode_hex::<Transaction>("zz"),
+            Err(RPCError::Decode(_))
+        ));
+        assert!(matches!(
+            decode_hex::<Transaction>("00"),
+            Err(RPCError::Decode(_))
+        ));
+    }
+
+    #[test]
+    fn backoff_is_exponential_and_capped() {
+        let retry = RetryPolicy {
+            max_retries: 10,
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(1),
+        };
+
+        assert_eq!(retry.backoff(0), Duration::from_millis(100));
+        assert_eq!(retry.backoff(1), Duration::from_millis(200));
+        assert_eq!(retry.backoff(3), Duration::from_millis(800));
+        assert_eq!(retry.backoff(4), Duration::from_secs(1));
+        assert_eq!(retry.backoff(40), Duration::from_secs(1));
+    }
+
+    #[test]
+    fn cookie_file_credentials() {
+        let path =
+            std::env::temp_dir().join(format!("runes-explorer-{}.cookie", std::process::id()));
+        fs::write(&path, "__cookie__:5f1c0a\n").unwrap();
+
+        assert_eq!(
+            Auth::CookieFile(path.clone()).credentials().unwrap(),
+            Some(("__cookie__".to_string(), "5f1c0a".to_string()))
+        );
+
+        fs::write(&path, "garbage").unwrap();
+        assert!(Auth::CookieFile(path.clone()).credentials().is_err());
 
-        Ok(response)
+        fs::remove_file(&path).unwrap();
+        assert!(Auth::CookieFile(path).credentials().is_err());
     }
 }
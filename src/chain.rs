@@ -0,0 +1,157 @@
+use bitcoin::consensus::serialize;
+use bitcoin::hashes::{sha256d, Hash};
+use bitcoin::network::constants::Network;
+use bitcoin::Script;
+use clap::ValueEnum;
+use serde::*;
+use std::fmt::{self, Display, Formatter};
+use std::path::PathBuf;
+
+#[derive(Default, ValueEnum, Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Chain {
+    #[default]
+    #[value(alias("main"))]
+    #[serde(alias = "main", alias = "bitcoin")]
+    Mainnet,
+    #[value(alias("test"))]
+    #[serde(alias = "test")]
+    Testnet,
+    Signet,
+    Regtest,
+}
+
+impl Chain {
+    pub fn network(self) -> Network {
+        match self {
+            Self::Mainnet => Network::Bitcoin,
+            Self::Testnet => Network::Testnet,
+            Self::Signet => Network::Signet,
+            Self::Regtest => Network::Regtest,
+        }
+    }
+
+    /// The magic bytes that prefix blocks in bitcoind's block files. Custom
+    /// signets derive theirs from the signet challenge.
+    pub fn magic(self, signet_challenge: Option<&Script>) -> [u8; 4] {
+        match (self, signet_challenge) {
+            (Self::Signet, Some(challenge)) => {
+                let hash = sha256d::Hash::hash(&serialize(challenge));
+                hash[..4].try_into().unwrap()
+            }
+            _ => self.network().magic().to_bytes(),
+        }
+    }
+
+    /// Identifies the chain an index belongs to, telling custom signets apart.
+    pub fn id(self, signet_challenge: Option<&Script>) -> String {
+        match (self, signet_challenge) {
+            (Self::Signet, Some(challenge)) => {
+                format!("signet-{}", hex::encode(self.magic(Some(challenge))))
+            }
+            _ => self.to_string(),
+        }
+    }
+
+    /// Where the index is stored by default, kept apart per chain like
+    /// bitcoind does with its data directories.
+    pub fn default_db_path(self, signet_challenge: Option<&Script>) -> PathBuf {
+        let dir = match self {
+            Self::Mainnet => return PathBuf::from("./indexer.db"),
+            Self::Testnet => "testnet3".to_string(),
+            Self::Signet | Self::Regtest => self.id(signet_challenge),
+        };
+
+        PathBuf::from(".").join(dir).join("indexer.db")
+    }
+
+    pub fn default_rpc_port(self) -> u16 {
+        match self {
+            Self::Mainnet => 8332,
+            Self::Testnet => 18332,
+            Self::Signet => 38332,
+            Self::Regtest => 18443,
+        }
+    }
+}
+
+impl Display for Chain {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                Self::Mainnet => "mainnet",
+                Self::Testnet => "testnet",
+                Self::Signet => "signet",
+                Self::Regtest => "regtest",
+            }
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bitcoin::ScriptBuf;
+
+    #[test]
+    fn magic() {
+        assert_eq!(Chain::Mainnet.magic(None), [0xf9, 0xbe, 0xb4, 0xd9]);
+        assert_eq!(Chain::Signet.magic(None), [0x0a, 0x03, 0xcf, 0x40]);
+
+        // the default signet's challenge produces the default signet's magic
+        let challenge = ScriptBuf::from_hex("512103ad5e0edad18cb1f0fc0d28a3d4f1f3e445640337489abb10404f2d1e086be430210359ef5021964fe22d6f8e05b2463c9540ce96883fe3b278760f048f5189f2e6c452ae").unwrap();
+        assert_eq!(
+            Chain::Signet.magic(Some(&challenge)),
+            [0x0a, 0x03, 0xcf, 0x40]
+        );
+
+        let challenge = ScriptBuf::from_hex("51").unwrap();
+        assert_ne!(
+            Chain::Signet.magic(Some(&challenge)),
+            Chain::Signet.magic(None)
+        );
+    }
+
+    #[test]
+    fn id() {
+        assert_eq!(Chain::Mainnet.id(None), "mainnet");
+        assert_eq!(Chain::Signet.id(None), "signet");
+
+        let challenge = ScriptBuf::from_hex("51").unwrap();
+        assert_eq!(
+            Chain::Signet.id(Some(&challenge)),
+            format!(
+                "signet-{}",
+                hex::encode(Chain::Signet.magic(Some(&challenge)))
+            )
+        );
+        assert_eq!(Chain::Regtest.id(Some(&challenge)), "regtest");
+    }
+
+    #[test]
+    fn default_db_path() {
+        assert_eq!(
+            Chain::Mainnet.default_db_path(None),
+            PathBuf::from("./indexer.db")
+        );
+        assert_eq!(
+            Chain::Testnet.default_db_path(None),
+            PathBuf::from("./testnet3/indexer.db")
+        );
+        assert_eq!(
+            Chain::Regtest.default_db_path(None),
+            PathBuf::from("./regtest/indexer.db")
+        );
+
+        let challenge = ScriptBuf::from_hex("51").unwrap();
+        assert_eq!(
+            Chain::Signet.default_db_path(Some(&challenge)),
+            PathBuf::from(format!(
+                "./signet-{}/indexer.db",
+                hex::encode(Chain::Signet.magic(Some(&challenge)))
+            ))
+        );
+    }
+}
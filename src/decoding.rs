@@ -0,0 +1,194 @@
+use super::runes::*;
+use utoipa::ToSchema;
+
+/// The runestone or cenotaph of a transaction, along with the integers it
+/// was read from.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, ToSchema)]
+pub struct DecodedTransaction {
+    pub tx_id: String,
+    /// `{"Runestone": {...}}` or `{"Cenotaph": {...}}`, missing if the
+    /// transaction has no runestone
+    #[schema(value_type = Option<Object>)]
+    pub artifact: Option<Artifact>,
+    /// Why the transaction is a cenotaph
+    pub cenotapth_message: Option<String>,
+    /// The payload as integers, missing if it couldn't be read
+    pub integers: Option<Vec<u128>>,
+    /// Tag/value pairs in the order they appear, up to the body
+    pub fields: Vec<DecodedField>,
+    /// Integers following the body tag, four per edict
+    pub body: Vec<u128>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, ToSchema)]
+pub struct DecodedField {
+    pub tag: u128,
+    /// Name of the tag, missing for unrecognized tags
+    pub name: Option<String>,
+    /// Missing when the payload ends after the tag
+    pub value: Option<u128>,
+}
+
+/// Deciphers `tx` without looking anything up, so it works for transactions
+/// the index hasn't seen, or from another chain.
+pub fn decode(tx: &Transaction) -> DecodedTransaction {
+    let artifact = Runestone::decipher(tx);
+
+    let integers = Runestone::integers_of(tx).and_then(|integers| integers.ok());
+
+    let mut fields = Vec::new();
+    let mut body = Vec::new();
+
+    if let Some(integers) = &integers {
+        for (i, pair) in integers.chunks(2).enumerate() {
+            if pair[0] == 0 {
+                body = integers[i * 2 + 1..].to_vec();
+                break;
+            }
+
+            fields.push(DecodedField {
+                tag: pair[0],
+                name: Runestone::tag_name(pair[0]).map(str::to_string),
+                value: pair.get(1).copied(),
+            });
+        }
+    }
+
+    DecodedTransaction {
+        tx_id: tx.txid().to_string().to_lowercase(),
+        cenotapth_message: match &artifact {
+            Some(Artifact::Cenotaph(cenotaph)) => cenotaph.flaw.map(|flaw| flaw.to_string()),
+            _ => None,
+        },
+        artifact,
+        integers,
+        fields,
+        body,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::block_source::fixture::testing::*;
+
+    fn with_payload(integers: &[u128]) -> Transaction {
+        let mut payload = Vec::new();
+
+        for integer in integers {
+            varint::encode_to_vec(*integer, &mut payload);
+        }
+
+        let mut tx = tx(&[outpoint(1)], None, vec![address_script(1)]);
+
+        tx.output.insert(
+            0,
+            TxOut {
+                value: 0,
+                script_pubkey: script::Builder::new()
+                    .push_opcode(opcodes::all::OP_RETURN)
+                    .push_opcode(Runestone::MAGIC_NUMBER)
+                    .push_slice(<&script::PushBytes>::try_from(payload.as_slice()).unwrap())
+                    .into_script(),
+            },
+        );
+
+        tx
+    }
+
+    #[test]
+    fn decode_runestone() {
+        let runestone = Runestone {
+            edicts: vec![Edict {
+                id: RuneId::new(840000, 3).unwrap(),
+                amount: 100,
+                output: 1,
+            }],
+            pointer: Some(1),
+            ..Default::default()
+        };
+
+        let decoded = decode(&tx(
+            &[outpoint(1)],
+            Some(runestone),
+            vec![address_script(1)],
+        ));
+
+        assert_eq!(decoded.integers, Some(vec![22, 1, 0, 840000, 3, 100, 1]));
+        assert_eq!(
+            decoded.fields,
+            vec![DecodedField {
+                tag: 22,
+                name: Some("pointer".to_string()),
+                value: Some(1),
+            }]
+        );
+        assert_eq!(decoded.body, vec![840000, 3, 100, 1]);
+        assert!(matches!(decoded.artifact, Some(Artifact::Runestone(_))));
+        assert_eq!(decoded.cenotapth_message, None);
+    }
+
+    #[test]
+    fn decode_unrecognized_odd_tag() {
+        let decoded = decode(&with_payload(&[9, 5, 22, 0]));
+
+        assert_eq!(
+            decoded.fields,
+            vec![
+                DecodedField {
+                    tag: 9,
+                    name: None,
+                    value: Some(5),
+                },
+                DecodedField {
+                    tag: 22,
+                    name: Some("pointer".to_string()),
+                    value: Some(0),
+                },
+            ]
+        );
+        assert_eq!(
+            decoded.artifact,
+            Some(Artifact::Runestone(Runestone {
+                pointer: Some(0),
+                ..Default::default()
+            }))
+        );
+    }
+
+    #[test]
+    fn decode_cenotaph() {
+        let decoded = decode(&with_payload(&[24, 1, 20, 1, 20, 2]));
+
+        assert_eq!(
+            decoded.artifact,
+            Some(Artifact::Cenotaph(Cenotaph {
+                flaw: Some(Flaw::UnrecognizedEvenTag),
+                mint: Some(RuneId::new(1, 2).unwrap()),
+                ..Default::default()
+            }))
+        );
+        assert_eq!(
+            decoded.cenotapth_message,
+            Some("unrecognized even tag".to_string())
+        );
+        assert_eq!(decoded.fields.len(), 3);
+
+        let decoded = decode(&with_payload(&[22]));
+
+        assert_eq!(decoded.fields[0].value, None);
+        assert_eq!(
+            decoded.cenotapth_message,
+            Some("field with missing value".to_string())
+        );
+    }
+
+    #[test]
+    fn decode_without_runestone() {
+        let decoded = decode(&tx(&[outpoint(1)], None, vec![address_script(1)]));
+
+        assert_eq!(decoded.artifact, None);
+        assert_eq!(decoded.integers, None);
+        assert!(decoded.fields.is_empty());
+    }
+}
@@ -0,0 +1,344 @@
+use super::runes::*;
+use utoipa::ToSchema;
+
+/// A runestone to encode, as accepted by `encode` and
+/// `POST /runestones/encode`.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, ToSchema)]
+#[serde(deny_unknown_fields)]
+pub struct RunestoneSpec {
+    #[serde(default)]
+    pub edicts: Vec<EdictSpec>,
+    pub etching: Option<EtchingSpec>,
+    /// Id of the rune to mint, e.g. `840000:3`
+    pub mint: Option<String>,
+    /// Output receiving the runes that edicts leave unallocated
+    pub pointer: Option<u32>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
+#[serde(deny_unknown_fields)]
+pub struct EdictSpec {
+    /// Id of the rune to transfer, `0:0` for the rune being etched. Edicts
+    /// are encoded, and so applied, in rune id order
+    pub rune_id: String,
+    /// Amount to transfer, 0 for all that's left
+    pub amount: u128,
+    pub output: u32,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, ToSchema)]
+#[serde(deny_unknown_fields)]
+pub struct EtchingSpec {
+    /// Name of the rune, with `•` or `.` as spacers. A reserved name is
+    /// assigned if missing
+    pub name: Option<String>,
+    pub divisibility: Option<u8>,
+    pub symbol: Option<char>,
+    pub premine: Option<u128>,
+    pub terms: Option<TermsSpec>,
+    #[serde(default)]
+    pub turbo: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, ToSchema)]
+#[serde(deny_unknown_fields)]
+pub struct TermsSpec {
+    /// Runes received by each mint
+    pub amount: Option<u128>,
+    /// Number of mints allowed
+    pub cap: Option<u128>,
+    pub height_start: Option<u64>,
+    pub height_end: Option<u64>,
+    pub offset_start: Option<u64>,
+    pub offset_end: Option<u64>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
+pub struct EncodedRunestone {
+    /// The OP_RETURN output script, hex encoded
+    pub script: String,
+    /// Size of the script in bytes
+    pub script_size: usize,
+    /// Size of the output, script and amount included, in vbytes
+    pub vbytes: usize,
+    /// Whether the script fits in the 83 bytes bitcoind relays by default
+    pub standard: bool,
+}
+
+/// Largest OP_RETURN script bitcoind relays with its default `-datacarriersize`.
+const MAX_STANDARD_SCRIPT_SIZE: usize = 83;
+
+/// Outputs of at least 9 bytes that fit in a block of 4M weight units.
+const MAX_OUTPUTS: usize = 111_111;
+
+impl RunestoneSpec {
+    /// Builds the runestone, rejecting descriptions that wouldn't encode to
+    /// a valid runestone.
+    pub fn to_runestone(&self) -> Result<Runestone> {
+        let etching = self
+            .etching
+            .as_ref()
+            .map(|etching| etching.to_etching())
+            .transpose()?;
+
+        let mut edicts = Vec::new();
+
+        for (index, edict) in self.edicts.iter().enumerate() {
+            let id = parse_rune_id(&edict.rune_id).with_context(|| format!("edict {}", index))?;
+
+            if id == RuneId::default() && etching.is_none() {
+                bail!(
+                    "edict {} refers to the etched rune, but nothing is etched",
+                    index
+                );
+            }
+
+            edicts.push(Edict {
+                id,
+                amount: edict.amount,
+                output: edict.output,
+            });
+        }
+
+        let mint = self
+            .mint
+            .as_deref()
+            .map(parse_rune_id)
+            .transpose()
+            .context("mint")?;
+
+        if mint == Some(RuneId::default()) {
+            bail!("mint: can't mint the rune being etched");
+        }
+
+        Ok(Runestone {
+            edicts,
+            etching,
+            mint,
+            pointer: self.pointer,
+        })
+    }
+}
+
+impl EtchingSpec {
+    pub fn to_etching(&self) -> Result<Etching> {
+        let spaced_rune = self
+            .name
+            .as_deref()
+            .map(|name| {
+                name.parse::<SpacedRune>()
+                    .with_context(|| format!("invalid rune name {}", name))
+            })
+            .transpose()?;
+
+        if let Some(spaced_rune) = spaced_rune {
+            ensure!(
+                spaced_rune.spacers <= Etching::MAX_SPACERS,
+                "rune name has too many spacers"
+            );
+        }
+
+        if let Some(divisibility) = self.divisibility {
+            ensure!(
+                divisibility <= Etching::MAX_DIVISIBILITY,
+                "divisibility {} is greater than the maximum of {}",
+                divisibility,
+                Etching::MAX_DIVISIBILITY
+            );
+        }
+
+        let etching = Etching {
+            divisibility: self.divisibility,
+            premine: self.premine,
+            rune: spaced_rune.map(|spaced_rune| spaced_rune.rune),
+            spacers: spaced_rune
+                .map(|spaced_rune| spaced_rune.spacers)
+                .filter(|spacers| *spacers > 0),
+            symbol: self.symbol,
+            terms: self.terms.as_ref().map(|terms| Terms {
+                amount: terms.amount,
+                cap: terms.cap,
+                height: (terms.height_start, terms.height_end),
+                offset: (terms.offset_start, terms.offset_end),
+            }),
+            turbo: self.turbo,
+        };
+
+        ensure!(etching.supply().is_some(), "supply overflows u128");
+
+        Ok(etching)
+    }
+}
+
+/// Parses a rune id, rejecting ids that can't appear in a runestone.
+pub fn parse_rune_id(s: &str) -> Result<RuneId> {
+    let id = s
+        .parse::<RuneId>()
+        .with_context(|| format!("invalid rune id {}", s))?;
+
+    RuneId::new(id.block, id.tx).ok_or_else(|| anyhow!("invalid rune id {}", s))
+}
+
+/// Encodes the runestone described by `spec` into an OP_RETURN script.
+pub fn encode(spec: &RunestoneSpec) -> Result<EncodedRunestone> {
+    let runestone = spec.to_runestone()?;
+
+    let script = runestone.encipher();
+
+    // decipher it back from a transaction with enough outputs for every edict
+    // and the pointer, so anything the encoder can't represent is caught
+    let outputs = runestone
+        .edicts
+        .iter()
+        .map(|edict| edict.output)
+        .chain(runestone.pointer)
+        .max()
+        .map_or(1, |output| {
+            usize::try_from(output)
+                .unwrap_or(usize::MAX)
+                .saturating_add(1)
+        });
+
+    ensure!(
+        outputs <= MAX_OUTPUTS,
+        "output {} can't exist in a transaction",
+        outputs - 1
+    );
+
+    let tx = Transaction {
+        version: 2,
+        lock_time: LockTime::ZERO,
+        input: Vec::new(),
+        output: std::iter::once(TxOut {
+            value: 0,
+            script_pubkey: script.clone(),
+        })
+        .chain((1..outputs).map(|_| TxOut {
+            value: 0,
+            script_pubkey: ScriptBuf::new(),
+        }))
+        .collect(),
+    };
+
+    match Runestone::decipher(&tx) {
+        Some(Artifact::Runestone(_)) => {}
+        Some(Artifact::Cenotaph(Cenotaph {
+            flaw: Some(flaw), ..
+        })) => bail!("runestone would be a cenotaph: {}", flaw),
+        _ => bail!("runestone would not decode"),
+    }
+
+    let vbytes = consensus::serialize(&tx.output[0]).len();
+
+    Ok(EncodedRunestone {
+        script: hex::encode(script.as_bytes()),
+        script_size: script.len(),
+        vbytes,
+        standard: script.len() <= MAX_STANDARD_SCRIPT_SIZE,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn spec(json: &str) -> RunestoneSpec {
+        serde_json::from_str(json).unwrap()
+    }
+
+    #[test]
+    fn encode_round_trips() {
+        let spec = spec(
+            r#"{
+                "edicts": [
+                    {"rune_id": "840000:3", "amount": 100, "output": 2},
+                    {"rune_id": "0:0", "amount": 0, "output": 1}
+                ],
+                "etching": {
+                    "name": "UNCOMMON•GOODS",
+                    "divisibility": 2,
+                    "symbol": "⧉",
+                    "premine": 1000,
+                    "terms": {"amount": 1, "cap": 10, "height_end": 1050000},
+                    "turbo": true
+                },
+                "mint": "840000:3",
+                "pointer": 1
+            }"#,
+        );
+
+        let encoded = encode(&spec).unwrap();
+
+        let tx = Transaction {
+            version: 2,
+            lock_time: LockTime::ZERO,
+            input: Vec::new(),
+            output: (0..3)
+                .map(|vout| TxOut {
+                    value: 0,
+                    script_pubkey: if vout == 0 {
+                        ScriptBuf::from_bytes(hex::decode(&encoded.script).unwrap())
+                    } else {
+                        ScriptBuf::new()
+                    },
+                })
+                .collect(),
+        };
+
+        let mut expected = spec.to_runestone().unwrap();
+        expected.edicts.sort_by_key(|edict| edict.id);
+
+        assert_eq!(
+            Runestone::decipher(&tx),
+            Some(Artifact::Runestone(expected))
+        );
+
+        let Some(Artifact::Runestone(runestone)) = Runestone::decipher(&tx) else {
+            unreachable!()
+        };
+
+        let etching = runestone.etching.unwrap();
+        assert_eq!(
+            SpacedRune::new(etching.rune.unwrap(), etching.spacers.unwrap()).to_string(),
+            "UNCOMMON•GOODS"
+        );
+        assert_eq!(etching.terms.unwrap().height, (None, Some(1050000)));
+
+        assert_eq!(encoded.script_size * 2, encoded.script.len());
+        assert_eq!(encoded.vbytes, encoded.script_size + 9);
+        assert!(encoded.standard);
+    }
+
+    #[test]
+    fn encode_rejects_invalid_runestones() {
+        #[track_caller]
+        fn case(json: &str, error: &str) {
+            assert_eq!(format!("{:#}", encode(&spec(json)).unwrap_err()), error);
+        }
+
+        case(
+            r#"{"etching": {"divisibility": 39}}"#,
+            "divisibility 39 is greater than the maximum of 38",
+        );
+        case(
+            r#"{"etching": {"name": "A•"}}"#,
+            "invalid rune name A•: trailing spacer",
+        );
+        case(
+            r#"{"etching": {"premine": 1, "terms": {"amount": 340282366920938463463374607431768211455, "cap": 2}}}"#,
+            "supply overflows u128",
+        );
+        case(
+            r#"{"edicts": [{"rune_id": "0:0", "amount": 1, "output": 1}]}"#,
+            "edict 0 refers to the etched rune, but nothing is etched",
+        );
+        case(
+            r#"{"edicts": [{"rune_id": "0:1", "amount": 1, "output": 1}]}"#,
+            "edict 0: invalid rune id 0:1",
+        );
+        case(
+            r#"{"mint": "840000"}"#,
+            "mint: invalid rune id 840000: missing separator",
+        );
+    }
+}
@@ -0,0 +1,462 @@
+use super::adapters::sqlite::SQLite;
+use super::encoding::EtchingSpec;
+use super::runes::*;
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use bitcoin::key::XOnlyPublicKey;
+use bitcoin::psbt::PartiallySignedTransaction;
+use bitcoin::secp256k1::Secp256k1;
+use bitcoin::taproot::{ControlBlock, LeafVersion, TaprootBuilder, TaprootSpendInfo};
+use rusqlite::Connection;
+use utoipa::ToSchema;
+
+/// Sats put in the output receiving the premine when the request doesn't
+/// say.
+const DEFAULT_POSTAGE: u64 = 546;
+
+/// A rune to etch, committed to by a tapscript spendable with
+/// `internal_key`.
+#[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
+#[serde(deny_unknown_fields)]
+pub struct CommitRequest {
+    /// The etching, its name is required
+    pub etching: EtchingSpec,
+    /// Hex encoded x-only public key signing the reveal
+    pub internal_key: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
+pub struct EtchingCommit {
+    pub name: String,
+    /// Taproot address the commit transaction pays to
+    pub commit_address: String,
+    /// Tapscript committing to the rune, hex encoded
+    pub tapscript: String,
+    /// Control block spending the tapscript, hex encoded
+    pub control_block: String,
+    /// Lowest height the reveal can confirm at, if the commit confirms in
+    /// the next block
+    pub earliest_reveal_height: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
+#[serde(deny_unknown_fields)]
+pub struct RevealRequest {
+    pub etching: EtchingSpec,
+    pub internal_key: String,
+    /// Output of the commit transaction paying to the commit address,
+    /// `txid:vout`
+    pub commit_outpoint: String,
+    /// Sats in the commit output, what the reveal pays in fees is what's
+    /// left of them after postage, which can't be nothing
+    pub commit_value: u64,
+    /// Address receiving the premine
+    pub destination: String,
+    /// Sats in the destination output, 546 if missing
+    pub postage: Option<u64>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
+pub struct EtchingReveal {
+    pub name: String,
+    /// Base64 encoded PSBT of the reveal, with the tapscript and control
+    /// block of its input
+    pub psbt: String,
+    /// The OP_RETURN script holding the etching, hex encoded
+    pub runestone: String,
+    pub commit_height: u64,
+}
+
+/// The tapscript, with the key signing the reveal followed by the
+/// commitment, and the taproot output it is a leaf of.
+fn commitment(
+    chain: Network,
+    rune: Rune,
+    internal_key: XOnlyPublicKey,
+) -> Result<(ScriptBuf, ControlBlock, TaprootSpendInfo, Address)> {
+    let commitment = script::PushBytesBuf::try_from(rune.commitment())?;
+
+    let tapscript = script::Builder::new()
+        .push_x_only_key(&internal_key)
+        .push_opcode(opcodes::all::OP_CHECKSIG)
+        .push_slice(commitment)
+        .push_opcode(opcodes::all::OP_DROP)
+        .into_script();
+
+    let spend_info = TaprootBuilder::new()
+        .add_leaf(0, tapscript.clone())?
+        .finalize(&Secp256k1::verification_only(), internal_key)
+        .map_err(|_| anyhow!("can't build the taproot output"))?;
+
+    let control_block = spend_info
+        .control_block(&(tapscript.clone(), LeafVersion::TapScript))
+        .ok_or_else(|| anyhow!("can't build the control block"))?;
+
+    let address = Address::p2tr_tweaked(spend_info.output_key(), chain);
+
+    Ok((tapscript, control_block, spend_info, address))
+}
+
+/// Checks that `etching` names a rune that can be etched at `height`.
+fn check_etching(
+    conn: &mut Connection,
+    chain: Network,
+    etching: &EtchingSpec,
+    height: u64,
+) -> Result<(Etching, SpacedRune)> {
+    let parsed = etching.to_etching()?;
+
+    let Some(rune) = parsed.rune else {
+        bail!("the etching needs a name to be committed to");
+    };
+
+    let spaced_rune = SpacedRune::new(rune, parsed.spacers.unwrap_or_default());
+
+    let minimum = Rune::minimum_at_height(chain, Height(u32::try_from(height)?));
+
+    ensure!(
+        rune >= minimum,
+        "{} isn't unlocked at block {}, the minimum is {}",
+        spaced_rune,
+        height,
+        minimum
+    );
+    ensure!(!rune.is_reserved(), "{} is reserved", spaced_rune);
+
+    let database = SQLite {};
+
+    ensure!(
+        database
+            .get_rune_by_raw_name(conn, &rune.to_string())?
+            .is_none(),
+        "{} is already etched",
+        spaced_rune
+    );
+
+    let pending = database
+        .get_pending_etchings(conn)?
+        .into_iter()
+        .any(|etching| {
+            etching
+                .name
+                .parse::<SpacedRune>()
+                .is_ok_and(|pending| pending.rune == rune)
+        });
+
+    ensure!(
+        !pending,
+        "{} is being etched by a transaction in the mempool",
+        spaced_rune
+    );
+
+    Ok((parsed, spaced_rune))
+}
+
+fn parse_internal_key(internal_key: &str) -> Result<XOnlyPublicKey> {
+    internal_key
+        .parse::<XOnlyPublicKey>()
+        .with_context(|| format!("invalid internal key {}", internal_key))
+}
+
+fn next_height(conn: &mut Connection) -> Result<u64> {
+    Ok(SQLite {}
+        .get_latest_block(conn)?
+        .map(|block| block.height + 1)
+        .unwrap_or_default())
+}
+
+/// Builds the output the commit transaction pays to. The reveal spends it
+/// once the commit has `Runestone::COMMIT_CONFIRMATIONS` confirmations.
+pub fn commit(
+    conn: &mut Connection,
+    chain: Network,
+    request: &CommitRequest,
+) -> Result<EtchingCommit> {
+    let internal_key = parse_internal_key(&request.internal_key)?;
+
+    // the commit confirms in the next block at the earliest
+    let earliest_reveal_height =
+        next_height(conn)? + u64::from(Runestone::COMMIT_CONFIRMATIONS) - 1;
+
+    let (_, spaced_rune) = check_etching(conn, chain, &request.etching, earliest_reveal_height)?;
+
+    let (tapscript, control_block, _, address) = commitment(chain, spaced_rune.rune, internal_key)?;
+
+    Ok(EtchingCommit {
+        name: spaced_rune.to_string(),
+        commit_address: address.to_string(),
+        tapscript: hex::encode(tapscript.as_bytes()),
+        control_block: hex::encode(control_block.serialize()),
+        earliest_reveal_height,
+    })
+}
+
+/// Builds the reveal transaction, once the commit output has enough
+/// confirmations for the reveal to confirm in the next block.
+pub fn reveal(
+    conn: &mut Connection,
+    chain: Network,
+    request: &RevealRequest,
+) -> Result<EtchingReveal> {
+    let internal_key = parse_internal_key(&request.internal_key)?;
+
+    let commit_outpoint = request
+        .commit_outpoint
+        .parse::<OutPoint>()
+        .with_context(|| format!("invalid outpoint {}", request.commit_outpoint))?;
+
+    let destination = request
+        .destination
+        .parse::<Address<NetworkUnchecked>>()
+        .with_context(|| format!("invalid address {}", request.destination))?
+        .require_network(chain)
+        .with_context(|| format!("address {} is not for {}", request.destination, chain))?;
+
+    let height = next_height(conn)?;
+
+    let (etching, spaced_rune) = check_etching(conn, chain, &request.etching, height)?;
+
+    let Some(commit_height) = SQLite {}.get_taproot_txo_block_height(
+        conn,
+        &commit_outpoint.txid.to_string().to_lowercase(),
+        commit_outpoint.vout,
+    )?
+    else {
+        bail!(
+            "commit output {} isn't a confirmed taproot output in the index",
+            commit_outpoint
+        );
+    };
+
+    let confirmations = (height + 1).saturating_sub(commit_height);
+
+    ensure!(
+        confirmations >= u64::from(Runestone::COMMIT_CONFIRMATIONS),
+        "the commit would have {} confirmations in the next block, the reveal can confirm from block {}",
+        confirmations,
+        commit_height + u64::from(Runestone::COMMIT_CONFIRMATIONS) - 1
+    );
+
+    let (tapscript, control_block, spend_info, commit_address) =
+        commitment(chain, spaced_rune.rune, internal_key)?;
+
+    let postage = request.postage.unwrap_or(DEFAULT_POSTAGE);
+
+    ensure!(
+        request.commit_value > postage,
+        "commit value {} doesn't leave a fee after the postage of {}",
+        request.commit_value,
+        postage
+    );
+
+    let runestone = Runestone {
+        etching: Some(etching),
+        pointer: Some(1),
+        ..Default::default()
+    }
+    .encipher();
+
+    let tx = Transaction {
+        version: 2,
+        lock_time: LockTime::ZERO,
+        input: vec![TxIn {
+            previous_output: commit_outpoint,
+            script_sig: ScriptBuf::new(),
+            sequence: Sequence::ENABLE_RBF_NO_LOCKTIME,
+            witness: Witness::new(),
+        }],
+        output: vec![
+            TxOut {
+                value: 0,
+                script_pubkey: runestone.clone(),
+            },
+            TxOut {
+                value: postage,
+                script_pubkey: destination.script_pubkey(),
+            },
+        ],
+    };
+
+    let mut psbt = PartiallySignedTransaction::from_unsigned_tx(tx)?;
+
+    psbt.inputs[0].witness_utxo = Some(TxOut {
+        value: request.commit_value,
+        script_pubkey: commit_address.script_pubkey(),
+    });
+    psbt.inputs[0].tap_internal_key = Some(internal_key);
+    psbt.inputs[0].tap_merkle_root = spend_info.merkle_root();
+    psbt.inputs[0]
+        .tap_scripts
+        .insert(control_block, (tapscript, LeafVersion::TapScript));
+
+    Ok(EtchingReveal {
+        name: spaced_rune.to_string(),
+        psbt: BASE64.encode(psbt.serialize()),
+        runestone: hex::encode(runestone.as_bytes()),
+        commit_height,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::block_source::fixture::testing::*;
+    use crate::block_source::FixtureSource;
+    use bitcoin::secp256k1::{KeyPair, SecretKey};
+
+    #[tokio::test]
+    async fn commit_and_reveal_an_etching() {
+        let secp = Secp256k1::new();
+        let internal_key =
+            KeyPair::from_secret_key(&secp, &SecretKey::from_slice(&[1; 32]).unwrap())
+                .x_only_public_key()
+                .0
+                .to_string();
+
+        let etching = EtchingSpec {
+            name: Some("AAAAAAAAAAAAAAAA•B".to_string()),
+            premine: Some(1000),
+            ..Default::default()
+        };
+
+        let mut conn = Connection::open_in_memory().unwrap();
+
+        SQLite {}.init_tables(&mut conn).unwrap();
+
+        let commit_request = CommitRequest {
+            etching: etching.clone(),
+            internal_key: internal_key.clone(),
+        };
+
+        let etching_commit = commit(&mut conn, Network::Regtest, &commit_request).unwrap();
+
+        assert_eq!(etching_commit.name, "AAAAAAAAAAAAAAAA•B");
+        assert_eq!(etching_commit.earliest_reveal_height, 5);
+
+        let commit_script = etching_commit
+            .commit_address
+            .parse::<Address<NetworkUnchecked>>()
+            .unwrap()
+            .require_network(Network::Regtest)
+            .unwrap()
+            .script_pubkey();
+
+        let commit_tx = tx(&[outpoint(1)], None, vec![commit_script]);
+
+        let blocks = chain(
+            BlockHash::all_zeros(),
+            1,
+            vec![vec![commit_tx.clone()], vec![], vec![], vec![], vec![]],
+        );
+
+        let fixture = Fixture::record(&[
+            (FixtureSource::DEFAULT_CHAIN, 1, &blocks[..3]),
+            ("matured", 1, &blocks),
+        ]);
+
+        index(&fixture.source, &mut conn).await;
+
+        let reveal_request = RevealRequest {
+            etching: etching.clone(),
+            internal_key,
+            commit_outpoint: outpoint_of(&commit_tx, 0).to_string(),
+            commit_value: 10_000,
+            destination: Address::from_script(&address_script(10), Network::Regtest)
+                .unwrap()
+                .to_string(),
+            postage: None,
+        };
+
+        assert_eq!(
+            format!(
+                "{:#}",
+                reveal(&mut conn, Network::Regtest, &reveal_request).unwrap_err()
+            ),
+            "the commit would have 4 confirmations in the next block, the reveal can confirm from block 6"
+        );
+
+        fixture.source.switch_chain("matured").unwrap();
+
+        index(&fixture.source, &mut conn).await;
+
+        assert_eq!(
+            format!(
+                "{:#}",
+                reveal(
+                    &mut conn,
+                    Network::Regtest,
+                    &RevealRequest {
+                        postage: Some(10_000),
+                        ..reveal_request.clone()
+                    }
+                )
+                .unwrap_err()
+            ),
+            "commit value 10000 doesn't leave a fee after the postage of 10000"
+        );
+
+        let etching_reveal = reveal(&mut conn, Network::Regtest, &reveal_request).unwrap();
+
+        assert_eq!(etching_reveal.commit_height, 1);
+
+        let psbt =
+            PartiallySignedTransaction::deserialize(&BASE64.decode(&etching_reveal.psbt).unwrap())
+                .unwrap();
+
+        let (control_block, (tapscript, _)) = psbt.inputs[0].tap_scripts.iter().next().unwrap();
+
+        assert_eq!(hex::encode(tapscript.as_bytes()), etching_commit.tapscript);
+
+        // the indexer doesn't check signatures
+        let mut reveal_tx = psbt.unsigned_tx.clone();
+        reveal_tx.input[0].witness =
+            Witness::from_slice(&[vec![0; 64], tapscript.to_bytes(), control_block.serialize()]);
+
+        let mut revealed = blocks.clone();
+        revealed.extend(chain(
+            blocks.last().unwrap().block_hash(),
+            6,
+            vec![vec![reveal_tx]],
+        ));
+
+        FixtureSource::record(&fixture.dir, "revealed", 1, &revealed).unwrap();
+
+        let source = FixtureSource::open(&fixture.dir, "revealed").unwrap();
+
+        index(&source, &mut conn).await;
+
+        let rune_entry = SQLite {}
+            .get_rune_by_id(&mut conn, &RuneId::new(6, 1).unwrap().to_string())
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(rune_entry.name, "AAAAAAAAAAAAAAAA•B");
+        assert_eq!(balance(&mut conn, 10, RuneId::new(6, 1).unwrap()), 1000);
+
+        assert_eq!(
+            format!(
+                "{:#}",
+                commit(&mut conn, Network::Regtest, &commit_request).unwrap_err()
+            ),
+            "AAAAAAAAAAAAAAAA•B is already etched"
+        );
+
+        assert_eq!(
+            format!(
+                "{:#}",
+                commit(
+                    &mut conn,
+                    Network::Regtest,
+                    &CommitRequest {
+                        etching: EtchingSpec {
+                            name: Some("A".to_string()),
+                            ..Default::default()
+                        },
+                        ..commit_request
+                    }
+                )
+                .unwrap_err()
+            ),
+            "A isn't unlocked at block 12, the minimum is ZZMYNVSLLXMV"
+        );
+    }
+}
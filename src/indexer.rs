@@ -1,62 +1,185 @@
 use super::log_file::log;
-use super::{adapters::sqlite::SQLite, btc_rpc::BTCRPC, rune_updaters::RuneUpdater};
-use crate::adapters::db::Block;
+use super::{
+    adapters::sqlite::SQLite,
+    rune_updaters::{unknown_commit_outputs, RuneUpdater},
+};
+use crate::adapters::db::{Block, PendingState};
+use crate::block_source::BlockSource;
+use crate::btc_rpc::Prevout;
+use crate::mempool::Mempool;
+use crate::prefetcher::Prefetcher;
 use crate::reorg::Reorg;
-use crate::runes::Runestone;
-use anyhow::Error;
-use async_recursion::async_recursion;
+use crate::runes::{Rune, Runestone};
+use anyhow::{bail, ensure, Error};
+use bitcoin::blockdata::constants::genesis_block;
 use bitcoin::network::constants::Network;
+use bitcoin::OutPoint;
 use chrono::Utc;
 use rusqlite::Connection;
 use std::collections::HashMap;
+use std::time::{Duration, Instant};
+use tokio::sync::watch;
 
-pub struct Indexer<'a> {
+pub struct Indexer<'a, S: BlockSource> {
     pub chain: Network,
-    pub rpc_url: String,
+    pub block_source: S,
+    pub start_height: Option<u32>,
+    pub prefetch_blocks: usize,
+    pub rpc_batch_size: usize,
+    pub reorg_window: u32,
+    pub mempool: bool,
+    pub shutdown: watch::Receiver<bool>,
     // pub pool: Pool<SqliteConnectionManager>,
     pub conn: &'a mut Connection,
     // database: SQLite<'a>,
 }
 
-impl<'a> Indexer<'a> {
-    #[async_recursion]
+impl<'a, S: BlockSource> Indexer<'a, S> {
+    pub async fn follow(&mut self, poll_interval: Duration) -> Result<(), Error> {
+        log(&format!(
+            "Following chain tip, polling every {} seconds",
+            poll_interval.as_secs()
+        ))?;
+
+        // pending state left by a previous run is stale
+        SQLite {}.replace_mempool(self.conn, &PendingState::default())?;
+
+        let mut mempool = self
+            .mempool
+            .then(|| Mempool::new(self.chain, self.rpc_batch_size));
+
+        while !self.shutdown_requested() {
+            // errors are usually the node going away for a while, keep the
+            // service running and try again on the next poll
+            if let Err(err) = self.poll(&mut mempool).await {
+                log(&format!(
+                    "Indexing failed: {:#}. Retrying in {} seconds",
+                    err,
+                    poll_interval.as_secs()
+                ))?;
+            }
+
+            tokio::select! {
+                _ = tokio::time::sleep(poll_interval) => {}
+                changed = self.shutdown.changed() => if changed.is_err() {
+                    break;
+                }
+            }
+        }
+
+        log("Indexer stopped")?;
+
+        Ok(())
+    }
+
+    async fn poll(&mut self, mempool: &mut Option<Mempool>) -> Result<(), Error> {
+        self.index_new_blocks().await?;
+
+        if let Some(mempool) = mempool {
+            mempool.update(&self.block_source, self.conn).await?;
+        }
+
+        Ok(())
+    }
+
+    async fn index_new_blocks(&mut self) -> Result<(), Error> {
+        let database = SQLite {};
+
+        let indexed_height = database
+            .get_latest_block(self.conn)?
+            .map(|block| block.height);
+
+        let tip_height = u64::from(self.block_source.get_block_count().await?);
+
+        if indexed_height.is_none_or(|height| height < tip_height) {
+            self.index_blocks().await?;
+        }
+
+        Ok(())
+    }
+
+    fn shutdown_requested(&self) -> bool {
+        *self.shutdown.borrow()
+    }
+
     pub async fn index_blocks(&mut self) -> Result<(), Error> {
+        // after a reorg was rolled back, index the new chain from the common
+        // ancestor on
+        while self.index_blocks_until_reorg().await? {
+            log("Resuming indexing")?;
+        }
+
+        Ok(())
+    }
+
+    /// Indexes blocks up to the current tip. Returns whether it stopped early
+    /// because of a reorg.
+    async fn index_blocks_until_reorg(&mut self) -> Result<bool, Error> {
         log("Indexing blocks")?;
         // let conn = &pool.get().unwrap();
-        let mut database = SQLite {};
+        let database = SQLite {};
         database.init_tables(self.conn)?;
 
-        let btc_rpc = &BTCRPC {
-            url: self.rpc_url.clone(),
-        };
-
-        let halving_block_height: u32 = 2583205;
+        let block_source = &self.block_source.clone();
 
-        let end_block_height: u32 = btc_rpc.get_block_count().await?;
+        let end_block_height: u32 = block_source.get_block_count().await?;
         log(&format!("Current block height: {}", end_block_height))?;
 
         let start_block_height = if let Some(block) = database.get_latest_block(self.conn)? {
             log(&format!("Resuming from: {}", block.height + 1))?;
             u32::try_from(block.height + 1)?
         } else {
-            log(&format!(
-                "No blocks indexed yet, starting from the halving block: {}",
-                halving_block_height
-            ))?;
+            check_genesis_block(block_source, self.chain).await?;
+
+            match self.start_height {
+                Some(start_height) => {
+                    log(&format!(
+                        "No blocks indexed yet, starting from: {}",
+                        start_height
+                    ))?;
+
+                    start_height
+                }
+                None => {
+                    let first_rune_height = Rune::first_rune_height(self.chain);
 
-            halving_block_height
+                    log(&format!(
+                        "No blocks indexed yet, starting from the first rune height: {}",
+                        first_rune_height
+                    ))?;
+
+                    first_rune_height
+                }
+            }
         };
 
         if start_block_height > end_block_height {
             log("No new blocks to index")?;
-            return Ok(());
+            return Ok(false);
         }
 
         let mut reorg_detected = false;
 
+        let mut prefetcher = Prefetcher::start(
+            block_source.clone(),
+            start_block_height..=end_block_height,
+            self.prefetch_blocks,
+            self.rpc_batch_size,
+        );
+
+        let mut throughput = Throughput::new();
+
         for block_height in start_block_height..=end_block_height {
+            if self.shutdown_requested() {
+                log(&format!(
+                    "Shutdown requested, stopping before block: {}",
+                    block_height
+                ))?;
+                return Ok(false);
+            }
+
             let percentage = ((block_height - start_block_height) as f32
-                / (end_block_height - start_block_height) as f32)
+                / (end_block_height - start_block_height).max(1) as f32)
                 * 100.0;
 
             log(&format!(
@@ -68,7 +191,18 @@ impl<'a> Indexer<'a> {
 
             let start_block_fetch_time = Utc::now();
 
-            let block = btc_rpc.get_block_by_height(block_height).await?;
+            let Some(fetched) = prefetcher.next().await else {
+                bail!("block prefetcher stopped before block {}", block_height);
+            };
+
+            let (fetched_height, block) = fetched?;
+
+            ensure!(
+                fetched_height == block_height,
+                "block prefetcher returned block {} instead of {}",
+                fetched_height,
+                block_height
+            );
 
             let end_block_fetch_time = Utc::now();
             let artifact_tx_count = block
@@ -80,78 +214,1852 @@ impl<'a> Indexer<'a> {
             let total_tx_count = block.txdata.len();
 
             log(&format!(
-                "Block fetched in: {} seconds. Indexing Artifact txs: {} out of {}",
+                "Block received in: {} ms. Indexing Artifact txs: {} out of {}",
                 end_block_fetch_time
                     .signed_duration_since(start_block_fetch_time)
-                    .num_seconds(),
+                    .num_milliseconds(),
                 artifact_tx_count,
                 total_tx_count,
             ))?;
 
-            let mut reorg = Reorg {
-                database: database.clone(),
-                conn: self.conn,
-                rpc_url: self.rpc_url.clone(),
-            };
+            let database = SQLite {};
 
-            reorg_detected = reorg
-                .detect_and_handle_reorg(
-                    &block.header.prev_blockhash.to_string().to_lowercase(),
-                    block_height,
-                )
-                .await?
-                .is_some();
+            // the node is asked for everything the block needs before the
+            // write lock is taken, so readers aren't blocked by a slow node
+            reorg_detected = Reorg {
+                database,
+                conn: self.conn,
+                block_source,
+            }
+            .detect_and_handle_reorg(
+                &block.header.prev_blockhash.to_string().to_lowercase(),
+                block_height,
+            )
+            .await?
+            .is_some();
 
             if reorg_detected {
                 break;
             }
 
-            let mut rune_updater = RuneUpdater {
-                database,
-                conn: self.conn,
-                chain: self.chain,
-                burned: HashMap::new(),
-                block_height,
-                block_time: block.header.time,
-                btc_rpc,
-            };
+            let prevouts =
+                if !unknown_commit_outputs(&database, self.conn, &block.txdata)?.is_empty() {
+                    block_source
+                        .get_block_prevouts(&block.block_hash().to_string().to_lowercase())
+                        .await?
+                        .unwrap_or_default()
+                } else {
+                    HashMap::new()
+                };
+
+            // everything the block changes is committed together with its
+            // block record, so an interrupted block is indexed again from
+            // scratch on the next run
+            self.conn.execute_batch("BEGIN IMMEDIATE")?;
 
-            for (tx_index, tx) in block.txdata.iter().enumerate() {
-                // let tx_percentage = ((tx_index + 1) as f32 / total_tx_count as f32) * 100.0;
-                // log(&format!(
-                //     "{}% transactions indexed on block: {}",
-                //     format!("{:.1$}", tx_percentage, 2),
-                //     block_height
-                // ))?;
-
-                rune_updater
-                    .index_runes(
-                        u32::try_from(tx_index)?,
-                        tx,
-                        tx.txid().to_string().to_lowercase().as_str(),
-                    )
-                    .await?;
+            if let Err(err) = self.index_block(block_height, &block, prevouts) {
+                self.conn.execute_batch("ROLLBACK")?;
+                return Err(err);
             }
 
-            rune_updater.update()?;
+            self.conn.execute_batch("COMMIT")?;
 
-            database.insert_block(
-                self.conn,
-                Block {
-                    height: block_height.into(),
-                    hash: block.block_hash().to_string().to_lowercase(),
-                    timestamp: block.header.time,
-                },
-            )?;
+            throughput.record(total_tx_count);
         }
 
-        if reorg_detected {
-            log("Resuming indexing")?;
-            self.index_blocks().await?;
-        } else {
+        if !reorg_detected {
             log("Indexing completed")?;
         }
 
+        Ok(reorg_detected)
+    }
+
+    /// Indexes `block`, which builds on the last indexed block.
+    fn index_block(
+        &mut self,
+        block_height: u32,
+        block: &bitcoin::Block,
+        prevouts: HashMap<OutPoint, Prevout>,
+    ) -> Result<(), Error> {
+        let mut database = SQLite {};
+
+        let mut rune_updater = RuneUpdater {
+            database,
+            conn: self.conn,
+            chain: self.chain,
+            burned: HashMap::new(),
+            block_height,
+            block_time: block.header.time,
+            prevouts,
+        };
+
+        rune_updater.index_taproot_txos(&block.txdata)?;
+
+        for (tx_index, tx) in block.txdata.iter().enumerate() {
+            // let tx_percentage = ((tx_index + 1) as f32 / total_tx_count as f32) * 100.0;
+            // log(&format!(
+            //     "{}% transactions indexed on block: {}",
+            //     format!("{:.1$}", tx_percentage, 2),
+            //     block_height
+            // ))?;
+
+            rune_updater.index_runes(
+                u32::try_from(tx_index)?,
+                tx,
+                tx.txid().to_string().to_lowercase().as_str(),
+            )?;
+        }
+
+        rune_updater.update()?;
+
+        database.insert_block(
+            self.conn,
+            Block {
+                height: block_height.into(),
+                hash: block.block_hash().to_string().to_lowercase(),
+                timestamp: block.header.time,
+            },
+            self.reorg_window,
+        )?;
+
         Ok(())
     }
 }
+
+// catches a node on another chain before anything is written to the index
+async fn check_genesis_block<S: BlockSource>(
+    block_source: &S,
+    chain: Network,
+) -> Result<(), Error> {
+    let expected = genesis_block(chain).block_hash().to_string();
+
+    if let Some(genesis) = block_source.get_block_hash_by_height(0).await? {
+        ensure!(
+            genesis == expected,
+            "node is not on {}: expected genesis block {}, got {}",
+            chain,
+            expected,
+            genesis
+        );
+    }
+
+    Ok(())
+}
+
+/// Periodically logs how many blocks and transactions were indexed per second.
+struct Throughput {
+    since: Instant,
+    blocks: u64,
+    txs: u64,
+}
+
+impl Throughput {
+    const INTERVAL: Duration = Duration::from_secs(30);
+
+    fn new() -> Throughput {
+        Throughput {
+            since: Instant::now(),
+            blocks: 0,
+            txs: 0,
+        }
+    }
+
+    fn record(&mut self, tx_count: usize) {
+        self.blocks += 1;
+        self.txs += tx_count as u64;
+
+        let elapsed = self.since.elapsed();
+
+        if elapsed >= Self::INTERVAL {
+            let seconds = elapsed.as_secs_f64();
+
+            log(&format!(
+                "Throughput: {:.2} blocks/s, {:.0} txs/s over the last {} blocks",
+                self.blocks as f64 / seconds,
+                self.txs as f64 / seconds,
+                self.blocks
+            ))
+            .ok();
+
+            *self = Throughput::new();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::adapters::db::{
+        BurnTotal, EtchingRejectionReason, MintAttemptCounts, MintOutcome, RuneFlowKind,
+    };
+    use crate::allocation::BurnCause;
+    use crate::block_source::fixture::testing::*;
+    use crate::block_source::FixtureSource;
+    use crate::runes::{
+        opcodes, script, varint, Artifact, Edict, Etching, Height, OutPoint, Rune, RuneId,
+        Runestone, ScriptBuf, Terms, Transaction,
+    };
+    use bitcoin::hashes::Hash;
+    use bitcoin::BlockHash;
+
+    /// A node serving blocks from a fixture, that answers transaction and
+    /// prevout lookups as its `Lookups` say.
+    #[derive(Clone)]
+    struct Node(FixtureSource, Lookups);
+
+    #[derive(Clone, Copy)]
+    enum Lookups {
+        /// A node without -txindex: transactions aren't found, but the
+        /// prevouts of blocks are.
+        NoTxIndex,
+        /// A node that stops answering either.
+        Stalling,
+    }
+
+    impl BlockSource for Node {
+        async fn get_block_count(&self) -> Result<u32, Error> {
+            self.0.get_block_count().await
+        }
+
+        async fn get_block_hash_by_height(&self, height: u32) -> Result<Option<String>, Error> {
+            self.0.get_block_hash_by_height(height).await
+        }
+
+        async fn get_block_by_hash(&self, block_hash: &str) -> Result<bitcoin::Block, Error> {
+            self.0.get_block_by_hash(block_hash).await
+        }
+
+        async fn get_block_header_by_hash(
+            &self,
+            block_hash: &str,
+        ) -> Result<crate::btc_rpc::RawBlockHeader, Error> {
+            self.0.get_block_header_by_hash(block_hash).await
+        }
+
+        async fn get_transaction(
+            &self,
+            _tx_id: &str,
+        ) -> Result<Option<crate::btc_rpc::TransactionInfo>, Error> {
+            match self.1 {
+                Lookups::NoTxIndex => Ok(None),
+                Lookups::Stalling => std::future::pending().await,
+            }
+        }
+
+        async fn get_block_prevouts(
+            &self,
+            block_hash: &str,
+        ) -> Result<Option<HashMap<OutPoint, crate::btc_rpc::Prevout>>, Error> {
+            match self.1 {
+                Lookups::NoTxIndex => self.0.get_block_prevouts(block_hash).await,
+                Lookups::Stalling => std::future::pending().await,
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn index_etching_mints_and_transfers() {
+        let (etching, mut blocks) = etch_and_mint();
+        blocks.push(vec![transfer(&etching, 14)]);
+
+        let mut fixture = Fixture::index(blocks).await;
+        let conn = &mut fixture.conn;
+
+        let id = RuneId::new(1, 1).unwrap();
+
+        let rune_entry = SQLite {}
+            .get_rune_by_id(conn, &id.to_string())
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(rune_entry.raw_name, Rune::reserved(1, 1).to_string());
+        assert_eq!(rune_entry.premine, 1000);
+        assert_eq!(rune_entry.mint_count, 2);
+
+        assert_eq!(balance(conn, 10, id), 700);
+        assert_eq!(balance(conn, 11, id), 100);
+        assert_eq!(balance(conn, 12, id), 100);
+        assert_eq!(balance(conn, 13, id), 0);
+        assert_eq!(balance(conn, 14, id), 300);
+
+        assert_eq!(SQLite {}.get_latest_block(conn).unwrap().unwrap().height, 3);
+    }
+
+    // commits to a rune in block 1, reveals it too early in block 4 and
+    // after the commitment matured in block 6
+    fn named_etching() -> (Rune, Transaction, Fixture) {
+        let rune = "AAAAAAAAAAAAAAAA".parse::<Rune>().unwrap();
+
+        let commit = tx(&[outpoint(1)], None, vec![taproot_script(1)]);
+
+        let mut reveal = tx(
+            &[outpoint_of(&commit, 0)],
+            Some(Runestone {
+                etching: Some(Etching {
+                    rune: Some(rune),
+                    premine: Some(1000),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            }),
+            vec![address_script(10)],
+        );
+        reveal.input[0].witness = commitment_witness(rune);
+
+        let mut early_reveal = reveal.clone();
+        early_reveal.lock_time = bitcoin::absolute::LockTime::from_height(1).unwrap();
+
+        let fixture = Fixture::record(&[(
+            FixtureSource::DEFAULT_CHAIN,
+            1,
+            &chain(
+                BlockHash::all_zeros(),
+                1,
+                vec![
+                    vec![commit.clone()],
+                    vec![],
+                    vec![],
+                    vec![early_reveal],
+                    vec![],
+                    vec![reveal],
+                ],
+            ),
+        )]);
+
+        (rune, commit, fixture)
+    }
+
+    #[tokio::test]
+    async fn index_named_etching_after_commitment_matures() {
+        let (rune, commit, mut fixture) = named_etching();
+        let conn = &mut fixture.conn;
+
+        // the commitment is checked against the index, not the node
+        let source = Node(fixture.source.clone(), Lookups::NoTxIndex);
+
+        index_from(&source, 1, conn).await.unwrap();
+
+        let database = SQLite {};
+
+        assert!(database
+            .get_rune_by_id(conn, &RuneId::new(4, 1).unwrap().to_string())
+            .unwrap()
+            .is_none());
+
+        let rune_entry = database
+            .get_rune_by_raw_name(conn, &rune.to_string())
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(rune_entry.rune_id, RuneId::new(6, 1).unwrap().to_string());
+        assert_eq!(balance(conn, 10, RuneId::new(6, 1).unwrap()), 1000);
+
+        assert_eq!(
+            database
+                .get_taproot_txo_block_height(conn, &commit.txid().to_string(), 0)
+                .unwrap(),
+            Some(1)
+        );
+    }
+
+    #[tokio::test]
+    async fn record_etching_rejections() {
+        let rune = "AAAAAAAAAAAAAAAA".parse::<Rune>().unwrap();
+
+        let etching = |rune: Rune, input: OutPoint, witness: bool| {
+            let mut tx = tx(
+                &[input],
+                Some(Runestone {
+                    etching: Some(Etching {
+                        rune: Some(rune),
+                        ..Default::default()
+                    }),
+                    ..Default::default()
+                }),
+                vec![address_script(10)],
+            );
+
+            if witness {
+                tx.input[0].witness = commitment_witness(rune);
+            }
+
+            tx
+        };
+
+        let commits = (1..=3)
+            .map(|n| tx(&[outpoint(n)], None, vec![taproot_script(n)]))
+            .collect::<Vec<Transaction>>();
+
+        let too_short = etching("A".parse().unwrap(), outpoint(4), false);
+        let reserved = etching(Rune::reserved(0, 0), outpoint(5), false);
+        let missing = etching(rune, outpoint(6), false);
+        // reveals the commitment, but spends an output no block created
+        let unknown = etching(rune, outpoint(7), true);
+        let immature = etching(rune, outpoint_of(&commits[0], 0), true);
+        let reveal = etching(rune, outpoint_of(&commits[1], 0), true);
+        let duplicate = etching(rune, outpoint_of(&commits[2], 0), true);
+
+        let mut fixture = Fixture::index(vec![
+            commits,
+            vec![
+                too_short.clone(),
+                reserved.clone(),
+                missing.clone(),
+                unknown.clone(),
+            ],
+            vec![],
+            vec![immature.clone()],
+            vec![],
+            vec![reveal.clone()],
+            vec![duplicate.clone()],
+        ])
+        .await;
+        let conn = &mut fixture.conn;
+
+        let database = SQLite {};
+
+        let mut reason = |tx: &Transaction| {
+            database
+                .get_etching_rejection(conn, &tx.txid().to_string())
+                .unwrap()
+                .map(|rejection| rejection.reason)
+        };
+
+        assert_eq!(
+            reason(&too_short),
+            Some(EtchingRejectionReason::TooShort {
+                minimum: Rune::minimum_at_height(Network::Regtest, Height(2)).to_string(),
+            })
+        );
+        assert_eq!(reason(&reserved), Some(EtchingRejectionReason::Reserved));
+        assert_eq!(
+            reason(&missing),
+            Some(EtchingRejectionReason::MissingCommitment)
+        );
+        assert_eq!(
+            reason(&unknown),
+            Some(EtchingRejectionReason::MissingCommitment)
+        );
+        assert_eq!(
+            reason(&immature),
+            Some(EtchingRejectionReason::ImmatureCommitment { confirmations: 4 })
+        );
+        assert_eq!(reason(&reveal), None);
+        assert_eq!(
+            reason(&duplicate),
+            Some(EtchingRejectionReason::Duplicate {
+                rune_id: RuneId::new(6, 1).unwrap().to_string(),
+            })
+        );
+
+        // rejections in orphaned blocks are rolled back
+        SQLite {}.reorg_blocks(conn, 6, "").unwrap();
+
+        assert_eq!(
+            database
+                .get_etching_rejection(conn, &duplicate.txid().to_string())
+                .unwrap(),
+            None
+        );
+    }
+
+    #[tokio::test]
+    async fn record_mint_attempts() {
+        let (_, mut blocks) = etch_and_mint();
+
+        let mints = blocks[1].clone();
+
+        // a rune without terms, and an attempt to mint it
+        blocks.push(vec![tx(
+            &[outpoint(5)],
+            Some(Runestone {
+                etching: Some(Etching::default()),
+                ..Default::default()
+            }),
+            vec![address_script(14)],
+        )]);
+
+        let unmintable = tx(
+            &[outpoint(6)],
+            Some(Runestone {
+                mint: Some(RuneId::new(3, 1).unwrap()),
+                ..Default::default()
+            }),
+            vec![address_script(15)],
+        );
+
+        let missing = tx(
+            &[outpoint(7)],
+            Some(Runestone {
+                mint: Some(RuneId::new(9, 9).unwrap()),
+                ..Default::default()
+            }),
+            vec![address_script(16)],
+        );
+
+        blocks.push(vec![unmintable.clone(), missing.clone()]);
+
+        let mut fixture = Fixture::index(blocks).await;
+        let conn = &mut fixture.conn;
+
+        let database = SQLite {};
+
+        let id = RuneId::new(1, 1).unwrap().to_string();
+
+        let attempts = database.get_rune_mint_attempts(conn, &id, 0, 100).unwrap();
+
+        assert_eq!(
+            attempts
+                .iter()
+                .map(|attempt| (attempt.tx_id.clone(), attempt.outcome.clone()))
+                .collect::<Vec<(String, MintOutcome)>>(),
+            vec![
+                (
+                    mints[0].txid().to_string(),
+                    MintOutcome::Minted { amount: 100 }
+                ),
+                (
+                    mints[1].txid().to_string(),
+                    MintOutcome::Minted { amount: 100 }
+                ),
+                (mints[2].txid().to_string(), MintOutcome::Cap { cap: 2 }),
+            ]
+        );
+        assert_eq!(attempts[0].message, None);
+        assert_eq!(attempts[2].message.as_deref(), Some("limited to 2 mints"));
+
+        let page = database.get_rune_mint_attempts(conn, &id, 1, 1).unwrap();
+
+        assert_eq!(page, attempts[1..2]);
+
+        assert_eq!(
+            database.get_rune_mint_attempt_counts(conn, &id).unwrap(),
+            MintAttemptCounts {
+                minted: 2,
+                cap: 1,
+                ..Default::default()
+            }
+        );
+
+        let attempt = database
+            .get_transaction_mint_attempt(conn, &unmintable.txid().to_string())
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(attempt.rune_id, RuneId::new(3, 1).unwrap().to_string());
+        assert_eq!(attempt.block_height, 4);
+        assert_eq!(attempt.outcome, MintOutcome::Unmintable);
+        assert_eq!(attempt.message.as_deref(), Some("not mintable"));
+
+        let attempt = database
+            .get_transaction_mint_attempt(conn, &missing.txid().to_string())
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(attempt.rune_id, RuneId::new(9, 9).unwrap().to_string());
+        assert_eq!(attempt.outcome, MintOutcome::NotFound);
+        assert_eq!(attempt.message.as_deref(), Some("rune doesn't exist"));
+
+        assert_eq!(
+            database
+                .get_rune_mint_attempt_counts(conn, &RuneId::new(9, 9).unwrap().to_string())
+                .unwrap(),
+            MintAttemptCounts {
+                not_found: 1,
+                ..Default::default()
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn persist_decoded_runestones() {
+        let (etching, mut blocks) = etch_and_mint();
+
+        // a transfer with an unrecognized odd tag and an amount above u64
+        let mut payload = Vec::new();
+
+        for integer in [9, 5, 0, 1, 1, u128::from(u64::MAX) + 1, 1] {
+            varint::encode_to_vec(integer, &mut payload);
+        }
+
+        let transfer = tx(
+            &[outpoint_of(&etching, 1)],
+            None,
+            vec![
+                ScriptBuf::builder()
+                    .push_opcode(opcodes::all::OP_RETURN)
+                    .push_opcode(Runestone::MAGIC_NUMBER)
+                    .push_slice(<&script::PushBytes>::try_from(payload.as_slice()).unwrap())
+                    .into_script(),
+                address_script(14),
+            ],
+        );
+
+        // an etching sending part of its premine with an edict to 0:0
+        let self_transfer = tx(
+            &[outpoint(30)],
+            Some(Runestone {
+                edicts: vec![Edict {
+                    id: RuneId::default(),
+                    amount: 4,
+                    output: 2,
+                }],
+                etching: Some(Etching {
+                    premine: Some(10),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            }),
+            vec![address_script(15), address_script(16)],
+        );
+
+        blocks.push(vec![transfer.clone(), self_transfer.clone()]);
+
+        let mut fixture = Fixture::index(blocks).await;
+        let conn = &mut fixture.conn;
+
+        let database = SQLite {};
+
+        let decoded = database
+            .get_runestone(conn, &etching.txid().to_string())
+            .unwrap()
+            .unwrap();
+
+        let Some(Artifact::Runestone(runestone)) = decoded.artifact else {
+            panic!("expected a runestone");
+        };
+
+        assert_eq!(runestone.etching.unwrap().premine, Some(1000));
+        assert_eq!(runestone.etching.unwrap().terms.unwrap().amount, Some(100));
+
+        let decoded = database
+            .get_runestone(conn, &transfer.txid().to_string())
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(
+            decoded.artifact,
+            Some(Artifact::Runestone(Runestone {
+                edicts: vec![Edict {
+                    id: RuneId::new(1, 1).unwrap(),
+                    amount: u128::from(u64::MAX) + 1,
+                    output: 1,
+                }],
+                ..Default::default()
+            }))
+        );
+        assert_eq!(decoded.fields[0].tag, 9);
+        assert_eq!(decoded.fields[0].name, None);
+        assert_eq!(decoded.fields[0].value, Some(5));
+        assert_eq!(decoded.body, vec![1, 1, u128::from(u64::MAX) + 1, 1]);
+
+        let decoded = database
+            .get_runestone(conn, &self_transfer.txid().to_string())
+            .unwrap()
+            .unwrap();
+
+        let Some(Artifact::Runestone(runestone)) = decoded.artifact else {
+            panic!("expected a runestone");
+        };
+
+        assert_eq!(runestone.edicts[0].id, RuneId::new(3, 2).unwrap());
+        assert_eq!(decoded.body, vec![0, 0, 4, 2]);
+
+        assert!(database
+            .get_runestone(conn, &coinbase(3).txid().to_string())
+            .unwrap()
+            .is_none());
+
+        SQLite {}.reorg_blocks(conn, 2, "").unwrap();
+
+        assert!(database
+            .get_runestone(conn, &transfer.txid().to_string())
+            .unwrap()
+            .is_none());
+    }
+
+    #[tokio::test]
+    async fn record_rune_flows() {
+        let (etching, mut blocks) = etch_and_mint();
+
+        let mints = blocks[1].clone();
+
+        let transfer = transfer(&etching, 14);
+
+        let id = RuneId::new(1, 1).unwrap();
+
+        let op_return = tx(
+            &[outpoint_of(&transfer, 2)],
+            Some(Runestone {
+                edicts: vec![Edict {
+                    id,
+                    amount: 100,
+                    output: 0,
+                }],
+                pointer: Some(1),
+                ..Default::default()
+            }),
+            vec![address_script(15)],
+        );
+
+        let cenotaph = tx(
+            &[outpoint_of(&transfer, 1)],
+            Some(Runestone {
+                edicts: vec![Edict {
+                    id,
+                    amount: 1,
+                    output: 5,
+                }],
+                ..Default::default()
+            }),
+            vec![address_script(16)],
+        );
+
+        blocks.push(vec![transfer.clone()]);
+        blocks.push(vec![op_return.clone(), cenotaph.clone()]);
+
+        let mut fixture = Fixture::index(blocks).await;
+        let conn = &mut fixture.conn;
+
+        let mut flows = |tx: &Transaction| {
+            SQLite {}
+                .get_transaction_rune_flows(conn, &tx.txid().to_string())
+                .unwrap()
+                .into_iter()
+                .inspect(|flow| assert_eq!(flow.rune_id, id.to_string()))
+                .map(|flow| (flow.amount, flow.kind))
+                .collect::<Vec<(u128, RuneFlowKind)>>()
+        };
+
+        assert_eq!(
+            flows(&etching),
+            vec![
+                (1000, RuneFlowKind::Premine),
+                (1000, RuneFlowKind::DefaultOutput { output_index: 1 }),
+            ]
+        );
+        assert_eq!(
+            flows(&mints[0]),
+            vec![
+                (100, RuneFlowKind::Mint),
+                (100, RuneFlowKind::DefaultOutput { output_index: 1 }),
+            ]
+        );
+        assert_eq!(flows(&mints[2]), vec![]);
+        assert_eq!(
+            flows(&transfer),
+            vec![
+                (
+                    1000,
+                    RuneFlowKind::Input {
+                        input_index: 0,
+                        outpoint: format!("{}:1", etching.txid()),
+                    }
+                ),
+                (
+                    300,
+                    RuneFlowKind::Edict {
+                        edict_index: 0,
+                        output_index: 2,
+                    }
+                ),
+                (700, RuneFlowKind::DefaultOutput { output_index: 1 }),
+            ]
+        );
+        assert_eq!(
+            flows(&op_return)[1..],
+            vec![
+                (
+                    100,
+                    RuneFlowKind::Edict {
+                        edict_index: 0,
+                        output_index: 0,
+                    }
+                ),
+                (200, RuneFlowKind::Pointer { output_index: 1 }),
+                (
+                    100,
+                    RuneFlowKind::Burn {
+                        cause: BurnCause::OpReturn,
+                        output_index: Some(0),
+                    }
+                ),
+            ]
+        );
+        assert_eq!(
+            flows(&cenotaph)[1..],
+            vec![(
+                700,
+                RuneFlowKind::Burn {
+                    cause: BurnCause::Cenotaph,
+                    output_index: None,
+                }
+            )]
+        );
+    }
+
+    #[tokio::test]
+    async fn categorize_burns() {
+        let (etching, mut blocks) = etch_and_mint();
+
+        let transfer = transfer(&etching, 14);
+
+        let id = RuneId::new(1, 1).unwrap();
+
+        let op_return = tx(
+            &[outpoint_of(&transfer, 2)],
+            Some(Runestone {
+                edicts: vec![Edict {
+                    id,
+                    amount: 100,
+                    output: 0,
+                }],
+                ..Default::default()
+            }),
+            vec![address_script(15)],
+        );
+
+        let cenotaph = tx(
+            &[outpoint_of(&op_return, 1)],
+            Some(Runestone {
+                edicts: vec![Edict {
+                    id,
+                    amount: 1,
+                    output: 5,
+                }],
+                ..Default::default()
+            }),
+            vec![address_script(16)],
+        );
+
+        // only an OP_RETURN output, so the runes have nowhere to go
+        let no_output = tx(
+            &[outpoint_of(&transfer, 1)],
+            Some(Runestone::default()),
+            vec![],
+        );
+
+        blocks.push(vec![transfer]);
+        blocks.push(vec![op_return.clone(), cenotaph.clone(), no_output.clone()]);
+
+        let mut fixture = Fixture::index(blocks).await;
+        let conn = &mut fixture.conn;
+
+        let database = SQLite {};
+
+        let events = database
+            .get_rune_burn_events(conn, &id.to_string(), 0, 10)
+            .unwrap();
+
+        assert_eq!(
+            events
+                .iter()
+                .map(|event| (
+                    event.tx_id.clone(),
+                    event.amount,
+                    event.burn_cause,
+                    event.output_index
+                ))
+                .collect::<Vec<(String, u128, Option<BurnCause>, Option<u32>)>>(),
+            vec![
+                (
+                    no_output.txid().to_string(),
+                    700,
+                    Some(BurnCause::NoOutput),
+                    None
+                ),
+                (
+                    cenotaph.txid().to_string(),
+                    200,
+                    Some(BurnCause::Cenotaph),
+                    None
+                ),
+                (
+                    op_return.txid().to_string(),
+                    100,
+                    Some(BurnCause::OpReturn),
+                    Some(0)
+                ),
+            ]
+        );
+
+        assert_eq!(
+            database
+                .get_rune_burn_events(conn, &id.to_string(), 1, 1)
+                .unwrap()[0]
+                .tx_id,
+            cenotaph.txid().to_string()
+        );
+
+        assert_eq!(
+            database
+                .get_rune_burn_totals(conn, &id.to_string())
+                .unwrap(),
+            vec![
+                BurnTotal {
+                    burn_cause: Some(BurnCause::Cenotaph),
+                    amount: 200,
+                    count: 1,
+                },
+                BurnTotal {
+                    burn_cause: Some(BurnCause::NoOutput),
+                    amount: 700,
+                    count: 1,
+                },
+                BurnTotal {
+                    burn_cause: Some(BurnCause::OpReturn),
+                    amount: 100,
+                    count: 1,
+                },
+            ]
+        );
+
+        assert_eq!(
+            database
+                .get_rune_by_id(conn, &id.to_string())
+                .unwrap()
+                .unwrap()
+                .burned,
+            1000
+        );
+    }
+
+    #[tokio::test]
+    async fn commitments_created_before_the_index_are_looked_up_in_block_data() {
+        let (rune, _, mut fixture) = named_etching();
+
+        index_from(&fixture.source, 2, &mut fixture.conn)
+            .await
+            .unwrap();
+
+        assert!(SQLite {}
+            .get_rune_by_raw_name(&mut fixture.conn, &rune.to_string())
+            .unwrap()
+            .is_some());
+
+        // without -txindex the commit output is found in the undo data of
+        // the reveal's block
+        let mut conn = Connection::open_in_memory().unwrap();
+
+        index_from(
+            &Node(fixture.source.clone(), Lookups::NoTxIndex),
+            2,
+            &mut conn,
+        )
+        .await
+        .unwrap();
+
+        assert!(SQLite {}
+            .get_rune_by_raw_name(&mut conn, &rune.to_string())
+            .unwrap()
+            .is_some());
+    }
+
+    #[tokio::test]
+    async fn resume_after_kill_mid_block() {
+        let (etching, blocks) = etch_and_mint();
+
+        // the reveal commits to a rune in an output the index doesn't know,
+        // so indexing block 3 stalls looking it up on the node
+        let rune = "AAAAAAAAAAAAAAAA".parse::<Rune>().unwrap();
+        let mut reveal = tx(
+            &[outpoint(99)],
+            Some(Runestone {
+                etching: Some(Etching {
+                    rune: Some(rune),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            }),
+            vec![address_script(20)],
+        );
+        reveal.input[0].witness = commitment_witness(rune);
+
+        let stalling = chain(
+            BlockHash::all_zeros(),
+            1,
+            [blocks.clone(), vec![vec![transfer(&etching, 14), reveal]]].concat(),
+        );
+
+        let fixed = chain(
+            BlockHash::all_zeros(),
+            1,
+            [blocks, vec![vec![transfer(&etching, 14)]]].concat(),
+        );
+
+        let mut fixture = Fixture::record(&[("stalling", 1, &stalling), ("fixed", 1, &fixed)]);
+
+        let db_path = fixture.dir.join("index.db");
+
+        {
+            let mut conn = Connection::open(&db_path).unwrap();
+
+            let source = Node(fixture.source.clone(), Lookups::Stalling);
+
+            // dropping the indexer mid-block, and then the connection, leaves
+            // the database as killing the process would
+            let (indexed, locked) = tokio::join!(
+                tokio::time::timeout(
+                    Duration::from_millis(500),
+                    index_from(&source, 1, &mut conn)
+                ),
+                async {
+                    tokio::time::sleep(Duration::from_millis(200)).await;
+
+                    // the index isn't locked while waiting for the node
+                    let other = Connection::open(&db_path).unwrap();
+                    other.busy_timeout(Duration::ZERO).unwrap();
+                    other.execute_batch("BEGIN IMMEDIATE; COMMIT").is_err()
+                }
+            );
+
+            assert!(indexed.is_err());
+            assert!(!locked);
+        }
+
+        let mut conn = Connection::open(&db_path).unwrap();
+
+        let id = RuneId::new(1, 1).unwrap();
+
+        assert_eq!(
+            SQLite {}
+                .get_latest_block(&mut conn)
+                .unwrap()
+                .unwrap()
+                .height,
+            2
+        );
+        assert_eq!(balance(&mut conn, 10, id), 1000);
+        assert_eq!(balance(&mut conn, 14, id), 0);
+
+        fixture.source.switch_chain("fixed").unwrap();
+
+        index(&fixture.source, &mut conn).await;
+
+        let clean = &mut fixture.conn;
+
+        index(&fixture.source, clean).await;
+
+        for n in 10..=20 {
+            assert_eq!(
+                SQLite {}
+                    .get_address_balance_list(&mut conn, &address(n))
+                    .unwrap(),
+                SQLite {}
+                    .get_address_balance_list(clean, &address(n))
+                    .unwrap(),
+            );
+        }
+
+        let mint_count = |conn: &mut Connection| {
+            SQLite {}
+                .get_rune_by_id(conn, &id.to_string())
+                .unwrap()
+                .unwrap()
+                .mint_count
+        };
+
+        assert_eq!(mint_count(&mut conn), mint_count(clean));
+        assert_eq!(balance(&mut conn, 14, id), 300);
+    }
+
+    #[tokio::test]
+    async fn refuse_to_index_another_chain() {
+        let mut fixture = Fixture::record(&[(
+            FixtureSource::DEFAULT_CHAIN,
+            0,
+            &chain(BlockHash::all_zeros(), 0, vec![vec![], vec![]]),
+        )]);
+        let conn = &mut fixture.conn;
+
+        assert!(index_from(&fixture.source, 1, conn).await.is_err());
+        assert!(SQLite {}.get_latest_block(conn).unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn reorg_restores_rune_entry_aggregates() {
+        let (etching, blocks) = etch_and_mint();
+
+        let id = RuneId::new(1, 1).unwrap();
+
+        let burn = tx(
+            &[outpoint_of(&etching, 1)],
+            Some(Runestone {
+                edicts: vec![Edict {
+                    id,
+                    amount: 100,
+                    output: 0,
+                }],
+                ..Default::default()
+            }),
+            vec![address_script(10)],
+        );
+
+        let common = chain(
+            BlockHash::all_zeros(),
+            1,
+            vec![blocks[0].clone(), vec![blocks[1][0].clone()]],
+        );
+
+        // the orphaned block mints and burns, the winning chain transfers
+        let main = [
+            common.clone(),
+            vec![block(
+                common[1].block_hash(),
+                3,
+                vec![blocks[1][1].clone(), burn],
+            )],
+        ]
+        .concat();
+
+        let fork = [
+            common.clone(),
+            chain(
+                common[1].block_hash(),
+                3,
+                vec![vec![transfer(&etching, 15)], vec![]],
+            ),
+        ]
+        .concat();
+
+        let mut fixture =
+            Fixture::record(&[(FixtureSource::DEFAULT_CHAIN, 1, &main), ("fork", 1, &fork)]);
+        let conn = &mut fixture.conn;
+
+        index(&fixture.source, conn).await;
+
+        let rune_entry = |conn: &mut Connection| {
+            SQLite {}
+                .get_rune_by_id(conn, &id.to_string())
+                .unwrap()
+                .unwrap()
+        };
+
+        assert_eq!(rune_entry(conn).mint_count, 2);
+        assert_eq!(rune_entry(conn).burned, 100);
+
+        fixture.source.switch_chain("fork").unwrap();
+
+        index(&fixture.source, conn).await;
+
+        let mut clean = Connection::open_in_memory().unwrap();
+
+        index(&fixture.source, &mut clean).await;
+
+        assert_eq!(rune_entry(conn).mint_count, 1);
+        assert_eq!(rune_entry(conn).burned, 0);
+
+        assert_eq!(
+            serde_json::to_value(rune_entry(conn)).unwrap(),
+            serde_json::to_value(rune_entry(&mut clean)).unwrap()
+        );
+
+        for n in 10..=15 {
+            assert_eq!(
+                SQLite {}
+                    .get_address_balance_list(conn, &address(n))
+                    .unwrap(),
+                SQLite {}
+                    .get_address_balance_list(&mut clean, &address(n))
+                    .unwrap(),
+            );
+        }
+    }
+
+    fn assert_same_balances(conn: &mut Connection, clean: &mut Connection) {
+        for n in 10..=20 {
+            assert_eq!(
+                SQLite {}
+                    .get_address_balance_list(conn, &address(n))
+                    .unwrap(),
+                SQLite {}
+                    .get_address_balance_list(clean, &address(n))
+                    .unwrap(),
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn reorg_deeper_than_window_rolls_back_to_checkpoint() {
+        let (etching, _) = etch_and_mint();
+
+        let id = RuneId::new(998, 1).unwrap();
+
+        let transfer = |to: u8| {
+            tx(
+                &[outpoint_of(&etching, 1)],
+                Some(Runestone {
+                    edicts: vec![Edict {
+                        id,
+                        amount: 300,
+                        output: 2,
+                    }],
+                    ..Default::default()
+                }),
+                vec![address_script(10), address_script(to)],
+            )
+        };
+
+        let commit = tx(&[outpoint(1)], None, vec![taproot_script(1)]);
+
+        let common = chain(
+            BlockHash::all_zeros(),
+            998,
+            vec![vec![etching.clone()], vec![commit.clone()], vec![], vec![]],
+        );
+
+        // only the orphaned blocks spend the taproot output
+        let main = [
+            common.clone(),
+            chain(
+                common[3].block_hash(),
+                1002,
+                [
+                    vec![vec![
+                        transfer(14),
+                        tx(&[outpoint_of(&commit, 0)], None, vec![address_script(11)]),
+                    ]],
+                    vec![vec![]; 4],
+                ]
+                .concat(),
+            ),
+        ]
+        .concat();
+
+        let fork = [
+            common.clone(),
+            chain(
+                common[3].block_hash(),
+                1002,
+                [vec![vec![transfer(15)]], vec![vec![]; 6]].concat(),
+            ),
+        ]
+        .concat();
+
+        let mut fixture = Fixture::record(&[
+            (FixtureSource::DEFAULT_CHAIN, 998, &main),
+            ("fork", 998, &fork),
+        ]);
+        let conn = &mut fixture.conn;
+
+        index_with_reorg_window(&fixture.source, 998, 2, conn)
+            .await
+            .unwrap();
+
+        // the window plus the checkpoint at 1000
+        assert_eq!(
+            SQLite {}
+                .get_blocks_below(conn, 2000)
+                .unwrap()
+                .iter()
+                .map(|block| block.height)
+                .collect::<Vec<u64>>(),
+            [1006, 1005, 1000]
+        );
+
+        assert_eq!(balance(conn, 14, id), 300);
+
+        // the fork is 5 blocks deep, more than the window
+        fixture.source.switch_chain("fork").unwrap();
+
+        index_with_reorg_window(&fixture.source, 998, 2, conn)
+            .await
+            .unwrap();
+
+        let mut clean = Connection::open_in_memory().unwrap();
+
+        index_with_reorg_window(&fixture.source, 998, 2, &mut clean)
+            .await
+            .unwrap();
+
+        assert_eq!(balance(conn, 14, id), 0);
+        assert_eq!(balance(conn, 15, id), 300);
+        assert_same_balances(conn, &mut clean);
+
+        // spent taproot outputs are kept for rollbacks below the window
+        assert_eq!(
+            SQLite {}
+                .get_taproot_txo_block_height(conn, &commit.txid().to_string(), 0)
+                .unwrap(),
+            Some(999)
+        );
+
+        let tip = SQLite {}.get_latest_block(conn).unwrap().unwrap();
+        assert_eq!(tip.height, 1008);
+        assert_eq!(tip.hash, fork[10].block_hash().to_string());
+    }
+
+    #[tokio::test]
+    async fn reorg_below_every_retained_block_rebuilds_the_index() {
+        let (etching, blocks) = etch_and_mint();
+
+        let main = chain(
+            BlockHash::all_zeros(),
+            1,
+            [
+                blocks.clone(),
+                vec![vec![transfer(&etching, 14)]],
+                vec![vec![]; 3],
+            ]
+            .concat(),
+        );
+
+        // a different first block makes every block of the fork different
+        let fork = chain(
+            BlockHash::all_zeros(),
+            1,
+            [
+                vec![vec![
+                    etching.clone(),
+                    tx(&[outpoint(50)], None, vec![address_script(20)]),
+                ]],
+                blocks[1..].to_vec(),
+                vec![vec![transfer(&etching, 15)]],
+                vec![vec![]; 4],
+            ]
+            .concat(),
+        );
+
+        let mut fixture =
+            Fixture::record(&[(FixtureSource::DEFAULT_CHAIN, 1, &main), ("fork", 1, &fork)]);
+        let conn = &mut fixture.conn;
+
+        index_with_reorg_window(&fixture.source, 1, 2, conn)
+            .await
+            .unwrap();
+
+        fixture.source.switch_chain("fork").unwrap();
+
+        index_with_reorg_window(&fixture.source, 1, 2, conn)
+            .await
+            .unwrap();
+
+        let mut clean = Connection::open_in_memory().unwrap();
+
+        index_with_reorg_window(&fixture.source, 1, 2, &mut clean)
+            .await
+            .unwrap();
+
+        let id = RuneId::new(1, 1).unwrap();
+
+        assert_eq!(balance(conn, 14, id), 0);
+        assert_eq!(balance(conn, 15, id), 300);
+        assert_same_balances(conn, &mut clean);
+
+        assert_eq!(
+            SQLite {}
+                .get_rune_by_id(conn, &id.to_string())
+                .unwrap()
+                .unwrap()
+                .mint_count,
+            2
+        );
+
+        let tip = SQLite {}.get_latest_block(conn).unwrap().unwrap();
+        assert_eq!(tip.hash, fork.last().unwrap().block_hash().to_string());
+    }
+
+    #[tokio::test]
+    async fn index_reorg_onto_fork() {
+        let (etching, blocks) = etch_and_mint();
+
+        let main = chain(
+            BlockHash::all_zeros(),
+            1,
+            [blocks.clone(), vec![vec![transfer(&etching, 14)]]].concat(),
+        );
+
+        let fork = [
+            main[..2].to_vec(),
+            chain(
+                main[1].block_hash(),
+                3,
+                vec![vec![transfer(&etching, 15)], vec![]],
+            ),
+        ]
+        .concat();
+
+        let mut fixture =
+            Fixture::record(&[(FixtureSource::DEFAULT_CHAIN, 1, &main), ("fork", 1, &fork)]);
+        let conn = &mut fixture.conn;
+
+        index(&fixture.source, conn).await;
+
+        let id = RuneId::new(1, 1).unwrap();
+
+        assert_eq!(balance(conn, 14, id), 300);
+
+        fixture.source.switch_chain("fork").unwrap();
+
+        index(&fixture.source, conn).await;
+
+        assert_eq!(balance(conn, 10, id), 700);
+        assert_eq!(balance(conn, 14, id), 0);
+        assert_eq!(balance(conn, 15, id), 300);
+
+        let tip = SQLite {}.get_latest_block(conn).unwrap().unwrap();
+        assert_eq!(tip.height, 4);
+        assert_eq!(tip.hash, fork[3].block_hash().to_string());
+    }
+
+    #[tokio::test]
+    async fn reorg_archives_orphaned_rows() {
+        let (etching, blocks) = etch_and_mint();
+
+        let transfer = transfer(&etching, 14);
+
+        let orphaned_etching = tx(
+            &[outpoint(30)],
+            Some(Runestone {
+                etching: Some(Etching {
+                    premine: Some(5),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            }),
+            vec![address_script(16)],
+        );
+
+        let main = chain(
+            BlockHash::all_zeros(),
+            1,
+            [
+                blocks.clone(),
+                vec![vec![transfer.clone(), orphaned_etching.clone()]],
+            ]
+            .concat(),
+        );
+
+        // the transfer confirms again one block later, the etching doesn't
+        let fork = [
+            main[..2].to_vec(),
+            chain(
+                main[1].block_hash(),
+                3,
+                vec![vec![], vec![transfer.clone()]],
+            ),
+        ]
+        .concat();
+
+        let mut fixture =
+            Fixture::record(&[(FixtureSource::DEFAULT_CHAIN, 1, &main), ("fork", 1, &fork)]);
+        let conn = &mut fixture.conn;
+
+        index(&fixture.source, conn).await;
+
+        fixture.source.switch_chain("fork").unwrap();
+
+        index(&fixture.source, conn).await;
+
+        let database = SQLite {};
+
+        let reorg_events = database.get_reorg_events(conn).unwrap();
+        assert_eq!(reorg_events.len(), 1);
+
+        let reorg_event = &reorg_events[0];
+        assert_eq!(reorg_event.fork_height, Some(2));
+        assert_eq!(reorg_event.old_tip_height, 3);
+        assert_eq!(reorg_event.old_tip_hash, main[2].block_hash().to_string());
+        assert_eq!(reorg_event.new_tip_hash, fork[3].block_hash().to_string());
+        assert_eq!(reorg_event.depth, 1);
+
+        let orphaned_transactions = database
+            .get_orphaned_transactions(conn, reorg_event.id)
+            .unwrap()
+            .into_iter()
+            .map(|orphaned| {
+                assert_eq!(orphaned.block_height, 3);
+                (orphaned.tx_id, orphaned.reconfirmed_block_height)
+            })
+            .collect::<HashMap<String, Option<u64>>>();
+
+        assert_eq!(
+            orphaned_transactions,
+            HashMap::from([
+                (transfer.txid().to_string(), Some(4)),
+                (orphaned_etching.txid().to_string(), None),
+            ])
+        );
+
+        let orphaned_rune_entries = database
+            .get_orphaned_rune_entries(conn, reorg_event.id)
+            .unwrap();
+        assert_eq!(orphaned_rune_entries.len(), 1);
+        assert_eq!(orphaned_rune_entries[0].rune_id, "3:2");
+        assert_eq!(
+            orphaned_rune_entries[0].etching_tx_id,
+            orphaned_etching.txid().to_string()
+        );
+        assert!(database.get_rune_by_id(conn, "3:2").unwrap().is_none());
+
+        assert!(database
+            .get_orphaned_runes_txos(conn, reorg_event.id)
+            .unwrap()
+            .iter()
+            .any(|txo| txo.address == Some(address(14)) && txo.amount == 300));
+
+        assert!(database
+            .get_orphaned_rune_events(conn, reorg_event.id)
+            .unwrap()
+            .iter()
+            .any(|event| event.event_type == "etch" && event.rune_id == "3:2"));
+    }
+
+    #[tokio::test]
+    async fn mempool_tracks_pending_transfers_mints_and_etchings() {
+        let id = RuneId::new(1, 1).unwrap();
+
+        let etching = tx(
+            &[outpoint(1)],
+            Some(Runestone {
+                etching: Some(Etching {
+                    premine: Some(1000),
+                    terms: Some(Terms {
+                        amount: Some(50),
+                        cap: Some(1),
+                        ..Default::default()
+                    }),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            }),
+            vec![address_script(10)],
+        );
+
+        let transfer = transfer(&etching, 14);
+
+        // spends the output the transfer sends 300 runes to
+        let child = tx(&[outpoint_of(&transfer, 2)], None, vec![address_script(15)]);
+
+        let mint = |input: u8, output: u8| {
+            tx(
+                &[outpoint(input)],
+                Some(Runestone {
+                    mint: Some(id),
+                    ..Default::default()
+                }),
+                vec![address_script(output)],
+            )
+        };
+
+        let first_mint = mint(20, 16);
+        let second_mint = mint(21, 17);
+
+        let pending_etching = tx(
+            &[outpoint(22)],
+            Some(Runestone {
+                etching: Some(Etching {
+                    premine: Some(5),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            }),
+            vec![address_script(18)],
+        );
+
+        let blocks = chain(BlockHash::all_zeros(), 1, vec![vec![etching.clone()]]);
+
+        let mined = [
+            blocks.clone(),
+            chain(
+                blocks[0].block_hash(),
+                2,
+                vec![vec![transfer.clone(), first_mint.clone()]],
+            ),
+        ]
+        .concat();
+
+        let mut fixture = Fixture::record(&[
+            (FixtureSource::DEFAULT_CHAIN, 1, &blocks),
+            ("mined", 1, &mined),
+        ]);
+        let conn = &mut fixture.conn;
+        let source = &fixture.source;
+
+        FixtureSource::record_mempool(&fixture.dir, "mined", &[child.clone(), second_mint.clone()])
+            .unwrap();
+        FixtureSource::record_mempool(
+            &fixture.dir,
+            FixtureSource::DEFAULT_CHAIN,
+            &[
+                child.clone(),
+                transfer.clone(),
+                first_mint.clone(),
+                second_mint.clone(),
+                pending_etching.clone(),
+            ],
+        )
+        .unwrap();
+
+        index(source, conn).await;
+
+        let mut mempool = Mempool::new(Network::Regtest, 2);
+
+        mempool.update(source, conn).await.unwrap();
+
+        let database = SQLite {};
+
+        let pending_balance = |conn: &mut Connection, n: u8| {
+            database
+                .get_address_pending_balance_list(conn, &address(n))
+                .unwrap()
+                .get(&id.to_string())
+                .copied()
+                .unwrap_or_default()
+        };
+
+        assert_eq!(pending_balance(conn, 10), 700);
+        assert_eq!(pending_balance(conn, 14), 0);
+        assert_eq!(pending_balance(conn, 15), 300);
+        assert_eq!(pending_balance(conn, 16), 50);
+        assert_eq!(pending_balance(conn, 17), 0);
+
+        let pending_utxo = database
+            .get_address_pending_runes_utxo(conn, &address(15))
+            .unwrap();
+        assert_eq!(pending_utxo.len(), 1);
+        assert_eq!(pending_utxo[0].tx_id, child.txid().to_string());
+        assert_eq!(pending_utxo[0].amount, 300);
+
+        let pending_mints = database.get_pending_mints(conn, &id.to_string()).unwrap();
+        assert_eq!(pending_mints.len(), 1);
+        assert_eq!(pending_mints[0].tx_id, first_mint.txid().to_string());
+
+        let pending_etchings = database.get_pending_etchings(conn).unwrap();
+        assert_eq!(pending_etchings.len(), 1);
+        assert_eq!(
+            pending_etchings[0].tx_id,
+            pending_etching.txid().to_string()
+        );
+        assert_eq!(pending_etchings[0].premine, 5);
+        assert!(database
+            .get_address_pending_balance_list(conn, &address(18))
+            .unwrap()
+            .is_empty());
+
+        // nothing pending is written to the confirmed index
+        assert_eq!(balance(conn, 10, id), 1000);
+        assert_eq!(
+            database
+                .get_rune_by_id(conn, &id.to_string())
+                .unwrap()
+                .unwrap()
+                .mint_count,
+            0
+        );
+
+        // the transfer and the first mint confirm, the pending etching is
+        // dropped
+        source.switch_chain("mined").unwrap();
+
+        index(source, conn).await;
+
+        mempool.update(source, conn).await.unwrap();
+
+        assert_eq!(balance(conn, 14, id), 300);
+        assert_eq!(pending_balance(conn, 14), 0);
+        assert_eq!(pending_balance(conn, 15), 300);
+        assert_eq!(pending_balance(conn, 16), 50);
+        assert_eq!(pending_balance(conn, 17), 0);
+
+        // the cap was reached by the confirmed mint
+        assert!(database
+            .get_pending_mints(conn, &id.to_string())
+            .unwrap()
+            .is_empty());
+        assert!(database.get_pending_etchings(conn).unwrap().is_empty());
+
+        // the child is dropped
+        FixtureSource::record_mempool(&fixture.dir, "mined", &[]).unwrap();
+
+        mempool.update(source, conn).await.unwrap();
+
+        assert_eq!(pending_balance(conn, 14), 300);
+        assert_eq!(pending_balance(conn, 15), 0);
+        assert!(database
+            .get_address_pending_runes_utxo(conn, &address(15))
+            .unwrap()
+            .is_empty());
+    }
+
+    #[tokio::test]
+    async fn mempool_changes_between_blocks_update_the_pending_state() {
+        let id = RuneId::new(1, 1).unwrap();
+
+        let etching = tx(
+            &[outpoint(1)],
+            Some(Runestone {
+                etching: Some(Etching {
+                    premine: Some(1000),
+                    terms: Some(Terms {
+                        amount: Some(50),
+                        cap: Some(1),
+                        ..Default::default()
+                    }),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            }),
+            vec![address_script(10)],
+        );
+
+        let transfer = transfer(&etching, 14);
+
+        let child = tx(&[outpoint_of(&transfer, 2)], None, vec![address_script(15)]);
+
+        let mint = |input: u8, output: u8| {
+            tx(
+                &[outpoint(input)],
+                Some(Runestone {
+                    mint: Some(id),
+                    ..Default::default()
+                }),
+                vec![address_script(output)],
+            )
+        };
+
+        let first_mint = mint(20, 16);
+        let second_mint = mint(21, 17);
+
+        let blocks = chain(BlockHash::all_zeros(), 1, vec![vec![etching.clone()]]);
+
+        let mut fixture = Fixture::record(&[(FixtureSource::DEFAULT_CHAIN, 1, &blocks)]);
+        let conn = &mut fixture.conn;
+        let source = &fixture.source;
+
+        let record_mempool = |txs: &[Transaction]| {
+            FixtureSource::record_mempool(&fixture.dir, FixtureSource::DEFAULT_CHAIN, txs).unwrap()
+        };
+
+        record_mempool(&[transfer.clone(), first_mint.clone()]);
+
+        index(source, conn).await;
+
+        let mut mempool = Mempool::new(Network::Regtest, 2);
+
+        mempool.update(source, conn).await.unwrap();
+
+        let database = SQLite {};
+
+        let pending_balance = |conn: &mut Connection, n: u8| {
+            database
+                .get_address_pending_balance_list(conn, &address(n))
+                .unwrap()
+                .get(&id.to_string())
+                .copied()
+                .unwrap_or_default()
+        };
+
+        assert_eq!(pending_balance(conn, 14), 300);
+        assert_eq!(pending_balance(conn, 16), 50);
+
+        // the child spends the transfer and the second mint is over the cap
+        record_mempool(&[
+            transfer.clone(),
+            first_mint.clone(),
+            child.clone(),
+            second_mint.clone(),
+        ]);
+
+        mempool.update(source, conn).await.unwrap();
+
+        assert_eq!(pending_balance(conn, 10), 700);
+        assert_eq!(pending_balance(conn, 14), 0);
+        assert_eq!(pending_balance(conn, 15), 300);
+        assert_eq!(pending_balance(conn, 16), 50);
+        assert_eq!(pending_balance(conn, 17), 0);
+
+        // the second mint gets the cap once the first one is dropped
+        record_mempool(&[transfer.clone(), child.clone(), second_mint.clone()]);
+
+        mempool.update(source, conn).await.unwrap();
+
+        assert_eq!(pending_balance(conn, 15), 300);
+        assert_eq!(pending_balance(conn, 16), 0);
+        assert_eq!(pending_balance(conn, 17), 50);
+
+        let pending_mints = database.get_pending_mints(conn, &id.to_string()).unwrap();
+        assert_eq!(pending_mints.len(), 1);
+        assert_eq!(pending_mints[0].tx_id, second_mint.txid().to_string());
+
+        // the transfer is replaced, taking the child with it
+        record_mempool(std::slice::from_ref(&second_mint));
+
+        mempool.update(source, conn).await.unwrap();
+
+        assert_eq!(pending_balance(conn, 10), 1000);
+        assert_eq!(pending_balance(conn, 15), 0);
+        assert_eq!(pending_balance(conn, 17), 50);
+        assert!(database
+            .get_address_pending_runes_utxo(conn, &address(15))
+            .unwrap()
+            .is_empty());
+    }
+
+    #[tokio::test]
+    async fn mempool_transactions_that_fail_to_index_are_left_out() {
+        let (etching, blocks) = etch_and_mint();
+
+        let failing = transfer(&etching, 14);
+
+        let pending_etching = tx(
+            &[outpoint(22)],
+            Some(Runestone {
+                etching: Some(Etching {
+                    premine: Some(5),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            }),
+            vec![address_script(18)],
+        );
+
+        let mut fixture = Fixture::index(blocks).await;
+        let conn = &mut fixture.conn;
+
+        FixtureSource::record_mempool(
+            &fixture.dir,
+            FixtureSource::DEFAULT_CHAIN,
+            &[failing.clone(), pending_etching.clone()],
+        )
+        .unwrap();
+
+        conn.execute_batch(&format!(
+            "CREATE TRIGGER fail_transfer BEFORE INSERT ON transactions
+            WHEN NEW.tx_id = '{}'
+            BEGIN SELECT RAISE(ABORT, 'injected failure'); END",
+            failing.txid()
+        ))
+        .unwrap();
+
+        let mut mempool = Mempool::new(Network::Regtest, 2);
+
+        mempool.update(&fixture.source, conn).await.unwrap();
+
+        let database = SQLite {};
+
+        let pending_etchings = database.get_pending_etchings(conn).unwrap();
+        assert_eq!(pending_etchings.len(), 1);
+        assert_eq!(
+            pending_etchings[0].tx_id,
+            pending_etching.txid().to_string()
+        );
+        assert!(database
+            .get_address_pending_balance_list(conn, &address(14))
+            .unwrap()
+            .is_empty());
+
+        // a failed update doesn't leave the previous pending state behind
+        std::fs::write(
+            fixture
+                .dir
+                .join("mempool")
+                .join(format!("{}.json", FixtureSource::DEFAULT_CHAIN)),
+            "not json",
+        )
+        .unwrap();
+
+        assert!(mempool.update(&fixture.source, conn).await.is_err());
+        assert!(database.get_pending_etchings(conn).unwrap().is_empty());
+    }
+}
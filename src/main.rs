@@ -1,27 +1,49 @@
 mod adapters;
+mod allocation;
+mod block_source;
 mod btc_rpc;
+mod chain;
+mod decoding;
+mod encoding;
+mod etch;
 mod indexer;
 mod log_file;
 mod lot;
+mod mempool;
+mod mint;
+mod options;
+mod prefetcher;
 mod reorg;
 mod rune_updaters;
 mod runes;
 mod server;
+mod settings;
+mod simulation;
+mod transfer;
 mod utils;
 
-use actix_web::{get, web::Data, App, HttpResponse, HttpServer, Responder};
-use adapters::db;
-use anyhow::Error;
-use bitcoin::network::constants::Network;
+use actix_web::{dev::Server, get, web::Data, App, HttpResponse, HttpServer, Responder};
+use adapters::{db, sqlite::SQLite};
+use anyhow::{ensure, Context, Error};
+use block_source::{BlkFileSource, BlockSource};
+use btc_rpc::BTCRPC;
+use clap::Parser;
 use indexer::Indexer;
+use log_file::log;
+use options::{Options, Subcommand};
 use r2d2::Pool;
 use r2d2_sqlite::SqliteConnectionManager;
+use rusqlite::Connection;
 use server::{schemas, services};
+use settings::Settings;
+use std::time::Duration;
+use tokio::sync::watch;
 use utoipa::OpenApi;
 use utoipa_swagger_ui::SwaggerUi;
 
 struct AppState {
     pub pool: Pool<SqliteConnectionManager>,
+    pub chain: bitcoin::Network,
 }
 
 #[utoipa::path(
@@ -39,19 +61,253 @@ async fn hello() -> impl Responder {
 // #[tokio::main]
 #[actix_web::main]
 async fn main() -> Result<(), Error> {
-    let manager = SqliteConnectionManager::file("./indexer.db");
+    let options = Options::parse();
+
+    // these need neither the index nor the node
+    match &options.subcommand {
+        Subcommand::Encode { runestone } => return encode(runestone.as_deref()),
+        Subcommand::Decode { transaction } => return decode(transaction.as_deref()),
+        _ => {}
+    }
+
+    let settings = Settings::load(&options)?;
+
+    log(&format!(
+        "Using {} chain, database: {}",
+        settings.chain_id(),
+        settings.db_path.display()
+    ))?;
+
+    if let Some(dir) = settings.db_path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+
+    let manager = SqliteConnectionManager::file(&settings.db_path).with_init(|conn| {
+        // let the HTTP server read while the indexer is writing
+        conn.execute_batch("PRAGMA journal_mode = WAL; PRAGMA busy_timeout = 5000;")
+    });
     let pool = r2d2::Pool::builder()
         .build(manager)
         .expect("Error building a connection pool");
 
+    {
+        let mut conn = pool.get()?;
+        let database = SQLite {};
+
+        database.init_tables(&mut conn)?;
+        check_chain(&settings, &mut conn).await?;
+    }
+
+    match options.subcommand {
+        Subcommand::Index => index(&settings, &pool).await,
+        Subcommand::Serve => serve(&settings, pool).await,
+        Subcommand::Run => run(&settings, pool).await,
+        Subcommand::EtchCommit { request } => etch_commit(&settings, &pool, request.as_deref()),
+        Subcommand::EtchReveal { request } => etch_reveal(&settings, &pool, request.as_deref()),
+        Subcommand::Encode { .. } | Subcommand::Decode { .. } => unreachable!(),
+    }
+}
+
+fn arg_or_stdin(arg: Option<&str>) -> Result<String, Error> {
+    Ok(match arg {
+        Some(arg) => arg.to_string(),
+        None => std::io::read_to_string(std::io::stdin())?,
+    })
+}
+
+fn encode(runestone: Option<&str>) -> Result<(), Error> {
+    let json = arg_or_stdin(runestone)?;
+
+    let spec = serde_json::from_str(&json).context("invalid runestone description")?;
+
+    println!(
+        "{}",
+        serde_json::to_string_pretty(&encoding::encode(&spec)?)?
+    );
+
+    Ok(())
+}
+
+fn decode(transaction: Option<&str>) -> Result<(), Error> {
+    let tx = simulation::decode_transaction(&arg_or_stdin(transaction)?)?;
+
+    println!("{}", serde_json::to_string_pretty(&decoding::decode(&tx))?);
+
+    Ok(())
+}
+
+fn etch_commit(
+    settings: &Settings,
+    pool: &Pool<SqliteConnectionManager>,
+    request: Option<&str>,
+) -> Result<(), Error> {
+    let request =
+        serde_json::from_str(&arg_or_stdin(request)?).context("invalid commit request")?;
+
+    let mut conn = pool.get()?;
+
+    let commit = etch::commit(&mut conn, settings.chain.network(), &request)?;
+
+    println!("{}", serde_json::to_string_pretty(&commit)?);
+
+    Ok(())
+}
+
+fn etch_reveal(
+    settings: &Settings,
+    pool: &Pool<SqliteConnectionManager>,
+    request: Option<&str>,
+) -> Result<(), Error> {
+    let request =
+        serde_json::from_str(&arg_or_stdin(request)?).context("invalid reveal request")?;
+
+    let mut conn = pool.get()?;
+
+    let reveal = etch::reveal(&mut conn, settings.chain.network(), &request)?;
+
+    println!("{}", serde_json::to_string_pretty(&reveal)?);
+
+    Ok(())
+}
+
+// indexes created before the chain was recorded are only labeled with the
+// chain once their latest block was found on the node
+async fn check_chain(settings: &Settings, conn: &mut Connection) -> Result<(), Error> {
+    let mut database = SQLite {};
+
+    if database.get_metadata(conn, "chain")?.is_none() {
+        if let Some(block) = database.get_latest_block(conn)? {
+            let block_hash = BlockSource::get_block_hash_by_height(
+                &btc_rpc(settings)?,
+                u32::try_from(block.height)?,
+            )
+            .await
+            .context("can't check which chain the index belongs to")?;
+
+            ensure!(
+                block_hash.as_deref() == Some(block.hash.as_str()),
+                "database has no chain recorded and its block {} at height {} isn't on the {} chain of the node. Use a different db_path for each chain",
+                block.hash,
+                block.height,
+                settings.chain_id()
+            );
+
+            database.set_metadata(conn, "chain", &settings.chain_id())?;
+        }
+    }
+
+    database.check_chain(conn, &settings.chain_id())
+}
+
+fn btc_rpc(settings: &Settings) -> Result<BTCRPC, Error> {
+    Ok(BTCRPC::new(
+        &settings.rpc_url,
+        settings.rpc_auth()?,
+        settings.rpc_retry,
+    ))
+}
+
+// with a blocks directory, blocks are read from disk up to close to the tip,
+// and over RPC from there on
+async fn index_blocks(
+    settings: &Settings,
+    conn: &mut Connection,
+    shutdown: watch::Receiver<bool>,
+    follow: bool,
+) -> Result<(), Error> {
+    let btc_rpc = btc_rpc(settings)?;
+
+    match &settings.blocks_dir {
+        Some(blocks_dir) => {
+            let block_source = BlkFileSource::open(blocks_dir, settings.magic(), btc_rpc).await?;
+
+            index_from(settings, block_source, conn, shutdown, follow).await
+        }
+        None => index_from(settings, btc_rpc, conn, shutdown, follow).await,
+    }
+}
+
+async fn index_from<S: BlockSource>(
+    settings: &Settings,
+    block_source: S,
+    conn: &mut Connection,
+    shutdown: watch::Receiver<bool>,
+    follow: bool,
+) -> Result<(), Error> {
     let mut indexer = Indexer {
-            chain: Network::Testnet,
-            rpc_url: "https://powerful-cool-bush.btc-testnet.quiknode.pro/cf40fbe86ac4d435ce4799c8aae18c1dc65b96c8".to_string(),
-            conn: &mut pool.clone().get().unwrap(),
+        chain: settings.chain.network(),
+        block_source,
+        start_height: settings.start_height,
+        prefetch_blocks: settings.prefetch_blocks,
+        rpc_batch_size: settings.rpc_batch_size,
+        reorg_window: settings.reorg_window,
+        mempool: settings.mempool,
+        shutdown,
+        conn,
     };
 
-    indexer.index_blocks().await?;
+    if follow {
+        indexer
+            .follow(Duration::from_secs(settings.poll_interval))
+            .await
+    } else {
+        indexer.index_blocks().await
+    }
+}
+
+async fn index(settings: &Settings, pool: &Pool<SqliteConnectionManager>) -> Result<(), Error> {
+    let (shutdown_sender, shutdown) = watch::channel(false);
+
+    actix_web::rt::spawn(async move {
+        if tokio::signal::ctrl_c().await.is_ok() {
+            shutdown_sender.send_replace(true);
+        }
+    });
 
+    let mut conn = pool.get()?;
+
+    index_blocks(settings, &mut conn, shutdown, false).await
+}
+
+async fn serve(settings: &Settings, pool: Pool<SqliteConnectionManager>) -> Result<(), Error> {
+    http_server(settings, pool)?.await?;
+
+    Ok(())
+}
+
+async fn run(settings: &Settings, pool: Pool<SqliteConnectionManager>) -> Result<(), Error> {
+    let (shutdown_sender, shutdown) = watch::channel(false);
+
+    let server = http_server(settings, pool.clone())?;
+    let server_handle = server.handle();
+
+    let indexer_settings = settings.clone();
+    let mut indexer_task = actix_web::rt::spawn(async move {
+        let mut conn = pool.get()?;
+
+        index_blocks(&indexer_settings, &mut conn, shutdown, true).await
+    });
+
+    // the server stops on SIGINT/SIGTERM, the indexer is then asked to finish
+    // the block it is working on. if the indexer fails first, the server is
+    // stopped so the process exits instead of serving a stale index
+    let indexer_result = tokio::select! {
+        server_result = server => {
+            server_result?;
+            shutdown_sender.send_replace(true);
+            log("Waiting for the indexer to stop")?;
+            (&mut indexer_task).await
+        }
+        indexer_result = &mut indexer_task => {
+            server_handle.stop(true).await;
+            indexer_result
+        }
+    };
+
+    indexer_result?
+}
+
+fn http_server(settings: &Settings, pool: Pool<SqliteConnectionManager>) -> Result<Server, Error> {
     #[derive(OpenApi)]
     #[openapi(
         paths(
@@ -64,6 +320,22 @@ async fn main() -> Result<(), Error> {
             services::get_address_runes_utxo_by_rune_id,
             services::get_transaction_list,
             services::get_transaction_with_runes_txo,
+            services::get_transaction_rune_flows,
+            services::get_reorg_events,
+            services::get_reorg_event,
+            services::get_address_pending_balance_list,
+            services::get_address_pending_runes_utxo,
+            services::get_rune_burns,
+            services::get_mint_attempts,
+            services::get_pending_mints,
+            services::get_pending_etchings,
+            services::simulate_transaction,
+            services::encode_runestone,
+            services::decode_runestone,
+            services::plan_transfer,
+            services::build_mint_transactions,
+            services::commit_etching,
+            services::reveal_etching,
         ),
         components(schemas(
             schemas::SimpleStatus,
@@ -75,21 +347,83 @@ async fn main() -> Result<(), Error> {
             schemas::AddressRunesUTXOByRuneIdResponse,
             schemas::TransactionListResponse,
             schemas::TransactionWithRunesResponse,
+            schemas::RuneFlowListResponse,
             schemas::TransactionWithRunesTXO,
+            schemas::ReorgEventListResponse,
+            schemas::ReorgEventDetailsResponse,
+            schemas::ReorgEventDetails,
+            schemas::PendingRunesUTXOListResponse,
+            schemas::RuneBurns,
+            schemas::RuneBurnsResponse,
+            schemas::RuneMintAttempts,
+            schemas::RuneMintAttemptsResponse,
+            schemas::PendingMintListResponse,
+            schemas::PendingEtchingListResponse,
+            schemas::SimulateRequest,
+            schemas::SimulationResponse,
+            schemas::EncodedRunestoneResponse,
+            schemas::DecodeRequest,
+            schemas::DecodedTransactionResponse,
+            schemas::TransferPlanResponse,
+            schemas::MintTemplateResponse,
+            schemas::EtchingCommitResponse,
+            schemas::EtchingRevealResponse,
             db::RuneEntry,
             db::Terms,
             db::RuneTXO,
             db::Transaction,
+            db::RuneEvent,
+            db::BurnTotal,
+            db::MintAttemptCounts,
+            db::RuneFlow,
+            db::RuneFlowKind,
+            db::ReorgEvent,
+            db::OrphanedTransaction,
+            db::EtchingRejection,
+            db::EtchingRejectionReason,
+            db::MintAttempt,
+            db::MintOutcome,
+            db::PendingRuneTXO,
+            db::PendingMint,
+            db::PendingEtching,
+            allocation::BurnCause,
+            simulation::Simulation,
+            simulation::SimulatedBalance,
+            simulation::SimulatedOutput,
+            simulation::SimulatedMint,
+            simulation::SimulatedEtching,
+            simulation::SimulatedIgnoredEdict,
+            encoding::RunestoneSpec,
+            encoding::EdictSpec,
+            encoding::EtchingSpec,
+            encoding::TermsSpec,
+            encoding::EncodedRunestone,
+            decoding::DecodedTransaction,
+            decoding::DecodedField,
+            transfer::TransferRequest,
+            transfer::Payment,
+            transfer::TransferPlan,
+            mint::MintRequest,
+            mint::MintTemplate,
+            etch::CommitRequest,
+            etch::EtchingCommit,
+            etch::RevealRequest,
+            etch::EtchingReveal,
         ))
     )]
     struct ApiDoc;
     let openapi = ApiDoc::openapi();
 
-    log_file::log("HTTP Server started on http://localhost:8080")?;
+    log(&format!("HTTP Server started on http://{}", settings.bind))?;
 
-    HttpServer::new(move || {
+    let chain = settings.chain.network();
+
+    let server = HttpServer::new(move || {
         App::new()
-            .app_data(Data::new(AppState { pool: pool.clone() }))
+            .app_data(Data::new(AppState {
+                pool: pool.clone(),
+                chain,
+            }))
             .service(hello)
             .service(services::get_runes)
             .service(services::get_rune_by_id)
@@ -99,13 +433,28 @@ async fn main() -> Result<(), Error> {
             .service(services::get_address_runes_utxo_by_rune_id)
             .service(services::get_transaction_list)
             .service(services::get_transaction_with_runes_txo)
+            .service(services::get_transaction_rune_flows)
+            .service(services::get_reorg_events)
+            .service(services::get_reorg_event)
+            .service(services::get_address_pending_balance_list)
+            .service(services::get_address_pending_runes_utxo)
+            .service(services::get_rune_burns)
+            .service(services::get_mint_attempts)
+            .service(services::get_pending_mints)
+            .service(services::get_pending_etchings)
+            .service(services::simulate_transaction)
+            .service(services::encode_runestone)
+            .service(services::decode_runestone)
+            .service(services::plan_transfer)
+            .service(services::build_mint_transactions)
+            .service(services::commit_etching)
+            .service(services::reveal_etching)
             .service(
                 SwaggerUi::new("/swagger/{_:.*}").url("/api-docs/openapi.json", openapi.clone()),
             )
     })
-    .bind(("localhost", 8080))?
-    .run()
-    .await?;
+    .bind(&settings.bind)?
+    .run();
 
-    Ok(())
+    Ok(server)
 }
@@ -0,0 +1,570 @@
+use super::adapters::db::{
+    Block, PendingEtching, PendingMint, PendingRuneTXO, PendingSpend, PendingState,
+};
+use super::adapters::sqlite::SQLite;
+use super::block_source::BlockSource;
+use super::btc_rpc::{Prevout, TransactionInfo};
+use super::log_file::log;
+use super::rune_updaters::{unknown_commit_outputs, RuneUpdater};
+use super::runes::*;
+use anyhow::Error;
+use chrono::Utc;
+use rusqlite::Connection;
+
+/// Tracks what the transactions in the node's mempool would do to runes.
+///
+/// Mempool transactions are run through the `RuneUpdater` as if they were
+/// mined in the block after the indexed tip, and the changes are rolled back
+/// once the pending state has been read. Transactions leaving the mempool,
+/// because they confirmed, were replaced or were dropped, are evicted on the
+/// next update.
+///
+/// Every transaction is indexed again when the tip changes. In between,
+/// only the transactions entering the mempool are, along with the tracked
+/// transactions they spend from, that spend from them, or that mint or etch
+/// the same runes as a transaction entering or leaving the mempool, so the
+/// write lock is held in proportion to what changed.
+pub struct Mempool {
+    chain: Network,
+    batch_size: usize,
+    txs: HashMap<String, (u64, Transaction)>,
+    // transactions that failed to index, they are left out until they leave
+    // the mempool
+    failed: HashSet<String>,
+    // what each tracked transaction adds to the pending state
+    pending: HashMap<String, PendingState>,
+    seen: u64,
+    tip: Option<String>,
+}
+
+impl Mempool {
+    pub fn new(chain: Network, batch_size: usize) -> Mempool {
+        Mempool {
+            chain,
+            batch_size,
+            txs: HashMap::new(),
+            failed: HashSet::new(),
+            pending: HashMap::new(),
+            seen: 0,
+            tip: None,
+        }
+    }
+
+    /// Fetches the transactions that entered the mempool since the last
+    /// update, and updates the pending state if the mempool or the indexed
+    /// tip changed. If the update fails the pending state is cleared instead
+    /// of serving a stale one, and rebuilt on the next update.
+    pub async fn update<S: BlockSource>(
+        &mut self,
+        block_source: &S,
+        conn: &mut Connection,
+    ) -> Result<(), Error> {
+        let result = self.try_update(block_source, conn).await;
+
+        if result.is_err() {
+            self.tip = None;
+            self.pending.clear();
+            SQLite {}.replace_mempool(conn, &PendingState::default())?;
+        }
+
+        result
+    }
+
+    async fn try_update<S: BlockSource>(
+        &mut self,
+        block_source: &S,
+        conn: &mut Connection,
+    ) -> Result<(), Error> {
+        let mut database = SQLite {};
+
+        let Some(tip) = database.get_latest_block(conn)? else {
+            return Ok(());
+        };
+
+        let tx_ids = block_source.get_raw_mempool().await?;
+
+        let removed = {
+            let in_mempool = tx_ids.iter().collect::<HashSet<&String>>();
+
+            let removed_tx_ids = self
+                .txs
+                .keys()
+                .filter(|tx_id| !in_mempool.contains(tx_id))
+                .cloned()
+                .collect::<Vec<String>>();
+
+            self.failed.retain(|tx_id| in_mempool.contains(tx_id));
+
+            removed_tx_ids
+                .into_iter()
+                .map(|tx_id| {
+                    let (_, tx) = self.txs.remove(&tx_id).unwrap();
+                    (tx_id, tx)
+                })
+                .collect::<HashMap<String, Transaction>>()
+        };
+
+        let new_tx_ids = tx_ids
+            .into_iter()
+            .filter(|tx_id| !self.txs.contains_key(tx_id))
+            .collect::<Vec<String>>();
+
+        let mut added = HashSet::new();
+
+        for tx_ids in new_tx_ids.chunks(self.batch_size.max(1)) {
+            let tx_infos = block_source.get_transactions(tx_ids).await?;
+
+            for (tx_id, tx_info) in tx_ids.iter().zip(tx_infos) {
+                // the transaction may have confirmed or been dropped since the
+                // mempool was listed, it is picked up again if it's still
+                // there on the next update
+                let Some(tx_info) = tx_info else {
+                    continue;
+                };
+
+                if tx_info.blockhash.is_some() {
+                    continue;
+                }
+
+                self.seen += 1;
+                self.txs.insert(tx_id.clone(), (self.seen, tx_info.data));
+                added.insert(tx_id.clone());
+            }
+        }
+
+        let rebuild = self.tip.as_ref() != Some(&tip.hash);
+
+        if !rebuild && removed.is_empty() && added.is_empty() {
+            return Ok(());
+        }
+
+        let (indexed, failed) = {
+            let txs = if rebuild {
+                self.in_dependency_order()
+            } else {
+                self.affected_by(&added, removed.values())
+            };
+
+            // the node is asked for everything before the write lock is taken
+            let prevouts = self.commit_prevouts(block_source, conn, &txs).await?;
+
+            conn.execute_batch("BEGIN IMMEDIATE")?;
+
+            let pending = self.pending(conn, &tip, prevouts, &txs);
+
+            conn.execute_batch("ROLLBACK")?;
+
+            pending?
+        };
+
+        if rebuild {
+            self.pending.clear();
+        }
+
+        let mut changed = removed.into_keys().collect::<HashSet<String>>();
+        changed.extend(indexed.keys().cloned());
+        changed.extend(failed.iter().cloned());
+
+        for tx_id in &changed {
+            self.pending.remove(tx_id);
+        }
+
+        self.pending.extend(indexed);
+        self.failed.extend(failed);
+
+        changed.extend(self.link_spends());
+
+        if rebuild {
+            database.replace_mempool(conn, &self.pending_state(self.pending.keys()))?;
+        } else {
+            database.update_mempool(conn, &changed, &self.pending_state(&changed))?;
+        }
+
+        self.tip = Some(tip.hash);
+
+        Ok(())
+    }
+
+    /// The transactions in `tx_ids`, the tracked transactions minting or
+    /// etching the same runes as them or as the `removed` ones, and those
+    /// spending their outputs, with the tracked transactions they all spend
+    /// from, in dependency order.
+    fn affected_by<'a>(
+        &self,
+        tx_ids: &HashSet<String>,
+        removed: impl Iterator<Item = &'a Transaction>,
+    ) -> Vec<(&String, &Transaction)> {
+        let claims = |tx: &Transaction| {
+            let artifact = Runestone::decipher(tx);
+
+            let mint = artifact.as_ref().and_then(|artifact| artifact.mint());
+
+            let etching = match &artifact {
+                Some(Artifact::Runestone(runestone)) => runestone
+                    .etching
+                    .and_then(|etching| etching.rune)
+                    .map(|rune| rune.0),
+                Some(Artifact::Cenotaph(cenotaph)) => cenotaph.etching.map(|rune| rune.0),
+                None => None,
+            };
+
+            (mint, etching)
+        };
+
+        let (mints, etchings): (HashSet<Option<RuneId>>, HashSet<Option<u128>>) = tx_ids
+            .iter()
+            .map(|tx_id| claims(&self.txs[tx_id].1))
+            .chain(removed.map(claims))
+            .unzip();
+
+        let mut affected = self
+            .txs
+            .iter()
+            .filter(|(tx_id, (_, tx))| {
+                let (mint, etching) = claims(tx);
+
+                tx_ids.contains(*tx_id)
+                    || mint.is_some() && mints.contains(&mint)
+                    || etching.is_some() && etchings.contains(&etching)
+            })
+            .map(|(tx_id, _)| tx_id)
+            .collect::<HashSet<&String>>();
+
+        // children are indexed again on top of what their parents now hold
+        loop {
+            let children =
+                self.txs
+                    .iter()
+                    .filter(|(tx_id, (_, tx))| {
+                        !affected.contains(tx_id)
+                            && tx.input.iter().any(|input| {
+                                affected.contains(&input.previous_output.txid.to_string())
+                            })
+                    })
+                    .map(|(tx_id, _)| tx_id)
+                    .collect::<Vec<&String>>();
+
+            if children.is_empty() {
+                break;
+            }
+
+            affected.extend(children);
+        }
+
+        // parents are indexed again so their outputs hold runes to spend
+        let ordered = self.in_dependency_order();
+
+        let mut with_parents = affected;
+
+        for (tx_id, tx) in ordered.iter().rev() {
+            if with_parents.contains(tx_id) {
+                for input in &tx.input {
+                    if let Some((parent_id, _)) = self
+                        .txs
+                        .get_key_value(&input.previous_output.txid.to_string())
+                    {
+                        with_parents.insert(parent_id);
+                    }
+                }
+            }
+        }
+
+        ordered
+            .into_iter()
+            .filter(|(tx_id, _)| with_parents.contains(tx_id))
+            .collect()
+    }
+
+    /// Points the pending outputs at the tracked transactions spending them,
+    /// returning the transactions whose outputs changed.
+    fn link_spends(&mut self) -> HashSet<String> {
+        let spenders = self
+            .txs
+            .iter()
+            .filter(|(tx_id, _)| self.pending.contains_key(*tx_id))
+            .flat_map(|(tx_id, (_, tx))| {
+                tx.input
+                    .iter()
+                    .map(move |input| (input.previous_output, tx_id.clone()))
+            })
+            .collect::<HashMap<OutPoint, String>>();
+
+        let mut changed = HashSet::new();
+
+        for (tx_id, pending) in &mut self.pending {
+            for txo in &mut pending.runes_txos {
+                let spent_tx_id = spenders
+                    .get(&OutPoint {
+                        txid: txo.tx_id.parse().unwrap(),
+                        vout: txo.output_index,
+                    })
+                    .cloned();
+
+                if txo.spent_tx_id != spent_tx_id {
+                    txo.spent_tx_id = spent_tx_id;
+                    changed.insert(tx_id.clone());
+                }
+            }
+        }
+
+        changed
+    }
+
+    /// The pending state of the transactions in `tx_ids`.
+    fn pending_state<'a>(&self, tx_ids: impl IntoIterator<Item = &'a String>) -> PendingState {
+        let mut state = PendingState::default();
+
+        for pending in tx_ids
+            .into_iter()
+            .filter_map(|tx_id| self.pending.get(tx_id))
+        {
+            state.runes_txos.extend(pending.runes_txos.iter().cloned());
+            state.spends.extend(pending.spends.iter().cloned());
+            state.mints.extend(pending.mints.iter().cloned());
+            state.etchings.extend(pending.etchings.iter().cloned());
+        }
+
+        state
+    }
+
+    /// Looks up commit outputs of mempool etchings the index doesn't know on
+    /// the node, which needs -txindex for that.
+    async fn commit_prevouts<S: BlockSource>(
+        &self,
+        block_source: &S,
+        conn: &mut Connection,
+        txs: &[(&String, &Transaction)],
+    ) -> Result<HashMap<OutPoint, Prevout>, Error> {
+        let outpoints = unknown_commit_outputs(&SQLite {}, conn, txs.iter().map(|(_, tx)| *tx))?;
+
+        let tx_ids = outpoints
+            .iter()
+            .map(|outpoint| outpoint.txid.to_string())
+            .collect::<HashSet<String>>()
+            .into_iter()
+            .collect::<Vec<String>>();
+
+        let mut commit_txs = HashMap::new();
+
+        for (tx_id, tx_info) in tx_ids
+            .iter()
+            .zip(block_source.get_transactions(&tx_ids).await?)
+        {
+            // unconfirmed commit transactions don't count
+            let Some(TransactionInfo {
+                data,
+                blockhash: Some(block_hash),
+            }) = tx_info
+            else {
+                continue;
+            };
+
+            let height = block_source
+                .get_block_header_by_hash(&block_hash)
+                .await?
+                .height;
+
+            commit_txs.insert(tx_id.clone(), (data, height));
+        }
+
+        Ok(outpoints
+            .into_iter()
+            .filter_map(|outpoint| {
+                let (tx, height) = commit_txs.get(&outpoint.txid.to_string())?;
+
+                let output = tx.output.get(outpoint.vout.into_usize())?;
+
+                Some((
+                    outpoint,
+                    Prevout {
+                        script_pubkey: output.script_pubkey.clone(),
+                        height: *height,
+                    },
+                ))
+            })
+            .collect())
+    }
+
+    /// Indexes `txs` on top of `tip` and reads back what each of them
+    /// changed, along with the transactions that failed to index. The caller
+    /// rolls the changes back.
+    fn pending(
+        &self,
+        conn: &mut Connection,
+        tip: &Block,
+        prevouts: HashMap<OutPoint, Prevout>,
+        txs: &[(&String, &Transaction)],
+    ) -> Result<(HashMap<String, PendingState>, Vec<String>), Error> {
+        let database = SQLite {};
+
+        let height = tip.height + 1;
+
+        let mut failed = Vec::new();
+
+        {
+            let mut rune_updater = RuneUpdater {
+                database,
+                conn: &mut *conn,
+                chain: self.chain,
+                burned: HashMap::new(),
+                block_height: u32::try_from(height)?,
+                block_time: u32::try_from(Utc::now().timestamp())?,
+                prevouts,
+            };
+
+            // the coinbase would come first
+            for (tx_index, (tx_id, tx)) in txs.iter().copied().enumerate() {
+                if self.failed.contains(tx_id) {
+                    continue;
+                }
+
+                // a transaction that fails to index is rolled back on its own
+                // and left out of the pending state
+                rune_updater.conn.execute_batch("SAVEPOINT mempool_tx")?;
+
+                match rune_updater.index_runes(u32::try_from(tx_index + 1)?, tx, tx_id) {
+                    Ok(()) => rune_updater.conn.execute_batch("RELEASE mempool_tx")?,
+                    Err(err) => {
+                        log(&format!(
+                            "Failed to index mempool transaction {}: {:#}",
+                            tx_id, err
+                        ))?;
+
+                        rune_updater
+                            .conn
+                            .execute_batch("ROLLBACK TO mempool_tx; RELEASE mempool_tx")?;
+
+                        failed.push(tx_id.clone());
+                    }
+                }
+            }
+        }
+
+        // runes etched in the mempool get their id when they confirm, their
+        // balances aren't reported until then
+        let etched_in_mempool =
+            |rune_id: &str| rune_id.parse::<RuneId>().is_ok_and(|id| id.block == height);
+
+        let runes_txos: Vec<PendingRuneTXO> = database
+            .get_runes_txos_by_block_height(conn, height)?
+            .into_iter()
+            .filter(|txo| !etched_in_mempool(&txo.rune_id))
+            .map(|txo| PendingRuneTXO {
+                tx_id: txo.tx_id,
+                output_index: txo.output_index,
+                rune_id: txo.rune_id,
+                amount: txo.amount,
+                address: txo.address,
+                spent_tx_id: txo.spent_tx_id,
+            })
+            .collect();
+
+        let spends: Vec<PendingSpend> = database
+            .get_runes_txos_spent_at_block_height(conn, height)?
+            .into_iter()
+            .filter_map(|txo| {
+                Some(PendingSpend {
+                    tx_id: txo.tx_id,
+                    output_index: txo.output_index,
+                    spent_tx_id: txo.spent_tx_id?,
+                })
+            })
+            .collect();
+
+        let mints: Vec<PendingMint> = database
+            .get_rune_events_by_block_height(conn, height)?
+            .into_iter()
+            .filter(|event| event.event_type == "mint" && !etched_in_mempool(&event.rune_id))
+            .map(|event| PendingMint {
+                tx_id: event.tx_id,
+                rune_id: event.rune_id,
+                amount: event.amount,
+            })
+            .collect();
+
+        let etchings: Vec<PendingEtching> = database
+            .get_runes_by_block_height(conn, height)?
+            .into_iter()
+            .map(|rune_entry| PendingEtching {
+                tx_id: rune_entry.etching_tx_id,
+                name: rune_entry.name,
+                symbol: rune_entry.symbol,
+                divisibility: rune_entry.divisibility,
+                premine: rune_entry.premine,
+                is_cenotapth: rune_entry.is_cenotapth,
+            })
+            .collect();
+
+        let mut indexed = txs
+            .iter()
+            .filter(|(tx_id, _)| !failed.contains(*tx_id))
+            .map(|(tx_id, _)| ((*tx_id).clone(), PendingState::default()))
+            .collect::<HashMap<String, PendingState>>();
+
+        for txo in runes_txos {
+            if let Some(pending) = indexed.get_mut(&txo.tx_id) {
+                pending.runes_txos.push(txo);
+            }
+        }
+
+        for spend in spends {
+            if let Some(pending) = indexed.get_mut(&spend.spent_tx_id) {
+                pending.spends.push(spend);
+            }
+        }
+
+        for mint in mints {
+            if let Some(pending) = indexed.get_mut(&mint.tx_id) {
+                pending.mints.push(mint);
+            }
+        }
+
+        for etching in etchings {
+            if let Some(pending) = indexed.get_mut(&etching.tx_id) {
+                pending.etchings.push(etching);
+            }
+        }
+
+        Ok((indexed, failed))
+    }
+
+    /// Tracked transactions in the order they were first seen, with parents
+    /// in the mempool before their children.
+    fn in_dependency_order(&self) -> Vec<(&String, &Transaction)> {
+        let mut tx_ids = self.txs.keys().collect::<Vec<&String>>();
+        tx_ids.sort_by_key(|tx_id| self.txs[*tx_id].0);
+
+        let mut ordered = Vec::new();
+        let mut visited = HashSet::new();
+
+        for tx_id in tx_ids {
+            self.visit(tx_id, &mut visited, &mut ordered);
+        }
+
+        ordered
+    }
+
+    fn visit<'a>(
+        &'a self,
+        tx_id: &'a String,
+        visited: &mut HashSet<&'a String>,
+        ordered: &mut Vec<(&'a String, &'a Transaction)>,
+    ) {
+        if !visited.insert(tx_id) {
+            return;
+        }
+
+        let (_, tx) = &self.txs[tx_id];
+
+        for input in &tx.input {
+            if let Some((parent_id, _)) = self
+                .txs
+                .get_key_value(&input.previous_output.txid.to_string().to_lowercase())
+            {
+                self.visit(parent_id, visited, ordered);
+            }
+        }
+
+        ordered.push((tx_id, tx));
+    }
+}
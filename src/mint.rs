@@ -0,0 +1,277 @@
+use super::adapters::db::RuneEntry;
+use super::adapters::sqlite::SQLite;
+use super::rune_updaters::mintable;
+use super::runes::*;
+use rusqlite::Connection;
+use utoipa::ToSchema;
+
+/// Sats put in the destination output when the request doesn't say.
+const DEFAULT_POSTAGE: u64 = 546;
+
+/// Most mint transactions built for a single request.
+const MAX_COUNT: u32 = 1000;
+
+#[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
+#[serde(deny_unknown_fields)]
+pub struct MintRequest {
+    /// Address receiving the minted runes
+    pub destination: String,
+    /// Number of mint transactions to build, 1 if missing
+    pub count: Option<u32>,
+    /// Sats in the destination output, 546 if missing
+    pub postage: Option<u64>,
+}
+
+/// Unsigned mint transactions, without inputs. They are meant to be funded,
+/// e.g. with bitcoind's `fundrawtransaction`, and signed by the wallet.
+/// They are serialized without the segwit marker, which a transaction with
+/// no inputs can't be decoded with.
+#[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
+pub struct MintTemplate {
+    pub rune_id: String,
+    /// Runes minted by each transaction
+    pub amount: u128,
+    /// The OP_RETURN script holding the runestone, hex encoded
+    pub runestone: String,
+    /// Hex encoded transactions in the legacy format, one for each mint
+    pub transactions: Vec<String>,
+}
+
+/// Checks that `count` mints of `rune_entry` would succeed in the block
+/// after the indexed tip, counting the mints already waiting in the mempool,
+/// and builds their transactions.
+pub fn mint_transactions(
+    conn: &mut Connection,
+    chain: Network,
+    rune_entry: &RuneEntry,
+    request: &MintRequest,
+) -> Result<MintTemplate> {
+    let database = SQLite {};
+
+    let count = request.count.unwrap_or(1);
+
+    ensure!(
+        (1..=MAX_COUNT).contains(&count),
+        "count must be between 1 and {}",
+        MAX_COUNT
+    );
+
+    let destination = request
+        .destination
+        .parse::<Address<NetworkUnchecked>>()
+        .with_context(|| format!("invalid address {}", request.destination))?
+        .require_network(chain)
+        .with_context(|| format!("address {} is not for {}", request.destination, chain))?;
+
+    let height = database
+        .get_latest_block(conn)?
+        .map(|block| block.height + 1)
+        .unwrap_or_default();
+
+    let pending = database.get_pending_mints(conn, &rune_entry.rune_id)?.len() as u128;
+
+    let mut rune_entry = rune_entry.clone();
+    rune_entry.mint_count = rune_entry.mint_count.saturating_add(pending);
+
+    let amount = mintable(&rune_entry, height).map_err(|err| anyhow!("{}", err))?;
+
+    let cap = rune_entry
+        .terms
+        .as_ref()
+        .and_then(|terms| terms.cap)
+        .unwrap_or_default();
+    let left = cap.saturating_sub(rune_entry.mint_count);
+
+    ensure!(
+        u128::from(count) <= left,
+        "{}, only {} left",
+        MintError::Cap(cap),
+        left
+    );
+
+    let runestone = Runestone {
+        mint: Some(rune_entry.rune_id.parse()?),
+        pointer: Some(1),
+        ..Default::default()
+    }
+    .encipher();
+
+    let tx = Transaction {
+        version: 2,
+        lock_time: LockTime::ZERO,
+        input: Vec::new(),
+        output: vec![
+            TxOut {
+                value: 0,
+                script_pubkey: runestone.clone(),
+            },
+            TxOut {
+                value: request.postage.unwrap_or(DEFAULT_POSTAGE),
+                script_pubkey: destination.script_pubkey(),
+            },
+        ],
+    };
+
+    Ok(MintTemplate {
+        rune_id: rune_entry.rune_id,
+        amount,
+        runestone: hex::encode(runestone.as_bytes()),
+        transactions: vec![hex::encode(serialize_without_witness(&tx)?); count as usize],
+    })
+}
+
+/// Serializes `tx` in the legacy format. rust-bitcoin picks the segwit one
+/// for transactions without inputs, so that their input count isn't taken
+/// for the segwit marker, but bitcoind reads them as legacy transactions.
+fn serialize_without_witness(tx: &Transaction) -> Result<Vec<u8>> {
+    let mut bytes = Vec::new();
+
+    tx.version.consensus_encode(&mut bytes)?;
+    tx.input.consensus_encode(&mut bytes)?;
+    tx.output.consensus_encode(&mut bytes)?;
+    tx.lock_time.consensus_encode(&mut bytes)?;
+
+    Ok(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::block_source::fixture::testing::*;
+
+    #[tokio::test]
+    async fn build_mint_transactions() {
+        let etching = |input: u8, terms: Terms| {
+            tx(
+                &[outpoint(input)],
+                Some(Runestone {
+                    etching: Some(Etching {
+                        terms: Some(terms),
+                        ..Default::default()
+                    }),
+                    ..Default::default()
+                }),
+                vec![address_script(10)],
+            )
+        };
+
+        let open = RuneId::new(1, 1).unwrap();
+        let later = RuneId::new(1, 2).unwrap();
+
+        let blocks = vec![
+            vec![
+                etching(
+                    1,
+                    Terms {
+                        amount: Some(100),
+                        cap: Some(3),
+                        ..Default::default()
+                    },
+                ),
+                etching(
+                    2,
+                    Terms {
+                        amount: Some(100),
+                        cap: Some(3),
+                        height: (Some(5), None),
+                        ..Default::default()
+                    },
+                ),
+            ],
+            vec![tx(
+                &[outpoint(3)],
+                Some(Runestone {
+                    mint: Some(open),
+                    ..Default::default()
+                }),
+                vec![address_script(11)],
+            )],
+        ];
+
+        let mut fixture = Fixture::index(blocks).await;
+        let conn = &mut fixture.conn;
+
+        let rune_entry = |conn: &mut Connection, id: RuneId| {
+            SQLite {}
+                .get_rune_by_id(conn, &id.to_string())
+                .unwrap()
+                .unwrap()
+        };
+
+        let request = |count: u32| MintRequest {
+            destination: Address::from_script(&address_script(12), Network::Regtest)
+                .unwrap()
+                .to_string(),
+            count: Some(count),
+            postage: None,
+        };
+
+        let open_entry = rune_entry(conn, open);
+
+        let template = mint_transactions(conn, Network::Regtest, &open_entry, &request(2)).unwrap();
+
+        assert_eq!(template.amount, 100);
+        assert_eq!(template.transactions.len(), 2);
+
+        let tx = Transaction {
+            version: 2,
+            lock_time: LockTime::ZERO,
+            input: Vec::new(),
+            output: vec![
+                TxOut {
+                    value: 0,
+                    script_pubkey: ScriptBuf::from_bytes(hex::decode(&template.runestone).unwrap()),
+                },
+                TxOut {
+                    value: 546,
+                    script_pubkey: address_script(12),
+                },
+            ],
+        };
+
+        assert_eq!(
+            template.transactions[0],
+            hex::encode(serialize_without_witness(&tx).unwrap())
+        );
+
+        // decoded as bitcoind does without a witness: the version, no
+        // inputs, the outputs and the lock time
+        let bytes = hex::decode(&template.transactions[0]).unwrap();
+        let mut reader = bytes.as_slice();
+
+        assert_eq!(i32::consensus_decode(&mut reader).unwrap(), 2);
+        assert!(Vec::<TxIn>::consensus_decode(&mut reader)
+            .unwrap()
+            .is_empty());
+        assert_eq!(
+            Vec::<TxOut>::consensus_decode(&mut reader).unwrap(),
+            tx.output
+        );
+        assert_eq!(
+            LockTime::consensus_decode(&mut reader).unwrap(),
+            LockTime::ZERO
+        );
+        assert!(reader.is_empty());
+        assert_eq!(
+            Runestone::decipher(&tx),
+            Some(Artifact::Runestone(Runestone {
+                mint: Some(open),
+                pointer: Some(1),
+                ..Default::default()
+            }))
+        );
+
+        let error = |conn: &mut Connection, id: RuneId, count: u32| {
+            let rune_entry = rune_entry(conn, id);
+
+            format!(
+                "{:#}",
+                mint_transactions(conn, Network::Regtest, &rune_entry, &request(count))
+                    .unwrap_err()
+            )
+        };
+
+        assert_eq!(error(conn, open, 3), "limited to 3 mints, only 2 left");
+        assert_eq!(error(conn, later, 1), "mint starts on block 5");
+    }
+}
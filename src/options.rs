@@ -0,0 +1,188 @@
+use crate::chain::Chain;
+use clap::Parser;
+use std::path::PathBuf;
+
+#[derive(Debug, Parser)]
+#[command(version, about = "Bitcoin Runes indexer and explorer API")]
+pub struct Options {
+    #[arg(
+        long,
+        short,
+        global = true,
+        env = "RUNES_EXPLORER_CONFIG",
+        help = "Load settings from YAML file <CONFIG>."
+    )]
+    pub config: Option<PathBuf>,
+    #[arg(
+        long,
+        global = true,
+        value_enum,
+        env = "RUNES_EXPLORER_CHAIN",
+        help = "Index runes on <CHAIN>. [default: mainnet]"
+    )]
+    pub chain: Option<Chain>,
+    #[arg(
+        long,
+        global = true,
+        env = "RUNES_EXPLORER_SIGNET_CHALLENGE",
+        help = "Index the custom signet with hex encoded challenge script <SIGNET_CHALLENGE>."
+    )]
+    pub signet_challenge: Option<String>,
+    #[arg(
+        long,
+        global = true,
+        env = "RUNES_EXPLORER_RPC_URL",
+        help = "Connect to bitcoind JSON-RPC at <RPC_URL>."
+    )]
+    pub rpc_url: Option<String>,
+    #[arg(
+        long,
+        global = true,
+        env = "RUNES_EXPLORER_RPC_USER",
+        help = "Authenticate to bitcoind as <RPC_USER>."
+    )]
+    pub rpc_user: Option<String>,
+    #[arg(
+        long,
+        global = true,
+        env = "RUNES_EXPLORER_RPC_PASSWORD",
+        hide_env_values = true,
+        help = "Authenticate to bitcoind with <RPC_PASSWORD>."
+    )]
+    pub rpc_password: Option<String>,
+    #[arg(
+        long,
+        global = true,
+        env = "RUNES_EXPLORER_RPC_COOKIE_FILE",
+        help = "Authenticate to bitcoind with the cookie in <RPC_COOKIE_FILE>."
+    )]
+    pub rpc_cookie_file: Option<PathBuf>,
+    #[arg(
+        long,
+        global = true,
+        env = "RUNES_EXPLORER_DB_PATH",
+        help = "Store the index in SQLite database <DB_PATH>. [default: ./indexer.db on mainnet, in a directory named after the chain otherwise]"
+    )]
+    pub db_path: Option<PathBuf>,
+    #[arg(
+        long,
+        global = true,
+        env = "RUNES_EXPLORER_BIND",
+        help = "Listen for HTTP requests on <BIND>. [default: localhost:8080]"
+    )]
+    pub bind: Option<String>,
+    #[arg(
+        long,
+        global = true,
+        env = "RUNES_EXPLORER_START_HEIGHT",
+        help = "Start indexing an empty database at <START_HEIGHT>. [default: first rune height of the chain]"
+    )]
+    pub start_height: Option<u32>,
+    #[arg(
+        long,
+        global = true,
+        env = "RUNES_EXPLORER_POLL_INTERVAL",
+        help = "Poll bitcoind for new blocks every <POLL_INTERVAL> seconds. [default: 5]"
+    )]
+    pub poll_interval: Option<u64>,
+    #[arg(
+        long,
+        global = true,
+        env = "RUNES_EXPLORER_RPC_MAX_RETRIES",
+        help = "Retry failed bitcoind requests up to <RPC_MAX_RETRIES> times. [default: 10]"
+    )]
+    pub rpc_max_retries: Option<u32>,
+    #[arg(
+        long,
+        global = true,
+        env = "RUNES_EXPLORER_RPC_RETRY_BACKOFF_MS",
+        help = "Wait <RPC_RETRY_BACKOFF_MS> before the first retry, doubling on each attempt. [default: 500]"
+    )]
+    pub rpc_retry_backoff_ms: Option<u64>,
+    #[arg(
+        long,
+        global = true,
+        env = "RUNES_EXPLORER_RPC_RETRY_MAX_BACKOFF_MS",
+        help = "Never wait more than <RPC_RETRY_MAX_BACKOFF_MS> between retries. [default: 30000]"
+    )]
+    pub rpc_retry_max_backoff_ms: Option<u64>,
+    #[arg(
+        long,
+        global = true,
+        env = "RUNES_EXPLORER_PREFETCH_BLOCKS",
+        help = "Fetch up to <PREFETCH_BLOCKS> blocks ahead of the indexer. [default: 32]"
+    )]
+    pub prefetch_blocks: Option<usize>,
+    #[arg(
+        long,
+        global = true,
+        env = "RUNES_EXPLORER_RPC_BATCH_SIZE",
+        help = "Request <RPC_BATCH_SIZE> blocks per JSON-RPC batch. [default: 8]"
+    )]
+    pub rpc_batch_size: Option<usize>,
+    #[arg(
+        long,
+        global = true,
+        env = "RUNES_EXPLORER_REORG_WINDOW",
+        help = "Keep the hashes of the last <REORG_WINDOW> blocks to find where a reorg forked. Deeper reorgs roll back to an older checkpoint. [default: 20]"
+    )]
+    pub reorg_window: Option<u32>,
+    #[arg(
+        long,
+        global = true,
+        env = "RUNES_EXPLORER_BLOCKS_DIR",
+        help = "Read blocks from the blk*.dat files in bitcoind's blocks directory <BLOCKS_DIR> instead of over RPC, except near the tip."
+    )]
+    pub blocks_dir: Option<PathBuf>,
+    #[arg(
+        long,
+        global = true,
+        env = "RUNES_EXPLORER_MEMPOOL",
+        help = "Track rune transactions in bitcoind's mempool while following the chain tip."
+    )]
+    pub mempool: bool,
+    #[command(subcommand)]
+    pub subcommand: Subcommand,
+}
+
+#[derive(Debug, clap::Subcommand)]
+pub enum Subcommand {
+    #[command(about = "Index blocks up to the current chain tip and exit")]
+    Index,
+    #[command(about = "Serve the HTTP API from the existing index")]
+    Serve,
+    #[command(about = "Serve the HTTP API while indexing new blocks as they arrive")]
+    Run,
+    #[command(about = "Encode a runestone described in JSON into an OP_RETURN script")]
+    Encode {
+        #[arg(
+            help = "Encode the runestone described by JSON <RUNESTONE>, read from stdin if missing."
+        )]
+        runestone: Option<String>,
+    },
+    #[command(about = "Decode the runestone of a transaction without the index or a node")]
+    Decode {
+        #[arg(
+            help = "Decode raw transaction or PSBT <TRANSACTION>, hex or base64 encoded, read from stdin if missing."
+        )]
+        transaction: Option<String>,
+    },
+    #[command(
+        about = "Check that a rune can be etched and print the address its commit transaction pays to"
+    )]
+    EtchCommit {
+        #[arg(
+            help = "Commit to the etching described by JSON <REQUEST>, read from stdin if missing."
+        )]
+        request: Option<String>,
+    },
+    #[command(
+        about = "Build the reveal transaction of an etching once its commit has enough confirmations"
+    )]
+    EtchReveal {
+        #[arg(
+            help = "Reveal the etching described by JSON <REQUEST>, read from stdin if missing."
+        )]
+        request: Option<String>,
+    },
+}
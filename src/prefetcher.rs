@@ -0,0 +1,104 @@
+use crate::block_source::BlockSource;
+use anyhow::Error;
+use bitcoin::Block;
+use std::collections::VecDeque;
+use std::ops::RangeInclusive;
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+
+/// Fetches blocks ahead of the indexer. Up to `depth` blocks are requested
+/// concurrently in batches of `batch_size`, and handed out strictly in height
+/// order.
+pub struct Prefetcher {
+    receiver: mpsc::Receiver<Result<(u32, Block), Error>>,
+    _task: AbortOnDrop<()>,
+}
+
+impl Prefetcher {
+    pub fn start<S: BlockSource>(
+        block_source: S,
+        heights: RangeInclusive<u32>,
+        depth: usize,
+        batch_size: usize,
+    ) -> Prefetcher {
+        let batch_size = batch_size.max(1);
+        let max_in_flight = depth.div_ceil(batch_size).max(1);
+
+        let (sender, receiver) = mpsc::channel(batch_size);
+
+        let task = AbortOnDrop(tokio::spawn(async move {
+            let mut in_flight: VecDeque<(u32, Batch)> = VecDeque::new();
+
+            let mut next_height = *heights.start();
+            let end_height = *heights.end();
+
+            loop {
+                while in_flight.len() < max_in_flight && next_height <= end_height {
+                    let batch_end = (next_height + batch_size as u32).min(end_height + 1);
+
+                    let block_source = block_source.clone();
+
+                    in_flight.push_back((
+                        next_height,
+                        AbortOnDrop(tokio::spawn(async move {
+                            block_source
+                                .get_blocks_by_heights(next_height..batch_end)
+                                .await
+                        })),
+                    ));
+
+                    next_height = batch_end;
+                }
+
+                let Some((batch_start, mut batch)) = in_flight.pop_front() else {
+                    break;
+                };
+
+                let result = match (&mut batch.0).await {
+                    Ok(result) => result,
+                    Err(err) => Err(err.into()),
+                };
+
+                let blocks = match result {
+                    Ok(blocks) => blocks,
+                    Err(err) => {
+                        sender.send(Err(err)).await.ok();
+                        break;
+                    }
+                };
+
+                for (height, block) in (batch_start..).zip(blocks) {
+                    if sender.send(Ok((height, block))).await.is_err() {
+                        // the indexer stopped listening
+                        break;
+                    }
+                }
+
+                if sender.is_closed() {
+                    break;
+                }
+            }
+        }));
+
+        Prefetcher {
+            receiver,
+            _task: task,
+        }
+    }
+
+    pub async fn next(&mut self) -> Option<Result<(u32, Block), Error>> {
+        self.receiver.recv().await
+    }
+}
+
+type Batch = AbortOnDrop<Result<Vec<Block>, Error>>;
+
+// dropping the prefetcher cancels the fetches still in flight instead of
+// leaving them to finish in the background
+struct AbortOnDrop<T>(JoinHandle<T>);
+
+impl<T> Drop for AbortOnDrop<T> {
+    fn drop(&mut self) {
+        self.0.abort();
+    }
+}
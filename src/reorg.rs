@@ -1,6 +1,7 @@
 use super::log_file::log;
 use super::Error;
-use super::{adapters::sqlite::SQLite, btc_rpc::BTCRPC};
+use super::{adapters::sqlite::SQLite, block_source::BlockSource};
+use anyhow::Context;
 use rusqlite::Connection;
 
 pub struct Recoverable {
@@ -8,67 +9,78 @@ pub struct Recoverable {
     pub depth: u32,
 }
 
-pub struct Reorg<'a> {
+pub struct Reorg<'a, S: BlockSource> {
     pub database: SQLite,
     pub conn: &'a mut Connection,
-    pub rpc_url: String,
+    pub block_source: &'a S,
 }
 
-impl<'a> Reorg<'a> {
+impl<'a, S: BlockSource> Reorg<'a, S> {
     pub async fn detect_and_handle_reorg(
         &mut self,
         bitcoind_prev_blockhash: &str,
         height: u32,
     ) -> Result<Option<Recoverable>, Error> {
-        let max_recoverable_reorg_depth: u32 = 20;
-
-        match self
+        let Some(prev_block) = self
             .database
             .get_block_by_height(self.conn, u64::from(height.checked_sub(1).unwrap()))?
-        {
-            Some(prev_block) => {
-                if prev_block.hash == bitcoind_prev_blockhash.to_string() {
-                    return Ok(None);
-                } else {
-                    log(&format!("Reorg detected at height: {}", height))?;
+        else {
+            return Ok(None);
+        };
 
-                    let btc_rpc = &BTCRPC {
-                        url: self.rpc_url.clone(),
-                    };
+        if prev_block.hash == bitcoind_prev_blockhash {
+            return Ok(None);
+        }
 
-                    for depth in 1..=max_recoverable_reorg_depth {
-                        let index_block = self
-                            .database
-                            .get_block_by_height(self.conn, u64::from(height - depth))?;
+        log(&format!("Reorg detected at height: {}", height))?;
 
-                        let bitcoind_block_hash = btc_rpc
-                            .get_block_hash_by_height(height.saturating_sub(depth))
-                            .await
-                            .ok();
+        // recorded with the reorg event, along with the orphaned rows
+        let tip = self.block_source.get_block_count().await?;
+        let new_tip_hash = self
+            .block_source
+            .get_block_hash_by_height(tip)
+            .await?
+            .with_context(|| format!("no block at tip height {}", tip))?;
+
+        // the blocks table keeps every block of the reorg window plus sparse
+        // checkpoints below it. the newest one still on the active chain is
+        // the last common ancestor, or close enough below it
+        for block in self
+            .database
+            .get_blocks_below(self.conn, u64::from(height))?
+        {
+            let block_height = u32::try_from(block.height)?;
 
-                        if index_block.map(|b| b.hash) == bitcoind_block_hash {
-                            let reorg_from_height = height.saturating_sub(depth);
+            let bitcoind_block_hash = self
+                .block_source
+                .get_block_hash_by_height(block_height)
+                .await?;
 
-                            log(&format!(
-                                "Recovering at depth: {}. Rolling back to indexed height: {}",
-                                depth, reorg_from_height
-                            ))?;
+            if bitcoind_block_hash.as_deref() == Some(block.hash.as_str()) {
+                let depth = height - block_height;
 
-                            self.database
-                                .reorg_blocks(self.conn, u64::from(reorg_from_height))?;
+                log(&format!(
+                    "Recovering at depth: {}. Rolling back to indexed height: {}",
+                    depth, block_height
+                ))?;
 
-                            log(&format!("Roll back finished"))?;
+                self.database
+                    .reorg_blocks(self.conn, block.height, &new_tip_hash)?;
 
-                            return Ok(Some(Recoverable { height, depth }));
-                        }
-                    }
+                log("Roll back finished")?;
 
-                    panic!("Reorg depth exceeded the recoverable limit");
-                }
-            }
-            None => {
-                return Ok(None);
+                return Ok(Some(Recoverable { height, depth }));
             }
         }
+
+        // not even the oldest checkpoint is on the active chain anymore
+        log("No indexed block is on the active chain, rebuilding the index")?;
+
+        self.database.reset(self.conn, &new_tip_hash)?;
+
+        Ok(Some(Recoverable {
+            height,
+            depth: height,
+        }))
     }
 }
@@ -1,9 +1,14 @@
 use rusqlite::Connection;
 
-use super::adapters::db::{RuneEntry, RuneTXO, Terms, Transaction as DbTransaction, TXO};
+use super::adapters::db::{
+    EtchingRejection, EtchingRejectionReason, MintAttempt, MintOutcome, RuneEntry, RuneEvent,
+    RuneFlow, RuneFlowKind, RuneTXO, Terms, Transaction as DbTransaction, TXO,
+};
 // use super::adapters::mock_db::MockDb as Db;
 use super::adapters::sqlite::SQLite as Db;
-use super::btc_rpc::BTCRPC;
+use super::allocation::{allocate, Allocation, Burn, Transfer, TransferSource};
+use super::btc_rpc::Prevout;
+use super::decoding::decode;
 use super::lot::Lot;
 use super::runes::*;
 use super::utils;
@@ -15,24 +20,23 @@ pub struct RuneUpdater<'a> {
     pub burned: HashMap<RuneId, Lot>,
     pub block_height: u32,
     pub block_time: u32,
-    pub btc_rpc: &'a BTCRPC,
+    /// Commit outputs the indexed transactions spend that aren't in the
+    /// taproot output table, see `unknown_commit_outputs`.
+    pub prevouts: HashMap<OutPoint, Prevout>,
 }
 
 impl<'a> RuneUpdater<'a> {
-    pub async fn index_runes(
-        &mut self,
-        tx_index: u32,
-        tx: &Transaction,
-        tx_id: &str,
-    ) -> Result<()> {
+    pub fn index_runes(&mut self, tx_index: u32, tx: &Transaction, tx_id: &str) -> Result<()> {
         let artifact = Runestone::decipher(tx);
 
-        let mut unallocated = self.unallocated(tx)?;
+        let mut flows = Vec::new();
 
-        let mut allocated: Vec<HashMap<RuneId, Lot>> = vec![HashMap::new(); tx.output.len()];
+        let mut unallocated = self.unallocated(tx, tx_id, &mut flows)?;
 
         self.mark_txs_as_spent(tx, tx_id)?;
 
+        let mut etched = None;
+
         if let Some(artifact) = &artifact {
             self.add_transaction(tx_id, &artifact)?;
             // self.add_txo(tx, tx_id)?;
@@ -40,152 +44,131 @@ impl<'a> RuneUpdater<'a> {
             if let Some(id) = artifact.mint() {
                 if let Some(amount) = self.mint(id, tx_id)? {
                     *unallocated.entry(id).or_default() += amount;
+
+                    if amount > 0 {
+                        flows.push(self.flow(
+                            tx_id,
+                            id.to_string(),
+                            amount.n(),
+                            RuneFlowKind::Mint,
+                        ));
+                    }
                 }
             }
 
-            let etched = self.etched(tx_index, tx, artifact).await?;
+            etched = self.etched(tx_index, tx, tx_id, artifact)?;
+
+            let mut decoded = decode(tx);
 
-            if let Artifact::Runestone(runestone) = artifact {
-                if let Some((id, ..)) = etched {
-                    *unallocated.entry(id).or_default() +=
-                        runestone.etching.unwrap().premine.unwrap_or_default();
+            // edicts referring to the etched rune by 0:0 are stored with its id
+            if let (Some((id, _)), Some(Artifact::Runestone(runestone))) =
+                (etched, &mut decoded.artifact)
+            {
+                for edict in &mut runestone.edicts {
+                    if edict.id == RuneId::default() {
+                        edict.id = id;
+                    }
                 }
+            }
 
-                for Edict { id, amount, output } in runestone.edicts.iter().copied() {
-                    let amount = Lot(amount);
+            self.database
+                .add_runestone(self.conn, self.block_height.into(), &decoded)?;
 
-                    // edicts with output values greater than the number of outputs
-                    // should never be produced by the edict parser
-                    let output = usize::try_from(output).unwrap();
-                    assert!(output <= tx.output.len());
+            if let Some((id, rune)) = etched {
+                self.create_rune_entry(tx_id, artifact, id, rune)?;
 
-                    let id = if id == RuneId::default() {
-                        let Some((id, ..)) = etched else {
-                            continue;
-                        };
+                if let Artifact::Runestone(runestone) = artifact {
+                    let premine = runestone.etching.unwrap().premine.unwrap_or_default();
 
-                        id
-                    } else {
-                        id
-                    };
-
-                    let Some(balance) = unallocated.get_mut(&id) else {
-                        continue;
-                    };
-
-                    let mut allocate = |balance: &mut Lot, amount: Lot, output: usize| {
-                        if amount > 0 {
-                            *balance -= amount;
-                            *allocated[output].entry(id).or_default() += amount;
-                        }
-                    };
-
-                    if output == tx.output.len() {
-                        // find non-OP_RETURN outputs
-                        let destinations = tx
-                            .output
-                            .iter()
-                            .enumerate()
-                            .filter_map(|(output, tx_out)| {
-                                (!tx_out.script_pubkey.is_op_return()).then_some(output)
-                            })
-                            .collect::<Vec<usize>>();
-
-                        if !destinations.is_empty() {
-                            if amount == 0 {
-                                // if amount is zero, divide balance between eligible outputs
-                                let amount = *balance / destinations.len() as u128;
-                                let remainder =
-                                    usize::try_from(*balance % destinations.len() as u128).unwrap();
-
-                                for (i, output) in destinations.iter().enumerate() {
-                                    allocate(
-                                        balance,
-                                        if i < remainder { amount + 1 } else { amount },
-                                        *output,
-                                    );
-                                }
-                            } else {
-                                // if amount is non-zero, distribute amount to eligible outputs
-                                for output in destinations {
-                                    allocate(balance, amount.min(*balance), output);
-                                }
-                            }
-                        }
-                    } else {
-                        // Get the allocatable amount
-                        let amount = if amount == 0 {
-                            *balance
-                        } else {
-                            amount.min(*balance)
-                        };
-
-                        allocate(balance, amount, output);
+                    if premine > 0 {
+                        flows.push(self.flow(
+                            tx_id,
+                            id.to_string(),
+                            premine,
+                            RuneFlowKind::Premine,
+                        ));
                     }
                 }
             }
+        }
 
-            if let Some((id, rune)) = etched {
-                self.create_rune_entry(tx_id, artifact, id, rune)?;
-            }
+        let Allocation {
+            outputs,
+            transfers,
+            burns,
+            ..
+        } = allocate(tx, artifact.as_ref(), unallocated, etched.map(|(id, _)| id));
+
+        for Transfer {
+            id,
+            amount,
+            output,
+            source,
+        } in transfers
+        {
+            let output_index = u32::try_from(output)?;
+
+            let kind = match source {
+                TransferSource::Edict(index) => RuneFlowKind::Edict {
+                    edict_index: u32::try_from(index)?,
+                    output_index,
+                },
+                TransferSource::Pointer => RuneFlowKind::Pointer { output_index },
+                TransferSource::DefaultOutput => RuneFlowKind::DefaultOutput { output_index },
+            };
+
+            flows.push(self.flow(tx_id, id.to_string(), amount.n(), kind));
         }
 
-        let mut burned: HashMap<RuneId, Lot> = HashMap::new();
+        // increment entries with burned runes
+        for Burn {
+            id,
+            amount,
+            cause,
+            output,
+        } in burns
+        {
+            let output_index = output.map(u32::try_from).transpose()?;
 
-        if let Some(Artifact::Cenotaph(_)) = artifact {
-            for (id, balance) in unallocated {
-                *burned.entry(id).or_default() += balance;
-            }
-        } else {
-            let pointer = artifact
-                .map(|artifact| match artifact {
-                    Artifact::Runestone(runestone) => runestone.pointer,
-                    Artifact::Cenotaph(_) => unreachable!(),
-                })
-                .unwrap_or_default();
-
-            // assign all un-allocated runes to the default output, or the first non
-            // OP_RETURN output if there is no default, or if the default output is
-            // too large
-            if let Some(vout) = pointer
-                .map(|pointer| pointer.into_usize())
-                .inspect(|&pointer| assert!(pointer < allocated.len()))
-                .or_else(|| {
-                    tx.output
-                        .iter()
-                        .enumerate()
-                        .find(|(_vout, tx_out)| !tx_out.script_pubkey.is_op_return())
-                        .map(|(vout, _tx_out)| vout)
-                })
-            {
-                for (id, balance) in unallocated {
-                    if balance > 0 {
-                        *allocated[vout].entry(id).or_default() += balance;
-                    }
-                }
-            } else {
-                for (id, balance) in unallocated {
-                    if balance > 0 {
-                        *burned.entry(id).or_default() += balance;
-                    }
-                }
+            *self.burned.entry(id).or_default() += amount;
+            self.database.add_rune_burn_event(
+                self.conn,
+                RuneEvent {
+                    tx_id: tx_id.to_string(),
+                    rune_id: id.to_string(),
+                    block_height: self.block_height.into(),
+                    timestamp: self.block_time,
+                    amount: amount.n(),
+                    event_type: "burn".to_string(),
+                    output_index,
+                    address: None,
+                    burn_cause: Some(cause),
+                },
+            )?;
+
+            if amount > 0 {
+                flows.push(self.flow(
+                    tx_id,
+                    id.to_string(),
+                    amount.n(),
+                    RuneFlowKind::Burn {
+                        cause,
+                        output_index,
+                    },
+                ));
             }
         }
 
+        if !flows.is_empty() {
+            self.database.add_rune_flows(self.conn, &flows)?;
+        }
+
         // update outpoint balances
-        for (vout, balances) in allocated.into_iter().enumerate() {
+        for (vout, balances) in outputs.into_iter().enumerate() {
             if balances.is_empty() {
                 continue;
             }
 
-            // increment burned balances
-            if tx.output[vout].script_pubkey.is_op_return() {
-                for (id, balance) in &balances {
-                    *burned.entry(*id).or_default() += *balance;
-                }
-                continue;
-            }
-
             let mut balances = balances.into_iter().collect::<Vec<(RuneId, Lot)>>();
 
             // Sort balances by id so tests can assert balances in a fixed order
@@ -194,56 +177,85 @@ impl<'a> RuneUpdater<'a> {
             self.add_rune_txo(tx, tx_id, vout, balances)?;
         }
 
-        // increment entries with burned runes
-        for (id, amount) in burned {
-            *self.burned.entry(id).or_default() += amount;
-            self.database.add_rune_burn_event(
-                self.conn,
-                &id.to_string(),
-                tx_id,
-                amount.n(),
-                self.block_height.into(),
-                self.block_time,
-            )?;
+        Ok(())
+    }
+
+    /// Records the taproot outputs created and spent by the transactions of
+    /// the block, which etching commitments are checked against.
+    pub fn index_taproot_txos(&mut self, txs: &[Transaction]) -> Result {
+        let mut created = Vec::new();
+        let mut spent = Vec::new();
+
+        for tx in txs {
+            let tx_id = tx.txid().to_string().to_lowercase();
+
+            for (vout, output) in tx.output.iter().enumerate() {
+                if output.script_pubkey.is_v1_p2tr() {
+                    created.push((tx_id.clone(), u32::try_from(vout)?));
+                }
+            }
+
+            if tx.is_coin_base() {
+                continue;
+            }
+
+            for input in &tx.input {
+                spent.push((
+                    input.previous_output.txid.to_string().to_lowercase(),
+                    input.previous_output.vout,
+                ));
+            }
         }
 
+        self.database
+            .add_taproot_txos(self.conn, self.block_height.into(), &created)?;
+
+        self.database
+            .mark_taproot_txos_as_spent(self.conn, &spent, self.block_height.into())?;
+
         Ok(())
     }
 
     pub fn update(mut self) -> Result {
         for (rune_id, burned) in self.burned {
-            self.database
-                .increase_rune_entry_burned(self.conn, &rune_id.to_string(), burned.n())?;
+            self.database.increase_rune_entry_burned(
+                self.conn,
+                &rune_id.to_string(),
+                burned.n(),
+            )?;
         }
 
         Ok(())
     }
 
     fn add_transaction(&mut self, tx_id: &str, artifact: &Artifact) -> Result {
-        self.database.add_transaction(self.conn, DbTransaction {
-            tx_id: tx_id.to_string(),
-            block_height: self.block_height.into(),
-            is_artifact: true,
-            // is_artifact: artifact.is_some(),
-            is_runestone: if let Artifact::Runestone(_) = artifact {
-                // is_runestone: if let Some(Artifact::Runestone(_)) = artifact {
-                true
-            } else {
-                false
-            },
-            is_cenotapth: if let Artifact::Cenotaph(_) = artifact {
-                // is_cenotapth: if let Some(Artifact::Cenotaph(_)) = artifact {
-                true
-            } else {
-                false
-            },
-            cenotapth_message: if let Artifact::Cenotaph(cenotaph) = artifact {
-                cenotaph.flaw.map(|flaw| flaw.to_string())
-            } else {
-                None
+        self.database.add_transaction(
+            self.conn,
+            DbTransaction {
+                tx_id: tx_id.to_string(),
+                block_height: self.block_height.into(),
+                is_artifact: true,
+                // is_artifact: artifact.is_some(),
+                is_runestone: if let Artifact::Runestone(_) = artifact {
+                    // is_runestone: if let Some(Artifact::Runestone(_)) = artifact {
+                    true
+                } else {
+                    false
+                },
+                is_cenotapth: if let Artifact::Cenotaph(_) = artifact {
+                    // is_cenotapth: if let Some(Artifact::Cenotaph(_)) = artifact {
+                    true
+                } else {
+                    false
+                },
+                cenotapth_message: if let Artifact::Cenotaph(cenotaph) = artifact {
+                    cenotaph.flaw.map(|flaw| flaw.to_string())
+                } else {
+                    None
+                },
+                timestamp: self.block_time,
             },
-            timestamp: self.block_time,
-        })?;
+        )?;
         Ok(())
     }
 
@@ -272,19 +284,22 @@ impl<'a> RuneUpdater<'a> {
         balances: Vec<(RuneId, Lot)>,
     ) -> Result {
         for (id, balance) in balances {
-            self.database.add_rune_txo(self.conn, RuneTXO {
-                tx_id: tx_id.to_string(),
-                output_index: vout as u32,
-                block_height: self.block_height.into(),
-                rune_id: id.to_string(),
-                amount: balance.n(),
-                address: utils::output_to_address(&tx.output[vout], self.chain)
-                    .map(|s| s.to_lowercase()),
-                is_unspent: true,
-                spent_tx_id: None,
-                timestamp: self.block_time,
-                spent_block_height: None,
-            })?;
+            self.database.add_rune_txo(
+                self.conn,
+                RuneTXO {
+                    tx_id: tx_id.to_string(),
+                    output_index: vout as u32,
+                    block_height: self.block_height.into(),
+                    rune_id: id.to_string(),
+                    amount: balance.n(),
+                    address: utils::output_to_address(&tx.output[vout], self.chain)
+                        .map(|s| s.to_lowercase()),
+                    is_unspent: true,
+                    spent_tx_id: None,
+                    timestamp: self.block_time,
+                    spent_block_height: None,
+                },
+            )?;
         }
 
         Ok(())
@@ -371,10 +386,11 @@ impl<'a> RuneUpdater<'a> {
         self.database.add_rune_entry(self.conn, rune_entry)
     }
 
-    async fn etched(
+    fn etched(
         &mut self,
         tx_index: u32,
         tx: &Transaction,
+        tx_id: &str,
         artifact: &Artifact,
     ) -> Result<Option<(RuneId, Rune)>> {
         let rune = match artifact {
@@ -391,16 +407,45 @@ impl<'a> RuneUpdater<'a> {
         let minimum = Rune::minimum_at_height(self.chain, Height(self.block_height));
 
         let rune = if let Some(rune) = rune {
-            if rune < minimum
-                || rune.is_reserved()
-                || self
-                    .database
-                    .get_rune_by_raw_name(self.conn, &rune.to_string())?
-                    .is_some()
-                || !self.tx_commits_to_rune(tx, rune).await?
+            let rejection = if rune < minimum {
+                Some(EtchingRejectionReason::TooShort {
+                    minimum: minimum.to_string(),
+                })
+            } else if rune.is_reserved() {
+                Some(EtchingRejectionReason::Reserved)
+            } else if let Some(rune_entry) = self
+                .database
+                .get_rune_by_raw_name(self.conn, &rune.to_string())?
             {
+                Some(EtchingRejectionReason::Duplicate {
+                    rune_id: rune_entry.rune_id,
+                })
+            } else {
+                match self.tx_commits_to_rune(tx, rune)? {
+                    None => Some(EtchingRejectionReason::MissingCommitment),
+                    Some(confirmations)
+                        if confirmations < Runestone::COMMIT_CONFIRMATIONS.into() =>
+                    {
+                        Some(EtchingRejectionReason::ImmatureCommitment { confirmations })
+                    }
+                    Some(_) => None,
+                }
+            };
+
+            if let Some(reason) = rejection {
+                self.database.add_etching_rejection(
+                    self.conn,
+                    EtchingRejection {
+                        tx_id: tx_id.to_string(),
+                        block_height: self.block_height.into(),
+                        rune: rune.to_string(),
+                        reason,
+                    },
+                )?;
+
                 return Ok(None);
             }
+
             rune
         } else {
             // let reserved_runes = self
@@ -425,26 +470,76 @@ impl<'a> RuneUpdater<'a> {
     }
 
     fn mint(&mut self, id: RuneId, tx_id: &str) -> Result<Option<Lot>> {
-        let Some(rune_entry) = self.database.get_rune_by_id(self.conn, &id.to_string())? else {
-            return Ok(None);
+        let (outcome, message) = match self.database.get_rune_by_id(self.conn, &id.to_string())? {
+            Some(rune_entry) => match mintable(&rune_entry, self.block_height.into()) {
+                Ok(amount) => (MintOutcome::Minted { amount }, None),
+                Err(err) => (
+                    match &err {
+                        MintError::Cap(cap) => MintOutcome::Cap { cap: *cap },
+                        MintError::End(end) => MintOutcome::End { end: *end },
+                        MintError::Start(start) => MintOutcome::Start { start: *start },
+                        MintError::Unmintable => MintOutcome::Unmintable,
+                    },
+                    Some(err.to_string()),
+                ),
+            },
+            None => (
+                MintOutcome::NotFound,
+                Some("rune doesn't exist".to_string()),
+            ),
         };
 
-        let Ok(amount) = mintable(&rune_entry, self.block_height.into()) else {
+        self.database.add_mint_attempt(
+            self.conn,
+            MintAttempt {
+                tx_id: tx_id.to_string(),
+                rune_id: id.to_string(),
+                block_height: self.block_height.into(),
+                timestamp: self.block_time,
+                outcome: outcome.clone(),
+                message,
+            },
+        )?;
+
+        let MintOutcome::Minted { amount } = outcome else {
             return Ok(None);
         };
 
-        self.database
-            .update_rune_entry_mint_count(self.conn, &id.to_string(), tx_id, amount, self.block_height.into(), self.block_time)?;
+        self.database.update_rune_entry_mint_count(
+            self.conn,
+            &id.to_string(),
+            tx_id,
+            amount,
+            self.block_height.into(),
+            self.block_time,
+        )?;
 
         Ok(Some(Lot(amount)))
     }
 
-    fn unallocated(&mut self, tx: &Transaction) -> Result<HashMap<RuneId, Lot>> {
+    fn flow(&self, tx_id: &str, rune_id: String, amount: u128, kind: RuneFlowKind) -> RuneFlow {
+        RuneFlow {
+            tx_id: tx_id.to_string(),
+            block_height: self.block_height.into(),
+            rune_id,
+            amount,
+            kind,
+        }
+    }
+
+    /// The runes in the inputs of `tx`, recording a flow for each input
+    /// balance.
+    fn unallocated(
+        &mut self,
+        tx: &Transaction,
+        tx_id: &str,
+        flows: &mut Vec<RuneFlow>,
+    ) -> Result<HashMap<RuneId, Lot>> {
         // map of rune ID to un-allocated balance of that rune
         let mut unallocated: HashMap<RuneId, Lot> = HashMap::new();
 
         // increment unallocated runes with the runes in tx inputs
-        for input in &tx.input {
+        for (input_index, input) in tx.input.iter().enumerate() {
             let runes_txos = self.database.get_runes_txo_by_output_index(
                 self.conn,
                 &input.previous_output.txid.to_string().to_lowercase(),
@@ -455,6 +550,16 @@ impl<'a> RuneUpdater<'a> {
                 *unallocated
                     .entry(rt.rune_id.parse::<RuneId>()?)
                     .or_default() += rt.amount;
+
+                flows.push(self.flow(
+                    tx_id,
+                    rt.rune_id,
+                    rt.amount,
+                    RuneFlowKind::Input {
+                        input_index: u32::try_from(input_index)?,
+                        outpoint: format!("{}:{}", rt.tx_id, rt.output_index),
+                    },
+                ));
             }
         }
 
@@ -475,74 +580,128 @@ impl<'a> RuneUpdater<'a> {
         Ok(())
     }
 
-    async fn tx_commits_to_rune(&mut self, tx: &Transaction, rune: Rune) -> Result<bool> {
-        let commitment = rune.commitment();
+    /// Confirmations of the most confirmed commit output spent by an input
+    /// revealing a commitment to `rune`, or `None` if no input does.
+    fn tx_commits_to_rune(&mut self, tx: &Transaction, rune: Rune) -> Result<Option<u32>> {
+        let mut most_confirmations = None;
 
-        for input in &tx.input {
-            // extracting a tapscript does not indicate that the input being spent
-            // was actually a taproot output. this is checked below, when we load the
-            // output's entry from the database
-            let Some(tapscript) = input.witness.tapscript() else {
-                continue;
+        for input in commitment_inputs(tx, rune) {
+            // revealing a commitment does not indicate that the input being
+            // spent was actually a taproot output. this is checked below, when
+            // we load the output's entry from the database
+            let commit_tx_height = match self.database.get_taproot_txo_block_height(
+                self.conn,
+                &input.previous_output.txid.to_string().to_lowercase(),
+                input.previous_output.vout,
+            )? {
+                Some(height) => height,
+                None => match self.commit_tx_height(input) {
+                    Some(height) => height,
+                    None => continue,
+                },
             };
 
-            for instruction in tapscript.instructions() {
-                // ignore errors, since the extracted script may not be valid
-                let Ok(instruction) = instruction else {
-                    break;
-                };
+            let confirmations = self
+                .block_height
+                .checked_sub(commit_tx_height.try_into().unwrap())
+                .unwrap()
+                + 1;
 
-                let Some(pushbytes) = instruction.push_bytes() else {
-                    continue;
-                };
+            if confirmations >= Runestone::COMMIT_CONFIRMATIONS.into() {
+                return Ok(Some(confirmations));
+            }
 
-                if pushbytes.as_bytes() != commitment {
-                    continue;
-                }
+            most_confirmations = most_confirmations.max(Some(confirmations));
+        }
 
-                let Ok(tx_info) = self
-                    .btc_rpc
-                    .get_transaction(&input.previous_output.txid.to_string())
-                    .await
-                // .into_option()?
-                else {
-                    panic!(
-                        "can't get input transaction: {}",
-                        input.previous_output.txid
-                    );
-                };
-
-                let taproot = tx_info.data.output[input.previous_output.vout.into_usize()]
-                    .script_pubkey
-                    .is_v1_p2tr();
-
-                if !taproot {
-                    continue;
-                }
+        Ok(most_confirmations)
+    }
 
-                let commit_tx_height = self
-                    .btc_rpc
-                    .get_block_header_by_hash(&tx_info.raw.blockhash.unwrap())
-                    .await
-                    .unwrap()
-                    .height;
-
-                let confirmations = self
-                    .block_height
-                    .checked_sub(commit_tx_height.try_into().unwrap())
-                    .unwrap()
-                    + 1;
-
-                if confirmations >= Runestone::COMMIT_CONFIRMATIONS.into() {
-                    return Ok(true);
-                }
-            }
+    // outputs created before the index started aren't in the taproot output
+    // table. an output that couldn't be looked up elsewhere either doesn't
+    // count as a commitment
+    fn commit_tx_height(&self, input: &TxIn) -> Option<u64> {
+        let prevout = self.prevouts.get(&input.previous_output)?;
+
+        if !prevout.script_pubkey.is_v1_p2tr() {
+            return None;
         }
 
-        Ok(false)
+        Some(prevout.height.into())
     }
 }
 
+/// Inputs of `tx` revealing a commitment to `rune` in their tapscript.
+fn commitment_inputs(tx: &Transaction, rune: Rune) -> impl Iterator<Item = &TxIn> {
+    let commitment = rune.commitment();
+
+    tx.input.iter().filter(move |input| {
+        let Some(tapscript) = input.witness.tapscript() else {
+            return false;
+        };
+
+        tapscript
+            .instructions()
+            // ignore errors, since the extracted script may not be valid
+            .map_while(|instruction| instruction.ok())
+            .any(|instruction| {
+                instruction
+                    .push_bytes()
+                    .is_some_and(|pushbytes| pushbytes.as_bytes() == commitment)
+            })
+    })
+}
+
+/// Outputs spent by etchings in `txs` revealing a commitment that neither the
+/// taproot output table nor `txs` themselves created. They have to be looked
+/// up on the node before `txs` are indexed.
+pub fn unknown_commit_outputs<'a>(
+    database: &Db,
+    conn: &mut Connection,
+    txs: impl IntoIterator<Item = &'a Transaction> + Clone,
+) -> Result<Vec<OutPoint>> {
+    let created = txs
+        .clone()
+        .into_iter()
+        .map(|tx| tx.txid())
+        .collect::<HashSet<Txid>>();
+
+    let mut unknown = Vec::new();
+
+    for tx in txs {
+        let rune = match Runestone::decipher(tx) {
+            Some(Artifact::Runestone(runestone)) => {
+                runestone.etching.and_then(|etching| etching.rune)
+            }
+            Some(Artifact::Cenotaph(cenotaph)) => cenotaph.etching,
+            None => None,
+        };
+
+        let Some(rune) = rune else {
+            continue;
+        };
+
+        for input in commitment_inputs(tx, rune) {
+            if created.contains(&input.previous_output.txid) {
+                continue;
+            }
+
+            if database
+                .get_taproot_txo_block_height(
+                    conn,
+                    &input.previous_output.txid.to_string().to_lowercase(),
+                    input.previous_output.vout,
+                )?
+                .is_none()
+            {
+                unknown.push(input.previous_output);
+            }
+        }
+    }
+
+    Ok(unknown)
+}
+
 pub fn mintable(rune_entry: &RuneEntry, block_height: u64) -> Result<u128, MintError> {
     let Some(terms) = rune_entry.terms.clone() else {
         return Err(MintError::Unmintable);
@@ -1,16 +1,16 @@
 use super::runes::*;
 
-#[derive(Serialize, Eq, PartialEq, Deserialize, Debug)]
+#[derive(Serialize, Eq, PartialEq, Deserialize, Debug, Clone)]
 pub enum Artifact {
-  Cenotaph(Cenotaph),
-  Runestone(Runestone),
+    Cenotaph(Cenotaph),
+    Runestone(Runestone),
 }
 
 impl Artifact {
-  pub fn mint(&self) -> Option<RuneId> {
-    match self {
-      Self::Cenotaph(cenotaph) => cenotaph.mint,
-      Self::Runestone(runestone) => runestone.mint,
+    pub fn mint(&self) -> Option<RuneId> {
+        match self {
+            Self::Cenotaph(cenotaph) => cenotaph.mint,
+            Self::Runestone(runestone) => runestone.mint,
+        }
     }
-  }
 }
@@ -1,5 +1,5 @@
 use super::runes::*;
-#[derive(Default, Serialize, Deserialize, Debug, PartialEq, Eq)]
+#[derive(Default, Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
 pub struct Runestone {
     pub edicts: Vec<Edict>,
     pub etching: Option<Etching>,
@@ -190,6 +190,20 @@ impl Runestone {
         builder.into_script()
     }
 
+    /// The integers of the runestone payload of `transaction`, or the flaw
+    /// that keeps them from being read.
+    pub fn integers_of(transaction: &Transaction) -> Option<Result<Vec<u128>, Flaw>> {
+        Some(match Runestone::payload(transaction)? {
+            Payload::Valid(payload) => Runestone::integers(&payload).map_err(|_| Flaw::Varint),
+            Payload::Invalid(flaw) => Err(flaw),
+        })
+    }
+
+    /// Name of the field with tag `tag`, if the tag is known.
+    pub fn tag_name(tag: u128) -> Option<&'static str> {
+        Tag::name(tag)
+    }
+
     fn payload(transaction: &Transaction) -> Option<Payload> {
         // search transaction outputs for payload
         for output in &transaction.output {
@@ -25,6 +25,26 @@ pub(super) enum Tag {
 }
 
 impl Tag {
+  const ALL: [Tag; 17] = [
+    Tag::Body,
+    Tag::Flags,
+    Tag::Rune,
+    Tag::Premine,
+    Tag::Cap,
+    Tag::Amount,
+    Tag::HeightStart,
+    Tag::HeightEnd,
+    Tag::OffsetStart,
+    Tag::OffsetEnd,
+    Tag::Mint,
+    Tag::Pointer,
+    Tag::Cenotaph,
+    Tag::Divisibility,
+    Tag::Spacers,
+    Tag::Symbol,
+    Tag::Nop,
+  ];
+
   pub(super) fn take<const N: usize, T>(
     self,
     fields: &mut HashMap<u128, VecDeque<u128>>,
@@ -61,6 +81,30 @@ impl Tag {
       self.encode([value.into()], payload)
     }
   }
+
+  pub(super) fn name(tag: u128) -> Option<&'static str> {
+    let tag = Tag::ALL.into_iter().find(|known| *known == tag)?;
+
+    Some(match tag {
+      Tag::Body => "body",
+      Tag::Flags => "flags",
+      Tag::Rune => "rune",
+      Tag::Premine => "premine",
+      Tag::Cap => "cap",
+      Tag::Amount => "amount",
+      Tag::HeightStart => "height_start",
+      Tag::HeightEnd => "height_end",
+      Tag::OffsetStart => "offset_start",
+      Tag::OffsetEnd => "offset_end",
+      Tag::Mint => "mint",
+      Tag::Pointer => "pointer",
+      Tag::Cenotaph => "cenotaph",
+      Tag::Divisibility => "divisibility",
+      Tag::Spacers => "spacers",
+      Tag::Symbol => "symbol",
+      Tag::Nop => "nop",
+    })
+  }
 }
 
 impl From<Tag> for u128 {
@@ -85,6 +129,14 @@ mod tests {
     assert_eq!(2u128, Tag::Flags.into());
   }
 
+  #[test]
+  fn name() {
+    assert_eq!(Tag::name(0), Some("body"));
+    assert_eq!(Tag::name(12), Some("height_start"));
+    assert_eq!(Tag::name(127), Some("nop"));
+    assert_eq!(Tag::name(7), None);
+  }
+
   #[test]
   fn partial_eq() {
     assert_eq!(Tag::Body, 0);
@@ -1,6 +1,12 @@
 use std::collections::HashMap;
 
 use crate::adapters::db::*;
+use crate::decoding::DecodedTransaction;
+use crate::encoding::EncodedRunestone;
+use crate::etch::{EtchingCommit, EtchingReveal};
+use crate::mint::MintTemplate;
+use crate::simulation::Simulation;
+use crate::transfer::TransferPlan;
 use serde::*;
 use utoipa::{IntoParams, ToSchema};
 
@@ -84,6 +90,15 @@ pub struct TransactionWithRunesTXO {
     pub cenotapth_message: Option<String>,
     pub timestamp: u32,
     pub etched_rune_id: Option<String>,
+    /// Why the etching of the transaction didn't create a rune
+    pub etching_rejection: Option<EtchingRejection>,
+    /// The mint of the transaction and whether it succeeded
+    pub mint_attempt: Option<MintAttempt>,
+    /// The runestone or cenotaph as deciphered when the transaction was
+    /// indexed. Edict ids are absolute, edicts referring to the rune the
+    /// transaction etched by `0:0` carry its id. `0:0` is left as is when
+    /// the etching failed
+    pub runestone: Option<DecodedTransaction>,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
@@ -91,8 +106,152 @@ pub struct TransactionWithRunesResponse {
     pub data: TransactionWithRunesTXO,
 }
 
+#[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
+pub struct RuneFlowListResponse {
+    pub data: Vec<RuneFlow>,
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
 pub struct TransactionListResponse {
     pub data: Vec<Transaction>,
 }
 
+#[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
+pub struct ReorgEventListResponse {
+    pub data: Vec<ReorgEvent>,
+}
+
+#[derive(Deserialize, ToSchema, IntoParams)]
+pub struct ReorgEventDetailsParams {
+    pub reorg_id: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
+pub struct ReorgEventDetails {
+    pub reorg_event: ReorgEvent,
+    pub transactions: Vec<OrphanedTransaction>,
+    pub rune_entries: Vec<RuneEntry>,
+    pub rune_events: Vec<RuneEvent>,
+    pub runes_txos: Vec<RuneTXO>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
+pub struct ReorgEventDetailsResponse {
+    pub data: ReorgEventDetails,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
+pub struct PendingRunesUTXOListResponse {
+    pub data: Vec<PendingRuneTXO>,
+}
+
+#[derive(Deserialize, ToSchema, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct PaginationParams {
+    /// Number of items to skip, 0 if missing
+    pub offset: Option<u32>,
+    /// Number of items to return, 100 if missing and at most 1000
+    pub limit: Option<u32>,
+}
+
+impl PaginationParams {
+    const DEFAULT_LIMIT: u32 = 100;
+    const MAX_LIMIT: u32 = 1000;
+
+    pub fn offset(&self) -> u32 {
+        self.offset.unwrap_or_default()
+    }
+
+    pub fn limit(&self) -> u32 {
+        self.limit
+            .unwrap_or(Self::DEFAULT_LIMIT)
+            .min(Self::MAX_LIMIT)
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
+pub struct RuneBurns {
+    pub rune_id: String,
+    /// Runes burned in total, as recorded on the rune entry
+    pub burned: u128,
+    /// Runes burned by cause, over every burn event
+    pub totals: Vec<BurnTotal>,
+    /// Burn events of the requested page, newest first
+    pub events: Vec<RuneEvent>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
+pub struct RuneBurnsResponse {
+    pub data: RuneBurns,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
+pub struct RuneMintAttempts {
+    pub rune_id: String,
+    /// Mint attempts by outcome, over every attempt
+    pub counts: MintAttemptCounts,
+    /// Mint attempts of the requested page, oldest first
+    pub attempts: Vec<MintAttempt>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
+pub struct RuneMintAttemptsResponse {
+    pub data: RuneMintAttempts,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
+pub struct PendingMintListResponse {
+    pub data: Vec<PendingMint>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
+pub struct PendingEtchingListResponse {
+    pub data: Vec<PendingEtching>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
+pub struct SimulateRequest {
+    /// Raw transaction or PSBT, hex or base64 encoded
+    pub transaction: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
+pub struct SimulationResponse {
+    pub data: Simulation,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
+pub struct EncodedRunestoneResponse {
+    pub data: EncodedRunestone,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
+pub struct DecodeRequest {
+    /// Raw transaction or PSBT, hex or base64 encoded
+    pub transaction: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, ToSchema)]
+pub struct DecodedTransactionResponse {
+    pub data: DecodedTransaction,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
+pub struct TransferPlanResponse {
+    pub data: TransferPlan,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
+pub struct MintTemplateResponse {
+    pub data: MintTemplate,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
+pub struct EtchingCommitResponse {
+    pub data: EtchingCommit,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
+pub struct EtchingRevealResponse {
+    pub data: EtchingReveal,
+}
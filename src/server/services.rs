@@ -1,7 +1,8 @@
 use crate::adapters::sqlite::SQLite;
 use crate::server::schemas::*;
 use crate::{adapters::db::Database, AppState};
-use actix_web::{get, web, HttpResponse, Responder};
+use crate::{decoding, encoding, etch, mint, simulation, transfer};
+use actix_web::{get, post, web, HttpResponse, Responder};
 
 #[utoipa::path(
     responses(
@@ -12,7 +13,7 @@ use actix_web::{get, web, HttpResponse, Responder};
 async fn get_runes(state: web::Data<AppState>) -> impl Responder {
     let conn = &mut state.pool.get().unwrap();
 
-    let database = SQLite { };
+    let database = SQLite {};
 
     let response = RuneEntryListResponse {
         data: database.get_runes(conn).unwrap(),
@@ -32,7 +33,7 @@ async fn get_rune_by_id(
 ) -> impl Responder {
     let conn = &mut state.pool.get().unwrap();
 
-    let database = SQLite { };
+    let database = SQLite {};
 
     let rune_id = &path_params.rune_id;
 
@@ -54,7 +55,7 @@ async fn get_address_balance_by_rune_id(
 ) -> impl Responder {
     let conn = &mut state.pool.get().unwrap();
 
-    let database = SQLite { };
+    let database = SQLite {};
 
     let address = &path_params.address.to_lowercase();
     let rune_id = &path_params.rune_id;
@@ -79,7 +80,7 @@ async fn get_address_balance_list(
 ) -> impl Responder {
     let conn = &mut state.pool.get().unwrap();
 
-    let database = SQLite {  };
+    let database = SQLite {};
 
     let address = &path_params.address.to_lowercase();
 
@@ -101,7 +102,7 @@ async fn get_runes_txo_by_output_index(
 ) -> impl Responder {
     let conn = &mut state.pool.get().unwrap();
 
-    let database = SQLite {  };
+    let database = SQLite {};
 
     let tx_id = &path_params.tx_id.to_lowercase();
     let index = path_params.index;
@@ -126,14 +127,14 @@ async fn get_address_runes_utxo_by_rune_id(
 ) -> impl Responder {
     let conn = &mut state.pool.get().unwrap();
 
-    let database = SQLite { };
+    let database = SQLite {};
 
     let address = &path_params.address.to_lowercase();
     let rune_id = &path_params.rune_id;
 
     let response = AddressRunesUTXOByRuneIdResponse {
         data: database
-            .get_address_runes_utxo_by_rune_id(conn,address, rune_id)
+            .get_address_runes_utxo_by_rune_id(conn, address, rune_id)
             .unwrap(),
     };
 
@@ -147,7 +148,7 @@ async fn get_address_runes_utxo_by_rune_id(
 async fn get_transaction_list(state: web::Data<AppState>) -> impl Responder {
     let conn = &mut state.pool.get().unwrap();
 
-    let database = SQLite {  };
+    let database = SQLite {};
 
     let response = TransactionListResponse {
         data: database.get_transactions(conn).unwrap(),
@@ -167,7 +168,7 @@ async fn get_transaction_with_runes_txo(
 ) -> impl Responder {
     let conn = &mut state.pool.get().unwrap();
 
-    let database = SQLite {  };
+    let database = SQLite {};
 
     let tx_id = &path_params.tx_id.to_lowercase();
 
@@ -179,6 +180,12 @@ async fn get_transaction_with_runes_txo(
 
     let rune_entry = database.get_rune_by_etched_tx_id(conn, tx_id).unwrap();
 
+    let etching_rejection = database.get_etching_rejection(conn, tx_id).unwrap();
+
+    let mint_attempt = database.get_transaction_mint_attempt(conn, tx_id).unwrap();
+
+    let runestone = database.get_runestone(conn, tx_id).unwrap();
+
     let response = TransactionWithRunesResponse {
         data: TransactionWithRunesTXO {
             tx_id: tx_id.to_string(),
@@ -200,8 +207,399 @@ async fn get_transaction_with_runes_txo(
             cenotapth_message: transaction.cenotapth_message,
             timestamp: transaction.timestamp,
             etched_rune_id: rune_entry.map(|r| r.rune_id),
+            etching_rejection,
+            mint_attempt,
+            runestone,
+        },
+    };
+
+    HttpResponse::Ok().json(response)
+}
+
+#[utoipa::path(
+    responses((status = 200, description = "Returns how runes entered, moved within and left the transaction", body = RuneFlowListResponse)),
+    params(TransactionWithRunesParams)
+)]
+#[get("/transactions/{tx_id}/flow")]
+async fn get_transaction_rune_flows(
+    state: web::Data<AppState>,
+    path_params: web::Path<TransactionWithRunesParams>,
+) -> impl Responder {
+    let conn = &mut state.pool.get().unwrap();
+
+    let database = SQLite {};
+
+    let tx_id = &path_params.tx_id.to_lowercase();
+
+    let response = RuneFlowListResponse {
+        data: database.get_transaction_rune_flows(conn, tx_id).unwrap(),
+    };
+
+    HttpResponse::Ok().json(response)
+}
+
+#[utoipa::path(
+    responses((status = 200, description = "Returns reorg events, newest first", body = ReorgEventListResponse)),
+)]
+#[get("/reorgs")]
+async fn get_reorg_events(state: web::Data<AppState>) -> impl Responder {
+    let conn = &mut state.pool.get().unwrap();
+
+    let database = SQLite {};
+
+    let response = ReorgEventListResponse {
+        data: database.get_reorg_events(conn).unwrap(),
+    };
+
+    HttpResponse::Ok().json(response)
+}
+
+#[utoipa::path(
+    responses((status = 200, description = "Returns a reorg event with what it orphaned and whether orphaned transactions confirmed again", body = ReorgEventDetailsResponse)),
+    params(ReorgEventDetailsParams)
+)]
+#[get("/reorgs/{reorg_id}")]
+async fn get_reorg_event(
+    state: web::Data<AppState>,
+    path_params: web::Path<ReorgEventDetailsParams>,
+) -> impl Responder {
+    let conn = &mut state.pool.get().unwrap();
+
+    let database = SQLite {};
+
+    let reorg_id = path_params.reorg_id;
+
+    let Some(reorg_event) = database.get_reorg_event(conn, reorg_id).unwrap() else {
+        return HttpResponse::NotFound().finish();
+    };
+
+    let response = ReorgEventDetailsResponse {
+        data: ReorgEventDetails {
+            reorg_event,
+            transactions: database.get_orphaned_transactions(conn, reorg_id).unwrap(),
+            rune_entries: database.get_orphaned_rune_entries(conn, reorg_id).unwrap(),
+            rune_events: database.get_orphaned_rune_events(conn, reorg_id).unwrap(),
+            runes_txos: database.get_orphaned_runes_txos(conn, reorg_id).unwrap(),
+        },
+    };
+
+    HttpResponse::Ok().json(response)
+}
+
+#[utoipa::path(
+    responses((status = 200, description = "Returns balances object for the specified address once mempool transactions confirm", body = AddressBalanceListResponse)),
+    params(AddressBalanceListParams)
+)]
+#[get("/address/{address}/runes/pending-balance-list")]
+async fn get_address_pending_balance_list(
+    state: web::Data<AppState>,
+    path_params: web::Path<AddressBalanceListParams>,
+) -> impl Responder {
+    let conn = &mut state.pool.get().unwrap();
+
+    let database = SQLite {};
+
+    let address = &path_params.address.to_lowercase();
+
+    let response = AddressBalanceListResponse {
+        data: database
+            .get_address_pending_balance_list(conn, address)
+            .unwrap(),
+    };
+
+    HttpResponse::Ok().json(response)
+}
+
+#[utoipa::path(
+    responses((status = 200, description = "Returns unconfirmed runes utxo for the specified address", body = PendingRunesUTXOListResponse)),
+    params(AddressBalanceListParams)
+)]
+#[get("/address/{address}/runes/pending-utxo")]
+async fn get_address_pending_runes_utxo(
+    state: web::Data<AppState>,
+    path_params: web::Path<AddressBalanceListParams>,
+) -> impl Responder {
+    let conn = &mut state.pool.get().unwrap();
+
+    let database = SQLite {};
+
+    let address = &path_params.address.to_lowercase();
+
+    let response = PendingRunesUTXOListResponse {
+        data: database
+            .get_address_pending_runes_utxo(conn, address)
+            .unwrap(),
+    };
+
+    HttpResponse::Ok().json(response)
+}
+
+#[utoipa::path(
+    responses(
+        (status = 200, description = "Returns burn events of the specified rune, with the runes burned by cause", body = RuneBurnsResponse),
+        (status = 404, description = "Rune not found")
+    ),
+    params(RuneEntryDetailsParams, PaginationParams)
+)]
+#[get("/runes/{rune_id}/burns")]
+async fn get_rune_burns(
+    state: web::Data<AppState>,
+    path_params: web::Path<RuneEntryDetailsParams>,
+    query_params: web::Query<PaginationParams>,
+) -> impl Responder {
+    let conn = &mut state.pool.get().unwrap();
+
+    let database = SQLite {};
+
+    let rune_id = &path_params.rune_id;
+
+    let Some(rune_entry) = database.get_rune_by_id(conn, rune_id).unwrap() else {
+        return HttpResponse::NotFound().finish();
+    };
+
+    let response = RuneBurnsResponse {
+        data: RuneBurns {
+            rune_id: rune_id.to_string(),
+            burned: rune_entry.burned,
+            totals: database.get_rune_burn_totals(conn, rune_id).unwrap(),
+            events: database
+                .get_rune_burn_events(conn, rune_id, query_params.offset(), query_params.limit())
+                .unwrap(),
         },
     };
 
     HttpResponse::Ok().json(response)
 }
+
+#[utoipa::path(
+    responses((status = 200, description = "Returns confirmed attempts to mint the specified rune, failed ones included, with the attempts by outcome", body = RuneMintAttemptsResponse)),
+    params(RuneEntryDetailsParams, PaginationParams)
+)]
+#[get("/runes/{rune_id}/mint-attempts")]
+async fn get_mint_attempts(
+    state: web::Data<AppState>,
+    path_params: web::Path<RuneEntryDetailsParams>,
+    query_params: web::Query<PaginationParams>,
+) -> impl Responder {
+    let conn = &mut state.pool.get().unwrap();
+
+    let database = SQLite {};
+
+    let rune_id = &path_params.rune_id;
+
+    // attempts are recorded for ids no rune has, so there's no 404 here
+    let response = RuneMintAttemptsResponse {
+        data: RuneMintAttempts {
+            rune_id: rune_id.to_string(),
+            counts: database
+                .get_rune_mint_attempt_counts(conn, rune_id)
+                .unwrap(),
+            attempts: database
+                .get_rune_mint_attempts(conn, rune_id, query_params.offset(), query_params.limit())
+                .unwrap(),
+        },
+    };
+
+    HttpResponse::Ok().json(response)
+}
+
+#[utoipa::path(
+    responses((status = 200, description = "Returns mints of the specified rune waiting in the mempool", body = PendingMintListResponse)),
+    params(RuneEntryDetailsParams)
+)]
+#[get("/runes/{rune_id}/pending-mints")]
+async fn get_pending_mints(
+    state: web::Data<AppState>,
+    path_params: web::Path<RuneEntryDetailsParams>,
+) -> impl Responder {
+    let conn = &mut state.pool.get().unwrap();
+
+    let database = SQLite {};
+
+    let rune_id = &path_params.rune_id;
+
+    let response = PendingMintListResponse {
+        data: database.get_pending_mints(conn, rune_id).unwrap(),
+    };
+
+    HttpResponse::Ok().json(response)
+}
+
+#[utoipa::path(
+    responses((status = 200, description = "Returns etchings waiting in the mempool", body = PendingEtchingListResponse)),
+)]
+#[get("/mempool/etchings")]
+async fn get_pending_etchings(state: web::Data<AppState>) -> impl Responder {
+    let conn = &mut state.pool.get().unwrap();
+
+    let database = SQLite {};
+
+    let response = PendingEtchingListResponse {
+        data: database.get_pending_etchings(conn).unwrap(),
+    };
+
+    HttpResponse::Ok().json(response)
+}
+
+#[utoipa::path(
+    request_body = SimulateRequest,
+    responses(
+        (status = 200, description = "Returns what the transaction would do to runes if it confirmed in the next block", body = SimulationResponse),
+        (status = 400, description = "The transaction couldn't be decoded", body = SimpleStatus)
+    ),
+)]
+#[post("/simulate")]
+async fn simulate_transaction(
+    state: web::Data<AppState>,
+    body: web::Json<SimulateRequest>,
+) -> impl Responder {
+    let conn = &mut state.pool.get().unwrap();
+
+    let tx = match simulation::decode_transaction(&body.transaction) {
+        Ok(tx) => tx,
+        Err(err) => {
+            return HttpResponse::BadRequest().json(SimpleStatus {
+                message: format!("{:#}", err),
+            })
+        }
+    };
+
+    let response = SimulationResponse {
+        data: simulation::simulate(conn, state.chain, &tx).unwrap(),
+    };
+
+    HttpResponse::Ok().json(response)
+}
+
+#[utoipa::path(
+    request_body = RunestoneSpec,
+    responses(
+        (status = 200, description = "Returns the OP_RETURN script of the runestone", body = EncodedRunestoneResponse),
+        (status = 400, description = "The runestone is invalid", body = SimpleStatus)
+    ),
+)]
+#[post("/runestones/encode")]
+async fn encode_runestone(body: web::Json<encoding::RunestoneSpec>) -> impl Responder {
+    match encoding::encode(&body) {
+        Ok(encoded) => HttpResponse::Ok().json(EncodedRunestoneResponse { data: encoded }),
+        Err(err) => HttpResponse::BadRequest().json(SimpleStatus {
+            message: format!("{:#}", err),
+        }),
+    }
+}
+
+#[utoipa::path(
+    request_body = DecodeRequest,
+    responses(
+        (status = 200, description = "Returns the runestone or cenotaph of the transaction", body = DecodedTransactionResponse),
+        (status = 400, description = "The transaction couldn't be decoded", body = SimpleStatus)
+    ),
+)]
+#[post("/runestones/decode")]
+async fn decode_runestone(body: web::Json<DecodeRequest>) -> impl Responder {
+    match simulation::decode_transaction(&body.transaction) {
+        Ok(tx) => HttpResponse::Ok().json(DecodedTransactionResponse {
+            data: decoding::decode(&tx),
+        }),
+        Err(err) => HttpResponse::BadRequest().json(SimpleStatus {
+            message: format!("{:#}", err),
+        }),
+    }
+}
+
+#[utoipa::path(
+    request_body = TransferRequest,
+    responses(
+        (status = 200, description = "Returns an unsigned transaction sending the runes", body = TransferPlanResponse),
+        (status = 400, description = "The transfer can't be made", body = SimpleStatus)
+    ),
+)]
+#[post("/transfers/plan")]
+async fn plan_transfer(
+    state: web::Data<AppState>,
+    body: web::Json<transfer::TransferRequest>,
+) -> impl Responder {
+    let conn = &mut state.pool.get().unwrap();
+
+    match transfer::plan_transfer(conn, state.chain, &body) {
+        Ok(plan) => HttpResponse::Ok().json(TransferPlanResponse { data: plan }),
+        Err(err) => HttpResponse::BadRequest().json(SimpleStatus {
+            message: format!("{:#}", err),
+        }),
+    }
+}
+
+#[utoipa::path(
+    request_body = MintRequest,
+    responses(
+        (status = 200, description = "Returns unsigned transactions minting the specified rune", body = MintTemplateResponse),
+        (status = 400, description = "The rune can't be minted", body = SimpleStatus),
+        (status = 404, description = "The rune doesn't exist")
+    ),
+    params(RuneEntryDetailsParams)
+)]
+#[post("/runes/{rune_id}/mint-tx")]
+async fn build_mint_transactions(
+    state: web::Data<AppState>,
+    path_params: web::Path<RuneEntryDetailsParams>,
+    body: web::Json<mint::MintRequest>,
+) -> impl Responder {
+    let conn = &mut state.pool.get().unwrap();
+
+    let database = SQLite {};
+
+    let Some(rune_entry) = database.get_rune_by_id(conn, &path_params.rune_id).unwrap() else {
+        return HttpResponse::NotFound().finish();
+    };
+
+    match mint::mint_transactions(conn, state.chain, &rune_entry, &body) {
+        Ok(template) => HttpResponse::Ok().json(MintTemplateResponse { data: template }),
+        Err(err) => HttpResponse::BadRequest().json(SimpleStatus {
+            message: format!("{:#}", err),
+        }),
+    }
+}
+
+#[utoipa::path(
+    request_body = CommitRequest,
+    responses(
+        (status = 200, description = "Returns the address the commit transaction of the etching pays to", body = EtchingCommitResponse),
+        (status = 400, description = "The rune can't be etched", body = SimpleStatus)
+    ),
+)]
+#[post("/etchings/commit")]
+async fn commit_etching(
+    state: web::Data<AppState>,
+    body: web::Json<etch::CommitRequest>,
+) -> impl Responder {
+    let conn = &mut state.pool.get().unwrap();
+
+    match etch::commit(conn, state.chain, &body) {
+        Ok(commit) => HttpResponse::Ok().json(EtchingCommitResponse { data: commit }),
+        Err(err) => HttpResponse::BadRequest().json(SimpleStatus {
+            message: format!("{:#}", err),
+        }),
+    }
+}
+
+#[utoipa::path(
+    request_body = RevealRequest,
+    responses(
+        (status = 200, description = "Returns the unsigned reveal transaction of the etching", body = EtchingRevealResponse),
+        (status = 400, description = "The etching can't be revealed yet", body = SimpleStatus)
+    ),
+)]
+#[post("/etchings/reveal")]
+async fn reveal_etching(
+    state: web::Data<AppState>,
+    body: web::Json<etch::RevealRequest>,
+) -> impl Responder {
+    let conn = &mut state.pool.get().unwrap();
+
+    match etch::reveal(conn, state.chain, &body) {
+        Ok(reveal) => HttpResponse::Ok().json(EtchingRevealResponse { data: reveal }),
+        Err(err) => HttpResponse::BadRequest().json(SimpleStatus {
+            message: format!("{:#}", err),
+        }),
+    }
+}
@@ -0,0 +1,374 @@
+use crate::btc_rpc::{Auth, RetryPolicy};
+use crate::chain::Chain;
+use crate::options::Options;
+use anyhow::{bail, ensure, Context, Error};
+use bitcoin::ScriptBuf;
+use serde::*;
+use std::fs;
+use std::path::PathBuf;
+use std::time::Duration;
+
+/// Settings as they appear in the YAML config file. Every field is optional,
+/// values passed on the command line or through the environment take
+/// precedence over the ones in the file.
+#[derive(Deserialize, Default, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    pub chain: Option<Chain>,
+    pub signet_challenge: Option<String>,
+    pub rpc_url: Option<String>,
+    pub rpc_user: Option<String>,
+    pub rpc_password: Option<String>,
+    pub rpc_cookie_file: Option<PathBuf>,
+    pub db_path: Option<PathBuf>,
+    pub bind: Option<String>,
+    pub start_height: Option<u32>,
+    pub poll_interval: Option<u64>,
+    pub rpc_max_retries: Option<u32>,
+    pub rpc_retry_backoff_ms: Option<u64>,
+    pub rpc_retry_max_backoff_ms: Option<u64>,
+    pub prefetch_blocks: Option<usize>,
+    pub rpc_batch_size: Option<usize>,
+    pub reorg_window: Option<u32>,
+    pub blocks_dir: Option<PathBuf>,
+    pub mempool: Option<bool>,
+}
+
+impl Config {
+    pub fn load(path: &PathBuf) -> Result<Config, Error> {
+        let file = fs::File::open(path)
+            .with_context(|| format!("failed to open config file `{}`", path.display()))?;
+
+        serde_yaml::from_reader(file)
+            .with_context(|| format!("failed to parse config file `{}`", path.display()))
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Settings {
+    pub chain: Chain,
+    pub signet_challenge: Option<ScriptBuf>,
+    pub rpc_url: String,
+    pub rpc_user: Option<String>,
+    pub rpc_password: Option<String>,
+    pub rpc_cookie_file: Option<PathBuf>,
+    pub db_path: PathBuf,
+    pub bind: String,
+    pub start_height: Option<u32>,
+    pub poll_interval: u64,
+    pub rpc_retry: RetryPolicy,
+    pub prefetch_blocks: usize,
+    pub rpc_batch_size: usize,
+    pub reorg_window: u32,
+    pub blocks_dir: Option<PathBuf>,
+    pub mempool: bool,
+}
+
+impl Settings {
+    pub fn load(options: &Options) -> Result<Settings, Error> {
+        let config = match &options.config {
+            Some(path) => Config::load(path)?,
+            None => Config::default(),
+        };
+
+        Settings::merge(options, config)
+    }
+
+    /// Identifies the chain the index belongs to.
+    pub fn chain_id(&self) -> String {
+        self.chain.id(self.signet_challenge.as_deref())
+    }
+
+    pub fn magic(&self) -> [u8; 4] {
+        self.chain.magic(self.signet_challenge.as_deref())
+    }
+
+    pub fn rpc_auth(&self) -> Result<Auth, Error> {
+        if let Some(cookie_file) = &self.rpc_cookie_file {
+            return Ok(Auth::CookieFile(cookie_file.clone()));
+        }
+
+        match (&self.rpc_user, &self.rpc_password) {
+            (Some(user), Some(password)) => Ok(Auth::UserPass(user.clone(), password.clone())),
+            (None, None) => Ok(Auth::None),
+            _ => bail!("rpc_user and rpc_password must be set together"),
+        }
+    }
+
+    fn merge(options: &Options, config: Config) -> Result<Settings, Error> {
+        let chain = options.chain.or(config.chain).unwrap_or_default();
+
+        let signet_challenge = match options.signet_challenge.clone().or(config.signet_challenge) {
+            Some(challenge) => {
+                ensure!(
+                    chain == Chain::Signet,
+                    "signet_challenge can only be set on signet"
+                );
+
+                Some(
+                    ScriptBuf::from_hex(&challenge)
+                        .with_context(|| format!("invalid signet challenge `{}`", challenge))?,
+                )
+            }
+            None => None,
+        };
+
+        let poll_interval = options.poll_interval.or(config.poll_interval).unwrap_or(5);
+
+        // a zero interval would poll the node in a busy loop
+        ensure!(poll_interval > 0, "poll_interval must be at least 1 second");
+
+        let default_retry = RetryPolicy::default();
+
+        Ok(Settings {
+            chain,
+            rpc_url: options
+                .rpc_url
+                .clone()
+                .or(config.rpc_url)
+                .unwrap_or_else(|| format!("http://127.0.0.1:{}", chain.default_rpc_port())),
+            rpc_user: options.rpc_user.clone().or(config.rpc_user),
+            rpc_password: options.rpc_password.clone().or(config.rpc_password),
+            rpc_cookie_file: options.rpc_cookie_file.clone().or(config.rpc_cookie_file),
+            db_path: options
+                .db_path
+                .clone()
+                .or(config.db_path)
+                .unwrap_or_else(|| chain.default_db_path(signet_challenge.as_deref())),
+            bind: options
+                .bind
+                .clone()
+                .or(config.bind)
+                .unwrap_or_else(|| "localhost:8080".to_string()),
+            start_height: options.start_height.or(config.start_height),
+            poll_interval,
+            rpc_retry: RetryPolicy {
+                max_retries: options
+                    .rpc_max_retries
+                    .or(config.rpc_max_retries)
+                    .unwrap_or(default_retry.max_retries),
+                initial_backoff: options
+                    .rpc_retry_backoff_ms
+                    .or(config.rpc_retry_backoff_ms)
+                    .map(Duration::from_millis)
+                    .unwrap_or(default_retry.initial_backoff),
+                max_backoff: options
+                    .rpc_retry_max_backoff_ms
+                    .or(config.rpc_retry_max_backoff_ms)
+                    .map(Duration::from_millis)
+                    .unwrap_or(default_retry.max_backoff),
+            },
+            prefetch_blocks: options
+                .prefetch_blocks
+                .or(config.prefetch_blocks)
+                .unwrap_or(32),
+            rpc_batch_size: options
+                .rpc_batch_size
+                .or(config.rpc_batch_size)
+                .unwrap_or(8),
+            reorg_window: options.reorg_window.or(config.reorg_window).unwrap_or(20),
+            blocks_dir: options.blocks_dir.clone().or(config.blocks_dir),
+            mempool: options.mempool || config.mempool.unwrap_or_default(),
+            signet_challenge,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::options::Subcommand;
+    use clap::Parser;
+
+    fn options(args: &[&str]) -> Options {
+        Options::try_parse_from(["runes-explorer"].iter().chain(args)).unwrap()
+    }
+
+    #[test]
+    fn defaults() {
+        let settings = Settings::merge(&options(&["index"]), Config::default()).unwrap();
+
+        assert_eq!(settings.chain, Chain::Mainnet);
+        assert_eq!(settings.rpc_url, "http://127.0.0.1:8332");
+        assert_eq!(settings.db_path, PathBuf::from("./indexer.db"));
+        assert_eq!(settings.signet_challenge, None);
+        assert_eq!(settings.bind, "localhost:8080");
+        assert_eq!(settings.start_height, None);
+        assert_eq!(settings.poll_interval, 5);
+        assert_eq!(settings.rpc_retry.max_retries, 10);
+        assert_eq!(
+            settings.rpc_retry.initial_backoff,
+            Duration::from_millis(500)
+        );
+        assert_eq!(settings.rpc_retry.max_backoff, Duration::from_secs(30));
+        assert_eq!(settings.prefetch_blocks, 32);
+        assert_eq!(settings.rpc_batch_size, 8);
+        assert_eq!(settings.reorg_window, 20);
+        assert_eq!(settings.blocks_dir, None);
+        assert!(!settings.mempool);
+    }
+
+    #[test]
+    fn default_rpc_url_follows_chain() {
+        let settings =
+            Settings::merge(&options(&["--chain", "signet", "index"]), Config::default()).unwrap();
+
+        assert_eq!(settings.rpc_url, "http://127.0.0.1:38332");
+    }
+
+    #[test]
+    fn config_file_values_are_used() {
+        let config: Config = serde_yaml::from_str(
+            "
+chain: testnet
+rpc_url: http://node:18332
+rpc_user: alice
+rpc_password: secret
+db_path: /var/lib/runes/testnet.db
+bind: 0.0.0.0:9000
+start_height: 2583205
+poll_interval: 30
+rpc_max_retries: 3
+rpc_retry_backoff_ms: 250
+rpc_retry_max_backoff_ms: 4000
+prefetch_blocks: 64
+rpc_batch_size: 16
+reorg_window: 200
+blocks_dir: /var/lib/bitcoind/testnet3/blocks
+mempool: true
+",
+        )
+        .unwrap();
+
+        let settings = Settings::merge(&options(&["serve"]), config).unwrap();
+
+        assert_eq!(settings.chain, Chain::Testnet);
+        assert_eq!(settings.rpc_url, "http://node:18332");
+        assert_eq!(settings.rpc_user.as_deref(), Some("alice"));
+        assert_eq!(settings.rpc_password.as_deref(), Some("secret"));
+        assert_eq!(settings.db_path, PathBuf::from("/var/lib/runes/testnet.db"));
+        assert_eq!(settings.bind, "0.0.0.0:9000");
+        assert_eq!(settings.start_height, Some(2583205));
+        assert_eq!(settings.poll_interval, 30);
+        assert_eq!(settings.rpc_retry.max_retries, 3);
+        assert_eq!(
+            settings.rpc_retry.initial_backoff,
+            Duration::from_millis(250)
+        );
+        assert_eq!(settings.rpc_retry.max_backoff, Duration::from_secs(4));
+        assert_eq!(settings.prefetch_blocks, 64);
+        assert_eq!(settings.rpc_batch_size, 16);
+        assert_eq!(settings.reorg_window, 200);
+        assert_eq!(
+            settings.blocks_dir,
+            Some(PathBuf::from("/var/lib/bitcoind/testnet3/blocks"))
+        );
+        assert!(settings.mempool);
+    }
+
+    #[test]
+    fn options_override_config_file() {
+        let config: Config = serde_yaml::from_str("chain: testnet\nbind: 0.0.0.0:9000").unwrap();
+
+        let options = options(&["run", "--chain", "regtest", "--bind", "127.0.0.1:8081"]);
+
+        assert!(matches!(options.subcommand, Subcommand::Run));
+
+        let settings = Settings::merge(&options, config).unwrap();
+
+        assert_eq!(settings.chain, Chain::Regtest);
+        assert_eq!(settings.bind, "127.0.0.1:8081");
+        assert_eq!(settings.rpc_url, "http://127.0.0.1:18443");
+    }
+
+    #[test]
+    fn rpc_auth() {
+        let settings = Settings::merge(&options(&["index"]), Config::default()).unwrap();
+        assert!(matches!(settings.rpc_auth().unwrap(), Auth::None));
+
+        let settings = Settings::merge(
+            &options(&["index", "--rpc-user", "alice", "--rpc-password", "secret"]),
+            Config::default(),
+        )
+        .unwrap();
+        assert!(matches!(
+            settings.rpc_auth().unwrap(),
+            Auth::UserPass(user, password) if user == "alice" && password == "secret"
+        ));
+
+        let settings = Settings::merge(
+            &options(&[
+                "index",
+                "--rpc-user",
+                "alice",
+                "--rpc-cookie-file",
+                "/tmp/.cookie",
+            ]),
+            Config::default(),
+        )
+        .unwrap();
+        assert!(matches!(
+            settings.rpc_auth().unwrap(),
+            Auth::CookieFile(path) if path == std::path::Path::new("/tmp/.cookie")
+        ));
+
+        let settings = Settings::merge(
+            &options(&["index", "--rpc-user", "alice"]),
+            Config::default(),
+        )
+        .unwrap();
+        assert!(settings.rpc_auth().is_err());
+    }
+
+    #[test]
+    fn custom_signet() {
+        let settings = Settings::merge(
+            &options(&["--chain", "signet", "--signet-challenge", "51", "index"]),
+            Config::default(),
+        )
+        .unwrap();
+
+        assert_eq!(
+            settings.signet_challenge,
+            Some(ScriptBuf::from_hex("51").unwrap())
+        );
+        assert_eq!(
+            settings.chain_id(),
+            format!("signet-{}", hex::encode(settings.magic()))
+        );
+        assert_eq!(
+            settings.db_path,
+            PathBuf::from(format!("./{}/indexer.db", settings.chain_id()))
+        );
+
+        assert!(Settings::merge(
+            &options(&["--chain", "signet", "--signet-challenge", "zz", "index"]),
+            Config::default(),
+        )
+        .is_err());
+
+        assert!(Settings::merge(
+            &options(&["--chain", "testnet", "--signet-challenge", "51", "index"]),
+            Config::default(),
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn zero_poll_interval_is_rejected() {
+        assert!(Settings::merge(
+            &options(&["--poll-interval", "0", "run"]),
+            Config::default()
+        )
+        .is_err());
+
+        let config: Config = serde_yaml::from_str("poll_interval: 0").unwrap();
+
+        assert!(Settings::merge(&options(&["run"]), config).is_err());
+    }
+
+    #[test]
+    fn unknown_config_keys_are_rejected() {
+        assert!(serde_yaml::from_str::<Config>("rpc_urll: http://node:8332").is_err());
+    }
+}
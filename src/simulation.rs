@@ -0,0 +1,506 @@
+use super::adapters::db::RuneTXO;
+use super::adapters::sqlite::SQLite;
+use super::allocation::{allocate, Allocation};
+use super::lot::Lot;
+use super::rune_updaters::mintable;
+use super::runes::*;
+use super::utils;
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use bitcoin::psbt::PartiallySignedTransaction;
+use rusqlite::Connection;
+use utoipa::ToSchema;
+
+/// What a transaction would do to runes if it confirmed in the block after
+/// the indexed tip.
+#[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
+pub struct Simulation {
+    pub tx_id: String,
+    pub is_runestone: bool,
+    pub is_cenotapth: bool,
+    pub cenotapth_message: Option<String>,
+    pub inputs: Vec<RuneTXO>,
+    pub mint: Option<SimulatedMint>,
+    pub etching: Option<SimulatedEtching>,
+    pub outputs: Vec<SimulatedOutput>,
+    pub burned: Vec<SimulatedBalance>,
+    pub ignored_edicts: Vec<SimulatedIgnoredEdict>,
+    pub warnings: Vec<String>,
+}
+
+/// A balance of a rune. The rune being etched has no id yet.
+#[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
+pub struct SimulatedBalance {
+    pub rune_id: Option<String>,
+    pub name: Option<String>,
+    pub amount: u128,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
+pub struct SimulatedOutput {
+    pub output_index: u32,
+    pub address: Option<String>,
+    pub is_op_return: bool,
+    pub balances: Vec<SimulatedBalance>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
+pub struct SimulatedMint {
+    pub rune_id: String,
+    pub amount: Option<u128>,
+    pub error: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
+pub struct SimulatedEtching {
+    pub name: Option<String>,
+    pub premine: u128,
+    pub error: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
+pub struct SimulatedIgnoredEdict {
+    pub index: usize,
+    pub rune_id: String,
+    pub amount: u128,
+    pub output: u32,
+    pub reason: String,
+}
+
+/// Decodes a raw transaction, or the unsigned transaction of a PSBT, given
+/// as hex or base64.
+pub fn decode_transaction(data: &str) -> Result<Transaction> {
+    let data = data.trim();
+
+    let bytes = match hex::decode(data) {
+        Ok(bytes) => bytes,
+        Err(_) => BASE64
+            .decode(data)
+            .context("transaction is neither hex nor base64 encoded")?,
+    };
+
+    if bytes.starts_with(b"psbt\xff") {
+        return Ok(PartiallySignedTransaction::deserialize(&bytes)
+            .context("invalid PSBT")?
+            .unsigned_tx);
+    }
+
+    consensus::deserialize(&bytes).context("invalid transaction")
+}
+
+/// Runs `tx` through the same allocation rules as the indexer, against the
+/// current index, without writing anything.
+pub fn simulate(conn: &mut Connection, chain: Network, tx: &Transaction) -> Result<Simulation> {
+    let database = SQLite {};
+
+    let height = database
+        .get_latest_block(conn)?
+        .map(|block| block.height + 1)
+        .unwrap_or_default();
+
+    let artifact = Runestone::decipher(tx);
+
+    let mut warnings = Vec::new();
+
+    let mut inputs = Vec::new();
+    let mut unallocated: HashMap<RuneId, Lot> = HashMap::new();
+
+    for input in &tx.input {
+        let runes_txos = database.get_runes_txo_by_output_index(
+            conn,
+            &input.previous_output.txid.to_string().to_lowercase(),
+            input.previous_output.vout,
+        )?;
+
+        for rt in runes_txos {
+            if let Some(spent_tx_id) = &rt.spent_tx_id {
+                warnings.push(format!(
+                    "input {} was already spent by {}",
+                    input.previous_output, spent_tx_id
+                ));
+            }
+
+            *unallocated
+                .entry(rt.rune_id.parse::<RuneId>()?)
+                .or_default() += rt.amount;
+
+            inputs.push(rt);
+        }
+    }
+
+    let input_runes = unallocated.keys().copied().collect::<HashSet<RuneId>>();
+
+    let mint = match artifact.as_ref().and_then(|artifact| artifact.mint()) {
+        Some(id) => {
+            let minted = match database.get_rune_by_id(conn, &id.to_string())? {
+                Some(rune_entry) => mintable(&rune_entry, height).map_err(|err| err.to_string()),
+                None => Err("rune doesn't exist".to_string()),
+            };
+
+            if let Err(err) = &minted {
+                warnings.push(format!("mint of {} fails: {}", id, err));
+            }
+
+            if let Ok(amount) = minted {
+                *unallocated.entry(id).or_default() += amount;
+            }
+
+            Some(SimulatedMint {
+                rune_id: id.to_string(),
+                amount: minted.clone().ok(),
+                error: minted.err(),
+            })
+        }
+        None => None,
+    };
+
+    let etching = match &artifact {
+        Some(Artifact::Runestone(runestone)) => runestone.etching.map(|etching| {
+            (
+                etching.rune,
+                etching.spacers,
+                etching.premine.unwrap_or_default(),
+            )
+        }),
+        Some(Artifact::Cenotaph(cenotaph)) => cenotaph.etching.map(|rune| (Some(rune), None, 0)),
+        None => None,
+    };
+
+    let etching = match etching {
+        Some((rune, spacers, premine)) => {
+            let error = match rune {
+                Some(rune)
+                    if rune < Rune::minimum_at_height(chain, Height(u32::try_from(height)?)) =>
+                {
+                    Some("rune name is too short to be etched yet".to_string())
+                }
+                Some(rune) if rune.is_reserved() => Some("rune name is reserved".to_string()),
+                Some(rune)
+                    if database
+                        .get_rune_by_raw_name(conn, &rune.to_string())?
+                        .is_some() =>
+                {
+                    Some("rune name is already etched".to_string())
+                }
+                _ => None,
+            };
+
+            match &error {
+                Some(err) => warnings.push(format!("etching fails: {}", err)),
+                None if rune.is_some() => warnings.push(
+                    "etching commitment is not checked, the commit transaction needs 6 confirmations when this transaction confirms".to_string(),
+                ),
+                None => {}
+            }
+
+            Some(SimulatedEtching {
+                name: rune.map(|rune| {
+                    SpacedRune {
+                        rune,
+                        spacers: spacers.unwrap_or_default(),
+                    }
+                    .to_string()
+                }),
+                premine,
+                error,
+            })
+        }
+        None => None,
+    };
+
+    // the etched rune is only assigned an id once the transaction confirms,
+    // so it's allocated under an id nothing else in the transaction uses
+    let etched = etching
+        .as_ref()
+        .filter(|etching| etching.error.is_none())
+        .map(|_| unused_id(artifact.as_ref(), &unallocated));
+
+    if let Some(Artifact::Cenotaph(cenotaph)) = &artifact {
+        warnings.push(match cenotaph.flaw {
+            Some(flaw) => format!("transaction is a cenotaph: {}", flaw),
+            None => "transaction is a cenotaph".to_string(),
+        });
+    }
+
+    let Allocation {
+        outputs,
+        burned,
+        ignored_edicts,
+        ..
+    } = allocate(tx, artifact.as_ref(), unallocated, etched);
+
+    if burned
+        .iter()
+        .any(|(id, amount)| *amount > 0 && input_runes.contains(id))
+    {
+        warnings.push("inputs carry runes that will be burned".to_string());
+    }
+
+    let mut names: HashMap<RuneId, Option<String>> = HashMap::new();
+
+    // balances of the etched rune are keyed by `None`
+    let mut balances = |conn: &mut Connection, balances: HashMap<RuneId, Lot>| -> Result<_> {
+        let mut balances = balances
+            .into_iter()
+            .map(|(id, amount)| ((Some(id) != etched).then_some(id), amount))
+            .collect::<Vec<(Option<RuneId>, Lot)>>();
+        balances.sort();
+
+        balances
+            .into_iter()
+            .filter(|(_, amount)| *amount > 0)
+            .map(|(id, amount)| {
+                let name = match id {
+                    None => etching.as_ref().and_then(|etching| etching.name.clone()),
+                    Some(id) => match names.get(&id) {
+                        Some(name) => name.clone(),
+                        None => {
+                            let name = database
+                                .get_rune_by_id(conn, &id.to_string())?
+                                .map(|rune_entry| rune_entry.name);
+
+                            names.insert(id, name.clone());
+
+                            name
+                        }
+                    },
+                };
+
+                Ok(SimulatedBalance {
+                    rune_id: id.map(|id| id.to_string()),
+                    name,
+                    amount: amount.n(),
+                })
+            })
+            .collect::<Result<Vec<SimulatedBalance>>>()
+    };
+
+    let mut simulated_outputs = Vec::new();
+
+    for (vout, allocated) in outputs.into_iter().enumerate() {
+        simulated_outputs.push(SimulatedOutput {
+            output_index: u32::try_from(vout)?,
+            address: utils::output_to_address(&tx.output[vout], chain).map(|s| s.to_lowercase()),
+            is_op_return: tx.output[vout].script_pubkey.is_op_return(),
+            balances: balances(conn, allocated)?,
+        });
+    }
+
+    let burned = balances(conn, burned)?;
+
+    Ok(Simulation {
+        tx_id: tx.txid().to_string().to_lowercase(),
+        is_runestone: matches!(artifact, Some(Artifact::Runestone(_))),
+        is_cenotapth: matches!(artifact, Some(Artifact::Cenotaph(_))),
+        cenotapth_message: match &artifact {
+            Some(Artifact::Cenotaph(cenotaph)) => cenotaph.flaw.map(|flaw| flaw.to_string()),
+            _ => None,
+        },
+        inputs,
+        mint,
+        etching,
+        outputs: simulated_outputs,
+        burned,
+        ignored_edicts: ignored_edicts
+            .into_iter()
+            .map(|ignored| SimulatedIgnoredEdict {
+                index: ignored.index,
+                rune_id: ignored.edict.id.to_string(),
+                amount: ignored.edict.amount,
+                output: ignored.edict.output,
+                reason: ignored.reason.to_string(),
+            })
+            .collect(),
+        warnings,
+    })
+}
+
+/// An id that neither the edicts of `artifact` nor `unallocated` refer to.
+fn unused_id(artifact: Option<&Artifact>, unallocated: &HashMap<RuneId, Lot>) -> RuneId {
+    let edicts = match artifact {
+        Some(Artifact::Runestone(runestone)) => runestone.edicts.as_slice(),
+        _ => &[],
+    };
+
+    (0..=u32::MAX)
+        .rev()
+        .map(|tx| RuneId {
+            block: u64::MAX,
+            tx,
+        })
+        .find(|id| !unallocated.contains_key(id) && edicts.iter().all(|edict| edict.id != *id))
+        .unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::block_source::fixture::testing::*;
+
+    #[test]
+    fn decode_hex_and_psbt() {
+        let tx = tx(&[outpoint(1)], None, vec![address_script(1)]);
+
+        let hex = consensus::encode::serialize_hex(&tx);
+
+        assert_eq!(decode_transaction(&hex).unwrap(), tx);
+        assert_eq!(
+            decode_transaction(&BASE64.encode(consensus::serialize(&tx))).unwrap(),
+            tx
+        );
+
+        let psbt = PartiallySignedTransaction::from_unsigned_tx(tx.clone()).unwrap();
+
+        assert_eq!(
+            decode_transaction(&BASE64.encode(psbt.serialize())).unwrap(),
+            tx
+        );
+        assert_eq!(
+            decode_transaction(&hex::encode(psbt.serialize())).unwrap(),
+            tx
+        );
+
+        assert!(decode_transaction("not a transaction").is_err());
+    }
+
+    #[tokio::test]
+    async fn simulate_against_the_index() {
+        let (etching, blocks) = etch_and_mint();
+
+        let mut fixture = Fixture::index(blocks).await;
+        let conn = &mut fixture.conn;
+
+        let id = RuneId::new(1, 1).unwrap();
+
+        let simulation = simulate(conn, Network::Regtest, &transfer(&etching, 14)).unwrap();
+
+        assert!(simulation.is_runestone);
+        assert_eq!(simulation.inputs.len(), 1);
+        assert_eq!(simulation.inputs[0].amount, 1000);
+        assert!(simulation.outputs[0].is_op_return);
+        assert_eq!(simulation.outputs[1].address, Some(address(10)));
+        assert_eq!(simulation.outputs[1].balances[0].amount, 700);
+        assert_eq!(simulation.outputs[2].address, Some(address(14)));
+        assert_eq!(
+            simulation.outputs[2].balances[0].rune_id,
+            Some(id.to_string())
+        );
+        assert_eq!(simulation.outputs[2].balances[0].amount, 300);
+        assert!(simulation.burned.is_empty());
+        assert!(simulation.warnings.is_empty());
+
+        // the second edict finds nothing left, the third names a rune the
+        // inputs don't carry, and the cap has been reached
+        let edicts = tx(
+            &[outpoint_of(&etching, 1)],
+            Some(Runestone {
+                edicts: vec![
+                    Edict {
+                        id,
+                        amount: 0,
+                        output: 1,
+                    },
+                    Edict {
+                        id,
+                        amount: 10,
+                        output: 1,
+                    },
+                    Edict {
+                        id: RuneId::new(5, 5).unwrap(),
+                        amount: 10,
+                        output: 1,
+                    },
+                ],
+                mint: Some(id),
+                ..Default::default()
+            }),
+            vec![address_script(14)],
+        );
+
+        let simulation = simulate(conn, Network::Regtest, &edicts).unwrap();
+
+        assert_eq!(simulation.outputs[1].balances[0].amount, 1000);
+        assert_eq!(simulation.ignored_edicts.len(), 2);
+        assert_eq!(simulation.ignored_edicts[0].index, 1);
+        assert_eq!(
+            simulation.ignored_edicts[0].reason,
+            "earlier edicts allocated all of the rune"
+        );
+        assert_eq!(simulation.ignored_edicts[1].index, 2);
+        assert_eq!(
+            simulation.ignored_edicts[1].reason,
+            "inputs carry none of the rune"
+        );
+        let mint = simulation.mint.unwrap();
+        assert_eq!(mint.amount, None);
+        assert_eq!(mint.error, Some("limited to 2 mints".to_string()));
+        assert_eq!(
+            simulation.warnings,
+            vec![format!("mint of {} fails: limited to 2 mints", id)]
+        );
+
+        // an edict to an output that doesn't exist makes a cenotaph
+        let cenotaph = tx(
+            &[outpoint_of(&etching, 1)],
+            Some(Runestone {
+                edicts: vec![Edict {
+                    id,
+                    amount: 1,
+                    output: 5,
+                }],
+                ..Default::default()
+            }),
+            vec![address_script(14)],
+        );
+
+        let simulation = simulate(conn, Network::Regtest, &cenotaph).unwrap();
+
+        assert!(simulation.is_cenotapth);
+        assert!(simulation.outputs[1].balances.is_empty());
+        assert_eq!(simulation.burned.len(), 1);
+        assert_eq!(simulation.burned[0].amount, 1000);
+        assert!(simulation
+            .warnings
+            .contains(&"inputs carry runes that will be burned".to_string()));
+
+        // an edict can't reach the premine through whatever id it is
+        // allocated under, only through 0:0
+        let etching = tx(
+            &[outpoint(20)],
+            Some(Runestone {
+                edicts: vec![
+                    Edict {
+                        id: RuneId::default(),
+                        amount: 10,
+                        output: 2,
+                    },
+                    Edict {
+                        id: RuneId {
+                            block: u64::MAX,
+                            tx: u32::MAX,
+                        },
+                        amount: 0,
+                        output: 2,
+                    },
+                ],
+                etching: Some(Etching {
+                    premine: Some(50),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            }),
+            vec![address_script(10), address_script(14)],
+        );
+
+        let simulation = simulate(conn, Network::Regtest, &etching).unwrap();
+
+        assert_eq!(simulation.ignored_edicts.len(), 1);
+        assert_eq!(simulation.ignored_edicts[0].index, 1);
+        assert_eq!(simulation.outputs[1].balances[0].rune_id, None);
+        assert_eq!(simulation.outputs[1].balances[0].amount, 40);
+        assert_eq!(simulation.outputs[2].balances[0].rune_id, None);
+        assert_eq!(simulation.outputs[2].balances[0].amount, 10);
+
+        // nothing is written to the index
+        assert_eq!(balance(conn, 10, id), 1000);
+    }
+}
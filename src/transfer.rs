@@ -0,0 +1,451 @@
+use super::adapters::db::RuneTXO;
+use super::adapters::sqlite::SQLite;
+use super::encoding::parse_rune_id;
+use super::runes::*;
+use super::simulation::{simulate, SimulatedOutput};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use bitcoin::psbt::PartiallySignedTransaction;
+use rusqlite::Connection;
+use utoipa::ToSchema;
+
+/// Sats put in each output receiving runes when the request doesn't say.
+const DEFAULT_POSTAGE: u64 = 546;
+
+/// Runes to send, and where to take them from.
+#[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
+#[serde(deny_unknown_fields)]
+pub struct TransferRequest {
+    /// Address whose rune outputs are selected from, unless `outpoints` is
+    /// given
+    pub source_address: Option<String>,
+    /// Outputs to spend, `txid:vout`. All of them are spent
+    #[serde(default)]
+    pub outpoints: Vec<String>,
+    pub payments: Vec<Payment>,
+    /// Address receiving the runes left over in the spent outputs
+    pub change_address: String,
+    /// Sats in each output receiving runes, 546 if missing
+    pub postage: Option<u64>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
+#[serde(deny_unknown_fields)]
+pub struct Payment {
+    pub address: String,
+    pub rune_id: String,
+    pub amount: u128,
+}
+
+/// An unsigned transaction sending the requested runes. The wallet adds the
+/// inputs paying for postage and fees, and the bitcoin change, before
+/// signing.
+#[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
+pub struct TransferPlan {
+    /// Base64 encoded PSBT of the transaction
+    pub psbt: String,
+    /// Rune balances held by the spent outputs
+    pub inputs: Vec<RuneTXO>,
+    /// The OP_RETURN script holding the runestone, hex encoded
+    pub runestone: String,
+    /// Balances each output receives
+    pub outputs: Vec<SimulatedOutput>,
+}
+
+pub fn plan_transfer(
+    conn: &mut Connection,
+    chain: Network,
+    request: &TransferRequest,
+) -> Result<TransferPlan> {
+    let database = SQLite {};
+
+    let script_pubkey = |address: &str| -> Result<ScriptBuf> {
+        Ok(address
+            .parse::<Address<NetworkUnchecked>>()
+            .with_context(|| format!("invalid address {}", address))?
+            .require_network(chain)
+            .with_context(|| format!("address {} is not for {}", address, chain))?
+            .script_pubkey())
+    };
+
+    ensure!(!request.payments.is_empty(), "no payments");
+
+    let mut needed: BTreeMap<RuneId, u128> = BTreeMap::new();
+
+    for (index, payment) in request.payments.iter().enumerate() {
+        let id = parse_rune_id(&payment.rune_id).with_context(|| format!("payment {}", index))?;
+
+        ensure!(
+            id != RuneId::default(),
+            "payment {}: rune id 0:0 refers to an etching",
+            index
+        );
+        ensure!(payment.amount > 0, "payment {}: amount is zero", index);
+
+        let total = needed.entry(id).or_default();
+        *total = total
+            .checked_add(payment.amount)
+            .ok_or_else(|| anyhow!("payments of {} overflow", id))?;
+    }
+
+    // rune balances of each candidate output, in the order they are listed
+    let mut candidates: Vec<(OutPoint, Vec<RuneTXO>)> = Vec::new();
+
+    let explicit = !request.outpoints.is_empty();
+
+    if explicit {
+        for outpoint in &request.outpoints {
+            let outpoint = outpoint
+                .parse::<OutPoint>()
+                .with_context(|| format!("invalid outpoint {}", outpoint))?;
+
+            let runes_txos = database.get_runes_txo_by_output_index(
+                conn,
+                &outpoint.txid.to_string().to_lowercase(),
+                outpoint.vout,
+            )?;
+
+            if let Some(spent) = runes_txos.iter().find(|txo| !txo.is_unspent) {
+                bail!(
+                    "outpoint {} was spent by {}",
+                    outpoint,
+                    spent.spent_tx_id.clone().unwrap_or_default()
+                );
+            }
+
+            candidates.push((outpoint, runes_txos));
+        }
+    } else {
+        let Some(source_address) = &request.source_address else {
+            bail!("either source_address or outpoints is required");
+        };
+
+        for txo in database.get_address_spendable_runes_utxo(conn, source_address)? {
+            let outpoint = OutPoint {
+                txid: txo.tx_id.parse()?,
+                vout: txo.output_index,
+            };
+
+            match candidates
+                .iter_mut()
+                .find(|(candidate, _)| *candidate == outpoint)
+            {
+                Some((_, runes_txos)) => runes_txos.push(txo),
+                None => candidates.push((outpoint, vec![txo])),
+            }
+        }
+    }
+
+    let amount_of = |runes_txos: &[RuneTXO], id: RuneId| -> u128 {
+        runes_txos
+            .iter()
+            .filter(|txo| txo.rune_id == id.to_string())
+            .map(|txo| txo.amount)
+            .sum()
+    };
+
+    let mut selected: Vec<usize> = Vec::new();
+
+    if explicit {
+        selected.extend(0..candidates.len());
+    } else {
+        // largest outputs first, so as few outputs as possible are spent
+        for (&id, &amount) in &needed {
+            let mut covered: u128 = selected
+                .iter()
+                .map(|index| amount_of(&candidates[*index].1, id))
+                .sum();
+
+            while covered < amount {
+                let Some(index) = (0..candidates.len())
+                    .filter(|index| !selected.contains(index))
+                    .max_by_key(|index| (amount_of(&candidates[*index].1, id), usize::MAX - index))
+                    .filter(|index| amount_of(&candidates[*index].1, id) > 0)
+                else {
+                    break;
+                };
+
+                covered += amount_of(&candidates[index].1, id);
+                selected.push(index);
+            }
+        }
+    }
+
+    let mut available: HashMap<RuneId, u128> = HashMap::new();
+
+    for index in &selected {
+        for txo in &candidates[*index].1 {
+            *available.entry(txo.rune_id.parse()?).or_default() += txo.amount;
+        }
+    }
+
+    for (id, amount) in &needed {
+        let available = available.get(id).copied().unwrap_or_default();
+
+        ensure!(
+            available >= *amount,
+            "insufficient balance of {}: {} available, {} needed",
+            id,
+            available,
+            amount
+        );
+    }
+
+    let postage = request.postage.unwrap_or(DEFAULT_POSTAGE);
+
+    // the runestone goes first, then one output for each recipient
+    let mut recipients: Vec<&str> = Vec::new();
+
+    for payment in &request.payments {
+        if !recipients.contains(&payment.address.as_str()) {
+            recipients.push(&payment.address);
+        }
+    }
+
+    let mut edicts: Vec<Edict> = Vec::new();
+
+    for payment in &request.payments {
+        let id = parse_rune_id(&payment.rune_id)?;
+
+        let output = u32::try_from(
+            recipients
+                .iter()
+                .position(|recipient| *recipient == payment.address)
+                .unwrap()
+                + 1,
+        )?;
+
+        match edicts
+            .iter_mut()
+            .find(|edict| edict.id == id && edict.output == output)
+        {
+            Some(edict) => edict.amount += payment.amount,
+            None => edicts.push(Edict {
+                id,
+                amount: payment.amount,
+                output,
+            }),
+        }
+    }
+
+    let mut output = vec![TxOut {
+        value: 0,
+        script_pubkey: ScriptBuf::new(),
+    }];
+
+    for recipient in &recipients {
+        output.push(TxOut {
+            value: postage,
+            script_pubkey: script_pubkey(recipient)?,
+        });
+    }
+
+    let change_script = script_pubkey(&request.change_address)?;
+
+    let leftover = available
+        .iter()
+        .any(|(id, amount)| *amount > needed.get(id).copied().unwrap_or_default());
+
+    let pointer = if leftover {
+        output.push(TxOut {
+            value: postage,
+            script_pubkey: change_script,
+        });
+
+        Some(u32::try_from(output.len() - 1)?)
+    } else {
+        None
+    };
+
+    let runestone = Runestone {
+        edicts,
+        pointer,
+        ..Default::default()
+    }
+    .encipher();
+
+    output[0].script_pubkey = runestone.clone();
+
+    let tx = Transaction {
+        version: 2,
+        lock_time: LockTime::ZERO,
+        input: selected
+            .iter()
+            .map(|index| TxIn {
+                previous_output: candidates[*index].0,
+                script_sig: ScriptBuf::new(),
+                sequence: Sequence::ENABLE_RBF_NO_LOCKTIME,
+                witness: Witness::new(),
+            })
+            .collect(),
+        output,
+    };
+
+    let simulation = simulate(conn, chain, &tx)?;
+
+    ensure!(
+        simulation.burned.is_empty(),
+        "transfer would burn runes: {}",
+        simulation.warnings.join(", ")
+    );
+
+    Ok(TransferPlan {
+        psbt: BASE64.encode(PartiallySignedTransaction::from_unsigned_tx(tx)?.serialize()),
+        inputs: simulation.inputs,
+        runestone: hex::encode(runestone.as_bytes()),
+        outputs: simulation.outputs,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::block_source::fixture::testing::*;
+
+    #[tokio::test]
+    async fn plan_transfer_of_several_runes() {
+        let etching = |input: u8, premine: u128| {
+            tx(
+                &[outpoint(input)],
+                Some(Runestone {
+                    etching: Some(Etching {
+                        premine: Some(premine),
+                        ..Default::default()
+                    }),
+                    ..Default::default()
+                }),
+                vec![address_script(10)],
+            )
+        };
+
+        let a = RuneId::new(1, 1).unwrap();
+        let b = RuneId::new(1, 2).unwrap();
+
+        let etching_a = etching(1, 1000);
+        let etching_b = etching(2, 50);
+
+        // splits the balance of a in outputs of 600 and 400
+        let split = tx(
+            &[outpoint_of(&etching_a, 1)],
+            Some(Runestone {
+                edicts: vec![Edict {
+                    id: a,
+                    amount: 400,
+                    output: 2,
+                }],
+                ..Default::default()
+            }),
+            vec![address_script(10), address_script(10)],
+        );
+
+        let mut fixture = Fixture::index(vec![
+            vec![etching_a, etching_b.clone()],
+            vec![split.clone()],
+        ])
+        .await;
+        let conn = &mut fixture.conn;
+
+        let recipient = |n: u8| {
+            bitcoin::Address::from_script(&address_script(n), Network::Regtest)
+                .unwrap()
+                .to_string()
+        };
+
+        let payment = |n: u8, rune_id: RuneId, amount: u128| Payment {
+            address: recipient(n),
+            rune_id: rune_id.to_string(),
+            amount,
+        };
+
+        let request = TransferRequest {
+            source_address: Some(address(10)),
+            outpoints: Vec::new(),
+            payments: vec![payment(14, a, 500), payment(14, b, 20), payment(15, b, 10)],
+            change_address: recipient(10),
+            postage: None,
+        };
+
+        let plan = plan_transfer(conn, Network::Regtest, &request).unwrap();
+
+        let tx = crate::simulation::decode_transaction(&plan.psbt).unwrap();
+
+        assert_eq!(
+            tx.input
+                .iter()
+                .map(|input| input.previous_output)
+                .collect::<Vec<OutPoint>>(),
+            vec![outpoint_of(&split, 1), outpoint_of(&etching_b, 1)]
+        );
+        assert_eq!(tx.output.len(), 4);
+        assert_eq!(tx.output[1].value, 546);
+
+        let balances_of = |output: &crate::simulation::SimulatedOutput| {
+            output
+                .balances
+                .iter()
+                .map(|balance| (balance.rune_id.clone().unwrap(), balance.amount))
+                .collect::<Vec<(String, u128)>>()
+        };
+
+        assert_eq!(plan.outputs[1].address, Some(address(14)));
+        assert_eq!(
+            balances_of(&plan.outputs[1]),
+            vec![(a.to_string(), 500), (b.to_string(), 20)]
+        );
+        assert_eq!(plan.outputs[2].address, Some(address(15)));
+        assert_eq!(balances_of(&plan.outputs[2]), vec![(b.to_string(), 10)]);
+        assert_eq!(plan.outputs[3].address, Some(address(10)));
+        assert_eq!(
+            balances_of(&plan.outputs[3]),
+            vec![(a.to_string(), 100), (b.to_string(), 20)]
+        );
+
+        // spending the given outpoint, nothing is left for change
+        let plan = plan_transfer(
+            conn,
+            Network::Regtest,
+            &TransferRequest {
+                source_address: None,
+                outpoints: vec![outpoint_of(&split, 2).to_string()],
+                payments: vec![payment(14, a, 400)],
+                change_address: recipient(10),
+                postage: Some(1000),
+            },
+        )
+        .unwrap();
+
+        assert_eq!(plan.inputs.len(), 1);
+        assert_eq!(plan.outputs.len(), 2);
+        assert_eq!(balances_of(&plan.outputs[1]), vec![(a.to_string(), 400)]);
+
+        assert_eq!(
+            format!(
+                "{:#}",
+                plan_transfer(
+                    conn,
+                    Network::Regtest,
+                    &TransferRequest {
+                        payments: vec![payment(14, a, 2000)],
+                        ..request.clone()
+                    },
+                )
+                .unwrap_err()
+            ),
+            "insufficient balance of 1:1: 1000 available, 2000 needed"
+        );
+
+        assert!(plan_transfer(
+            conn,
+            Network::Regtest,
+            &TransferRequest {
+                change_address: bitcoin::Address::from_script(
+                    &address_script(10),
+                    Network::Bitcoin
+                )
+                .unwrap()
+                .to_string(),
+                ..request
+            },
+        )
+        .is_err());
+    }
+}